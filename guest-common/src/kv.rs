@@ -0,0 +1,69 @@
+//! Wrappers around the host key-value store. Keys are scoped to the calling agent and survive
+//! host restarts.
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::de::DeserializeOwned;
+
+use crate::agents_common::constants::HostMethod;
+use crate::common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use crate::common::flatbuffer_wrappers::guest_error::ErrorCode;
+use crate::guest::error::{HyperlightGuestError, Result};
+use crate::guest_bin::host_comm::call_host_function;
+
+/// Returns the value stored under `key`, or `None` if it is missing or expired.
+pub fn kv_get(key: &str) -> Result<Option<String>> {
+    let response = call_host_function::<String>(
+        HostMethod::KvGet.as_ref(),
+        Some(vec![ParameterValue::String(key.to_string())]),
+        ReturnType::String,
+    )?;
+    parse_response(&response)
+}
+
+/// Stores `value` under `key`, replacing any previous value. The entry expires after
+/// `ttl_seconds` if given; TTLs above `i64::MAX` seconds never expire.
+pub fn kv_put(key: &str, value: &str, ttl_seconds: Option<u64>) -> Result<()> {
+    call_host_function::<String>(
+        HostMethod::KvPut.as_ref(),
+        Some(vec![
+            ParameterValue::String(key.to_string()),
+            ParameterValue::String(value.to_string()),
+            ParameterValue::Long(
+                ttl_seconds.map_or(0, |ttl| i64::try_from(ttl).unwrap_or(i64::MAX)),
+            ),
+        ]),
+        ReturnType::String,
+    )?;
+    Ok(())
+}
+
+/// Removes `key`, returning whether it existed.
+pub fn kv_delete(key: &str) -> Result<bool> {
+    let response = call_host_function::<String>(
+        HostMethod::KvDelete.as_ref(),
+        Some(vec![ParameterValue::String(key.to_string())]),
+        ReturnType::String,
+    )?;
+    parse_response(&response)
+}
+
+/// Lists the keys starting with `prefix`, in lexicographic order.
+pub fn kv_list(prefix: &str) -> Result<Vec<String>> {
+    let response = call_host_function::<String>(
+        HostMethod::KvList.as_ref(),
+        Some(vec![ParameterValue::String(prefix.to_string())]),
+        ReturnType::String,
+    )?;
+    parse_response(&response)
+}
+
+fn parse_response<T: DeserializeOwned>(response: &str) -> Result<T> {
+    serde_json::from_str(response).map_err(|e| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("Invalid key-value store response: {}", e),
+        )
+    })
+}
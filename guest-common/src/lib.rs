@@ -1,6 +1,8 @@
 #![no_std]
 extern crate alloc;
 mod register_guest_function;
+pub mod kv;
+pub mod logger;
 pub mod prelude;
 pub use register_guest_function::register_guest_function;
 pub use hyperlight_agents_common as agents_common;
@@ -23,6 +25,7 @@ use core::result::Result::Err;
 use core::result::Result::Ok;
 use core::option::Option::Some;
 use agents_common::structs::agent_message::AgentMessage;
+use agents_common::structs::http_request::HttpRequestMessage;
 
 /// Send a message to the host using a method name, guest message, and callback function.
 pub fn send_message_to_host_method(
@@ -40,6 +43,19 @@ pub fn send_message_to_host_method(
 	Ok(get_flatbuffer_result("Success"))
 }
 
+/// Ask the host to perform an HTTP request; the response is delivered to `request.callback`.
+pub fn send_http_request(request: &HttpRequestMessage) -> Result<Vec<u8>> {
+	let serialized = serde_json::to_string(request).unwrap();
+	let _res = call_host_function::<String>(
+		agents_common::constants::HostMethod::HttpRequest.as_ref(),
+		Some(Vec::from(&[
+			ParameterValue::String(serialized)
+		])),
+		common::flatbuffer_wrappers::function_types::ReturnType::String,
+	)?;
+	Ok(get_flatbuffer_result("Success"))
+}
+
 /// Default guest_dispatch_function for guests that do not support dynamic dispatch.
 pub fn default_guest_dispatch_function(function_call: FunctionCall) -> Result<Vec<u8>> {
 	Err(HyperlightGuestError::new(
@@ -0,0 +1,86 @@
+//! `log` crate backend that forwards records to the host, where they are tagged with the agent
+//! and MCP request ids and relayed to MCP clients that enabled logging.
+//!
+//! Call `init_logger` once, e.g. from `hyperlight_main`, then use the usual `log` macros.
+//! Structured fields use the `log` key-value syntax: `log::info!(url = url; "fetching")`.
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+
+use log::kv::{Error, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::agents_common::constants::HostMethod;
+use crate::agents_common::structs::log_record::{GuestLogLevel, GuestLogRecord};
+use crate::common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use crate::guest::error::Result;
+use crate::guest_bin::host_comm::call_host_function;
+
+struct HostLogger;
+
+static LOGGER: HostLogger = HostLogger;
+
+impl Log for HostLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut fields = FieldCollector(BTreeMap::new());
+        // A failing visitor only loses the remaining fields
+        let _ = record.key_values().visit(&mut fields);
+
+        let log_record = GuestLogRecord {
+            level: guest_log_level(record.level()),
+            target: record.target().to_string(),
+            message: format!("{}", record.args()),
+            fields: fields.0,
+        };
+        // There is nowhere to report a failure to log
+        let _ = send_log_record(&log_record);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the host logger and sets the maximum level passed on to the host.
+pub fn init_logger(max_level: LevelFilter) -> core::result::Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Sends a single record to the host, bypassing the `log` facade.
+pub fn send_log_record(record: &GuestLogRecord) -> Result<()> {
+    let serialized = serde_json::to_string(record).unwrap();
+    call_host_function::<String>(
+        HostMethod::Log.as_ref(),
+        Some(vec![ParameterValue::String(serialized)]),
+        ReturnType::String,
+    )?;
+    Ok(())
+}
+
+fn guest_log_level(level: Level) -> GuestLogLevel {
+    match level {
+        Level::Error => GuestLogLevel::Error,
+        Level::Warn => GuestLogLevel::Warn,
+        Level::Info => GuestLogLevel::Info,
+        Level::Debug => GuestLogLevel::Debug,
+        Level::Trace => GuestLogLevel::Trace,
+    }
+}
+
+struct FieldCollector(BTreeMap<String, String>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> core::result::Result<(), Error> {
+        self.0.insert(key.as_str().to_string(), format!("{}", value));
+        Ok(())
+    }
+}
@@ -6,7 +6,12 @@ pub use crate::common::flatbuffer_wrappers::guest_error::ErrorCode;
 pub use crate::common::flatbuffer_wrappers::util::get_flatbuffer_result;
 pub use crate::guest::error::HyperlightGuestError;
 pub use crate::register_guest_function;
+pub use crate::kv::{kv_delete, kv_get, kv_list, kv_put};
+pub use crate::logger::init_logger;
+pub use log;
 pub use crate::send_message_to_host_method;
+pub use crate::send_http_request;
+pub use crate::agents_common::structs::http_request::{secret_ref, HttpRequestMessage};
 pub use crate::default_guest_dispatch_function;
 pub use crate::guest_bin::host_comm::call_host_function;
 pub type Result<T> = core::result::Result<T, crate::guest::error::HyperlightGuestError>;
@@ -8,6 +8,7 @@ use alloc::{format};
 use regex::Regex;
 use hyperlight_agents_guest_common::prelude::*;
 use hyperlight_agents_common::structs::agent_message::AgentMessage;
+use hyperlight_agents_common::structs::host_error::HostError;
 
 pub const PROCESS_HTTP_RESPONSE: &str = "ProcessHttpResponse";
 
@@ -27,8 +28,19 @@ pub fn find_title_links<'a>(html: &'a str) -> Vec<(&'a str, &'a str)> {
 fn process_http_response(function_call: &FunctionCall) -> Result<Vec<u8>> {
     if let Some(parameters) = &function_call.parameters {
         if let Some(ParameterValue::String(http_body)) = parameters.get(0) {
+            if let Some(host_error) = HostError::parse(http_body) {
+                log::warn!(kind:? = host_error.kind; "Fetching Hacker News failed: {}", host_error.message);
+                let message = AgentMessage {
+                    callback: None,
+                    message: Some(format!("Failed to fetch Hacker News: {}", host_error.message)),
+                    guest_message: None,
+                    is_success: false,
+                };
+                return send_message_to_host_method(constants::HostMethod::FinalResult.as_ref(), message);
+            }
             let mut result = String::from("Top Hacker News stories:\n");
             let title_links = find_title_links(&http_body);
+            log::info!(links = title_links.len(), bytes = http_body.len(); "Parsed Hacker News front page");
             for (i, (url, title)) in title_links.iter().enumerate() {
                 result.push_str(&format!("{}. {} - {}\n", i + 1, title, url));
             }
@@ -78,6 +90,7 @@ fn get_mcp_tool(_function_call: &FunctionCall) -> Result<Vec<u8>> {
 
 #[no_mangle]
 pub extern "C" fn hyperlight_main() {
+    let _ = init_logger(log::LevelFilter::Info);
     register_guest_function(
         PROCESS_HTTP_RESPONSE,
         &[ParameterType::String],
@@ -16,10 +16,33 @@ pub const PROCESS_VM_CREATION_RESULT: &str = "ProcessVmCreationResult";
 pub const PROCESS_VM_COMMAND_RESULT: &str = "ProcessVmCommandResult";
 pub const PROCESS_VM_DESTRUCTION_RESULT: &str = "ProcessVmDestructionResult";
 pub const PROCESS_VM_LIST_RESULT: &str = "ProcessVmListResult";
+pub const PROCESS_VM_LOGS_RESULT: &str = "ProcessVmLogsResult";
+pub const PROCESS_VOLUME_RESULT: &str = "ProcessVolumeResult";
+pub const PROCESS_FILE_RESULT: &str = "ProcessFileResult";
+pub const PROCESS_SYNC_RESULT: &str = "ProcessSyncResult";
 
 pub const PARAM_ACTION: &str = "action";
 pub const PARAM_VM_ID: &str = "vm_id";
 pub const PARAM_COMMAND: &str = "command";
+pub const PARAM_SPEC: &str = "spec";
+pub const PARAM_PROFILE: &str = "profile";
+pub const PARAM_STREAM: &str = "stream";
+pub const PARAM_LINES: &str = "lines";
+pub const PARAM_VOLUME: &str = "volume";
+pub const PARAM_SIZE_MIB: &str = "size_mib";
+pub const PARAM_PATH: &str = "path";
+pub const PARAM_CONTENT: &str = "content";
+pub const PARAM_ENCODING: &str = "encoding";
+pub const PARAM_MODE: &str = "mode";
+pub const PARAM_SOURCE: &str = "source";
+pub const PARAM_TARGET: &str = "target";
+pub const PARAM_INCREMENTAL: &str = "incremental";
+pub const PARAM_ARGS: &str = "args";
+pub const PARAM_WORKING_DIR: &str = "working_dir";
+pub const PARAM_TIMEOUT_SECONDS: &str = "timeout_seconds";
+pub const PARAM_ENV: &str = "env";
+pub const PARAM_STDIN: &str = "stdin";
+pub const PARAM_SHELL: &str = "shell";
 
 #[derive(Deserialize, Debug)]
 struct VmActionParams {
@@ -29,6 +52,44 @@ struct VmActionParams {
     vm_id: Option<String>,
     #[serde(rename = "command")]
     command: Option<String>,
+    #[serde(rename = "spec")]
+    spec: Option<Value>,
+    #[serde(rename = "profile")]
+    profile: Option<String>,
+    #[serde(rename = "stream")]
+    stream: Option<String>,
+    #[serde(rename = "lines")]
+    lines: Option<u64>,
+    #[serde(rename = "volume")]
+    volume: Option<String>,
+    #[serde(rename = "size_mib")]
+    size_mib: Option<u64>,
+    #[serde(rename = "path")]
+    path: Option<String>,
+    #[serde(rename = "content")]
+    content: Option<String>,
+    #[serde(rename = "encoding")]
+    encoding: Option<String>,
+    #[serde(rename = "mode")]
+    mode: Option<String>,
+    #[serde(rename = "source")]
+    source: Option<String>,
+    #[serde(rename = "target")]
+    target: Option<String>,
+    #[serde(rename = "incremental")]
+    incremental: Option<bool>,
+    #[serde(rename = "args")]
+    args: Option<Vec<String>>,
+    #[serde(rename = "working_dir")]
+    working_dir: Option<String>,
+    #[serde(rename = "timeout_seconds")]
+    timeout_seconds: Option<u64>,
+    #[serde(rename = "env")]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(rename = "stdin")]
+    stdin: Option<String>,
+    #[serde(rename = "shell")]
+    shell: Option<bool>,
 }
 
 fn guest_run(function_call: &FunctionCall) -> Result<Vec<u8>> {
@@ -46,11 +107,94 @@ fn guest_run(function_call: &FunctionCall) -> Result<Vec<u8>> {
             let action = params.action;
             let vm_id = params.vm_id.unwrap_or_else(|| "default_vm".to_string());
             let command = params.command.unwrap_or_default();
+            let mut command_request = Map::new();
+            command_request.insert(PARAM_COMMAND.to_string(), Value::String(command.clone()));
+            if let Some(args) = params.args {
+                command_request.insert(
+                    PARAM_ARGS.to_string(),
+                    Value::Array(args.into_iter().map(Value::String).collect()),
+                );
+            }
+            if let Some(working_dir) = params.working_dir {
+                command_request.insert(PARAM_WORKING_DIR.to_string(), Value::String(working_dir));
+            }
+            if let Some(timeout_seconds) = params.timeout_seconds {
+                command_request.insert(
+                    PARAM_TIMEOUT_SECONDS.to_string(),
+                    Value::from(timeout_seconds),
+                );
+            }
+            if let Some(env) = params.env {
+                let env = env
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::String(value)))
+                    .collect();
+                command_request.insert(PARAM_ENV.to_string(), Value::Object(env));
+            }
+            if let Some(stdin) = params.stdin {
+                command_request.insert(PARAM_STDIN.to_string(), Value::String(stdin));
+            }
+            if let Some(shell) = params.shell {
+                command_request.insert(PARAM_SHELL.to_string(), Value::Bool(shell));
+            }
+            let command_request = Value::Object(command_request).to_string();
+            let mut spec = match params.spec {
+                Some(Value::Object(spec)) => spec,
+                _ => Map::new(),
+            };
+            if let Some(profile) = params.profile {
+                spec.insert(PARAM_PROFILE.to_string(), Value::String(profile));
+            }
+            let spec = Value::Object(spec).to_string();
+            let mut logs_request = Map::new();
+            if let Some(stream) = params.stream {
+                logs_request.insert(PARAM_STREAM.to_string(), Value::String(stream));
+            }
+            if let Some(lines) = params.lines {
+                logs_request.insert(PARAM_LINES.to_string(), Value::from(lines));
+            }
+            let logs_request = Value::Object(logs_request).to_string();
+            let volume = params.volume.unwrap_or_default();
+            let mut volume_request = Map::new();
+            if let Some(size_mib) = params.size_mib {
+                volume_request.insert(PARAM_SIZE_MIB.to_string(), Value::from(size_mib));
+            }
+            let volume_request = Value::Object(volume_request).to_string();
+            let mut file_request = Map::new();
+            file_request.insert(
+                PARAM_PATH.to_string(),
+                Value::String(params.path.unwrap_or_default()),
+            );
+            if let Some(content) = params.content {
+                file_request.insert(PARAM_CONTENT.to_string(), Value::String(content));
+            }
+            if let Some(encoding) = params.encoding {
+                file_request.insert(PARAM_ENCODING.to_string(), Value::String(encoding));
+            }
+            if let Some(mode) = params.mode {
+                file_request.insert(PARAM_MODE.to_string(), Value::String(mode));
+            }
+            let file_request = Value::Object(file_request).to_string();
+            let mut sync_request = Map::new();
+            sync_request.insert(
+                PARAM_SOURCE.to_string(),
+                Value::String(params.source.unwrap_or_default()),
+            );
+            sync_request.insert(
+                PARAM_TARGET.to_string(),
+                Value::String(params.target.unwrap_or_default()),
+            );
+            sync_request.insert(
+                PARAM_INCREMENTAL.to_string(),
+                Value::Bool(params.incremental.unwrap_or(false)),
+            );
+            let sync_request = Value::Object(sync_request).to_string();
             let res = match action.as_str() {
                 "create_vm" => call_host_function::<String>(
                     constants::HostMethod::CreateVM.as_ref(),
                     Some(vec![
                         ParameterValue::String(vm_id),
+                        ParameterValue::String(spec),
                         ParameterValue::String(PROCESS_VM_CREATION_RESULT.to_string()),
                     ]),
                     ReturnType::String,
@@ -59,7 +203,7 @@ fn guest_run(function_call: &FunctionCall) -> Result<Vec<u8>> {
                     constants::HostMethod::ExecuteVMCommand.as_ref(),
                     Some(vec![
                         ParameterValue::String(vm_id.clone()),
-                        ParameterValue::String(command.clone()),
+                        ParameterValue::String(command_request),
                         ParameterValue::String(PROCESS_VM_COMMAND_RESULT.to_string()),
                     ]),
                     ReturnType::String,
@@ -68,7 +212,7 @@ fn guest_run(function_call: &FunctionCall) -> Result<Vec<u8>> {
                     constants::HostMethod::SpawnCommand.as_ref(),
                     Some(vec![
                         ParameterValue::String(vm_id.clone()),
-                        ParameterValue::String(command.clone()),
+                        ParameterValue::String(command_request),
                         ParameterValue::String(PROCESS_VM_COMMAND_RESULT.to_string()),
                     ]),
                     ReturnType::String,
@@ -106,9 +250,70 @@ fn guest_run(function_call: &FunctionCall) -> Result<Vec<u8>> {
                     ]),
                     ReturnType::String,
                 ),
+                "get_vm_logs" => call_host_function::<String>(
+                    constants::HostMethod::GetVMLogs.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(vm_id),
+                        ParameterValue::String(logs_request),
+                        ParameterValue::String(PROCESS_VM_LOGS_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "create_volume" => call_host_function::<String>(
+                    constants::HostMethod::CreateVolume.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(volume),
+                        ParameterValue::String(volume_request),
+                        ParameterValue::String(PROCESS_VOLUME_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "list_volumes" => call_host_function::<String>(
+                    constants::HostMethod::ListVolumes.as_ref(),
+                    Some(vec![
+                        ParameterValue::String("".to_string()),
+                        ParameterValue::String(PROCESS_VOLUME_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "delete_volume" => call_host_function::<String>(
+                    constants::HostMethod::DeleteVolume.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(volume),
+                        ParameterValue::String(PROCESS_VOLUME_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "put_file" => call_host_function::<String>(
+                    constants::HostMethod::PutFile.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(vm_id),
+                        ParameterValue::String(file_request),
+                        ParameterValue::String(PROCESS_FILE_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "get_file" => call_host_function::<String>(
+                    constants::HostMethod::GetFile.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(vm_id),
+                        ParameterValue::String(file_request),
+                        ParameterValue::String(PROCESS_FILE_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
+                "sync_directory" => call_host_function::<String>(
+                    constants::HostMethod::SyncDirectory.as_ref(),
+                    Some(vec![
+                        ParameterValue::String(vm_id),
+                        ParameterValue::String(sync_request),
+                        ParameterValue::String(PROCESS_SYNC_RESULT.to_string()),
+                    ]),
+                    ReturnType::String,
+                ),
                 _ => return Err(HyperlightGuestError::new(
                     ErrorCode::GuestFunctionParameterTypeMismatch,
-                    format!("VM action invalid, must be one of: create_vm, execute_vm_command, spawn_command, list_spawned_processes, stop_spawned_process, destroy_vm, list_vms. Got {:?}", action),
+                    format!("VM action invalid, must be one of: create_vm, execute_vm_command, spawn_command, list_spawned_processes, stop_spawned_process, destroy_vm, list_vms, get_vm_logs, create_volume, list_volumes, delete_volume, put_file, get_file, sync_directory. Got {:?}", action),
                 )),
             };
             match res {
@@ -132,7 +337,7 @@ fn get_mcp_tool(_function_call: &FunctionCall) -> Result<Vec<u8>> {
 
     let mut action_schema = Map::new();
     action_schema.insert("type".to_string(), Value::String("string".to_string()));
-    action_schema.insert("description".to_string(), Value::String("Action to perform, must be one of: create_vm, execute_vm_command, spawn_command, list_spawned_processes, stop_spawned_process, destroy_vm, list_vms".to_string()));
+    action_schema.insert("description".to_string(), Value::String("Action to perform, must be one of: create_vm, execute_vm_command, spawn_command, list_spawned_processes, stop_spawned_process, destroy_vm, list_vms, get_vm_logs, create_volume, list_volumes, delete_volume, put_file, get_file, sync_directory".to_string()));
     params.insert(PARAM_ACTION.to_string(), action_schema);
 
     let mut vm_id_schema = Map::new();
@@ -145,9 +350,182 @@ fn get_mcp_tool(_function_call: &FunctionCall) -> Result<Vec<u8>> {
 
     let mut command_schema = Map::new();
     command_schema.insert("type".to_string(), Value::String("string".to_string()));
-    command_schema.insert("description".to_string(), Value::String("Command to execute in the VM, arguments for spawn_command, or process_id for stop_spawned_process".to_string()));
+    command_schema.insert("description".to_string(), Value::String("Program to execute in the VM for execute_vm_command and spawn_command (a command line if shell is set), or process_id for stop_spawned_process".to_string()));
     params.insert(PARAM_COMMAND.to_string(), command_schema);
 
+    let mut args_schema = Map::new();
+    args_schema.insert("type".to_string(), Value::String("array".to_string()));
+    let mut args_items = Map::new();
+    args_items.insert("type".to_string(), Value::String("string".to_string()));
+    args_schema.insert("items".to_string(), Value::Object(args_items));
+    args_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Arguments for execute_vm_command and spawn_command, passed to the program as is (to the command line as $1, $2, ... if shell is set)"
+                .to_string(),
+        ),
+    );
+    params.insert(PARAM_ARGS.to_string(), args_schema);
+
+    let mut working_dir_schema = Map::new();
+    working_dir_schema.insert("type".to_string(), Value::String("string".to_string()));
+    working_dir_schema.insert(
+        "description".to_string(),
+        Value::String("Working directory for execute_vm_command and spawn_command, default the vm-agent's (/ in a VM)".to_string()),
+    );
+    params.insert(PARAM_WORKING_DIR.to_string(), working_dir_schema);
+
+    let mut timeout_seconds_schema = Map::new();
+    timeout_seconds_schema.insert("type".to_string(), Value::String("integer".to_string()));
+    timeout_seconds_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Seconds after which execute_vm_command kills the command, default 30".to_string(),
+        ),
+    );
+    params.insert(PARAM_TIMEOUT_SECONDS.to_string(), timeout_seconds_schema);
+
+    let mut env_schema = Map::new();
+    env_schema.insert("type".to_string(), Value::String("object".to_string()));
+    env_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Environment variables for execute_vm_command and spawn_command, added to the VM's environment"
+                .to_string(),
+        ),
+    );
+    params.insert(PARAM_ENV.to_string(), env_schema);
+
+    let mut stdin_schema = Map::new();
+    stdin_schema.insert("type".to_string(), Value::String("string".to_string()));
+    stdin_schema.insert(
+        "description".to_string(),
+        Value::String("Standard input for execute_vm_command".to_string()),
+    );
+    params.insert(PARAM_STDIN.to_string(), stdin_schema);
+
+    let mut shell_schema = Map::new();
+    shell_schema.insert("type".to_string(), Value::String("boolean".to_string()));
+    shell_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Run command as a command line through sh -c instead of executing it directly, default false"
+                .to_string(),
+        ),
+    );
+    params.insert(PARAM_SHELL.to_string(), shell_schema);
+
+    let mut spec_schema = Map::new();
+    spec_schema.insert("type".to_string(), Value::String("object".to_string()));
+    spec_schema.insert("description".to_string(), Value::String("Machine configuration for create_vm overriding the profile: vcpu_count, mem_size_mib, smt, boot_args, kernel_path, rootfs_path, env, ttl_secs, idle_timeout_secs, owner, labels, volumes (list of {mount_path, name, size_mib, read_only}; a name attaches a persistent volume, without one the volume is a scratch disk deleted with the VM)".to_string()));
+    params.insert(PARAM_SPEC.to_string(), spec_schema);
+
+    let mut profile_schema = Map::new();
+    profile_schema.insert("type".to_string(), Value::String("string".to_string()));
+    profile_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "VM profile for create_vm, e.g. alpine-base (default), rust-toolchain or node"
+                .to_string(),
+        ),
+    );
+    params.insert(PARAM_PROFILE.to_string(), profile_schema);
+
+    let mut stream_schema = Map::new();
+    stream_schema.insert("type".to_string(), Value::String("string".to_string()));
+    stream_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Output stream for get_vm_logs: console (default), log or metrics".to_string(),
+        ),
+    );
+    params.insert(PARAM_STREAM.to_string(), stream_schema);
+
+    let mut lines_schema = Map::new();
+    lines_schema.insert("type".to_string(), Value::String("integer".to_string()));
+    lines_schema.insert(
+        "description".to_string(),
+        Value::String("Number of most recent lines for get_vm_logs, default 100".to_string()),
+    );
+    params.insert(PARAM_LINES.to_string(), lines_schema);
+
+    let mut volume_schema = Map::new();
+    volume_schema.insert("type".to_string(), Value::String("string".to_string()));
+    volume_schema.insert(
+        "description".to_string(),
+        Value::String("Name of the volume for create_volume and delete_volume".to_string()),
+    );
+    params.insert(PARAM_VOLUME.to_string(), volume_schema);
+
+    let mut size_mib_schema = Map::new();
+    size_mib_schema.insert("type".to_string(), Value::String("integer".to_string()));
+    size_mib_schema.insert(
+        "description".to_string(),
+        Value::String("Size of the volume for create_volume, default 1024".to_string()),
+    );
+    params.insert(PARAM_SIZE_MIB.to_string(), size_mib_schema);
+
+    let mut path_schema = Map::new();
+    path_schema.insert("type".to_string(), Value::String("string".to_string()));
+    path_schema.insert(
+        "description".to_string(),
+        Value::String("Absolute path in the VM for put_file and get_file".to_string()),
+    );
+    params.insert(PARAM_PATH.to_string(), path_schema);
+
+    let mut content_schema = Map::new();
+    content_schema.insert("type".to_string(), Value::String("string".to_string()));
+    content_schema.insert(
+        "description".to_string(),
+        Value::String("Contents to write for put_file".to_string()),
+    );
+    params.insert(PARAM_CONTENT.to_string(), content_schema);
+
+    let mut encoding_schema = Map::new();
+    encoding_schema.insert("type".to_string(), Value::String("string".to_string()));
+    encoding_schema.insert(
+        "description".to_string(),
+        Value::String("Encoding of content for put_file: utf8 (default) or base64".to_string()),
+    );
+    params.insert(PARAM_ENCODING.to_string(), encoding_schema);
+
+    let mut mode_schema = Map::new();
+    mode_schema.insert("type".to_string(), Value::String("string".to_string()));
+    mode_schema.insert(
+        "description".to_string(),
+        Value::String("Octal permission bits for put_file, default 644".to_string()),
+    );
+    params.insert(PARAM_MODE.to_string(), mode_schema);
+
+    let mut source_schema = Map::new();
+    source_schema.insert("type".to_string(), Value::String("string".to_string()));
+    source_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Host directory or tar archive for sync_directory, inside the host's sync directories"
+                .to_string(),
+        ),
+    );
+    params.insert(PARAM_SOURCE.to_string(), source_schema);
+
+    let mut target_schema = Map::new();
+    target_schema.insert("type".to_string(), Value::String("string".to_string()));
+    target_schema.insert(
+        "description".to_string(),
+        Value::String("Absolute directory in the VM for sync_directory".to_string()),
+    );
+    params.insert(PARAM_TARGET.to_string(), target_schema);
+
+    let mut incremental_schema = Map::new();
+    incremental_schema.insert("type".to_string(), Value::String("boolean".to_string()));
+    incremental_schema.insert(
+        "description".to_string(),
+        Value::String(
+            "Only send files that changed since the last sync_directory, default false".to_string(),
+        ),
+    );
+    params.insert(PARAM_INCREMENTAL.to_string(), incremental_schema);
+
     let required = vec![PARAM_ACTION.to_string(), PARAM_VM_ID.to_string()];
 
     let tool = Tool {
@@ -190,20 +568,24 @@ fn process_vm_command_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
 fn process_vm_destruction_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
     process_result(function_call, "VM Destruction Result")
 }
+fn process_vm_logs_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
+    process_result(function_call, "VM Logs Result")
+}
 
 fn process_vm_list_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
-    // For list, show all parameters
-    if let Some(parameters) = function_call.parameters.as_ref() {
-        let result_message = format!("Available VMs: {:?}", parameters);
-        let message = AgentMessage {
-            callback: None,
-            message: Some(result_message),
-            guest_message: None,
-            is_success: true,
-        };
-        return send_message_to_host_method(constants::HostMethod::FinalResult.as_ref(), message);
-    }
-    Ok(get_flatbuffer_result("VM list result processed"))
+    process_result(function_call, "VM List Result")
+}
+
+fn process_volume_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
+    process_result(function_call, "Volume Result")
+}
+
+fn process_file_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
+    process_result(function_call, "File Result")
+}
+
+fn process_sync_result(function_call: &FunctionCall) -> Result<Vec<u8>> {
+    process_result(function_call, "Sync Result")
 }
 
 #[no_mangle]
@@ -245,6 +627,30 @@ pub extern "C" fn hyperlight_main() {
         ReturnType::String,
         process_vm_list_result as usize,
     );
+    register_guest_function(
+        PROCESS_VM_LOGS_RESULT,
+        &[ParameterType::String],
+        ReturnType::String,
+        process_vm_logs_result as usize,
+    );
+    register_guest_function(
+        PROCESS_VOLUME_RESULT,
+        &[ParameterType::String],
+        ReturnType::String,
+        process_volume_result as usize,
+    );
+    register_guest_function(
+        PROCESS_FILE_RESULT,
+        &[ParameterType::String],
+        ReturnType::String,
+        process_file_result as usize,
+    );
+    register_guest_function(
+        PROCESS_SYNC_RESULT,
+        &[ParameterType::String],
+        ReturnType::String,
+        process_sync_result as usize,
+    );
 }
 
 #[no_mangle]
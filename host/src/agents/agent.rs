@@ -1,17 +1,34 @@
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use hyperlight_agents_common::structs::agent_message::AgentMessage;
+use hyperlight_agents_common::structs::host_error::{HostError, HostErrorKind};
+use hyperlight_agents_common::structs::http_request::HttpRequestMessage;
+use hyperlight_agents_common::structs::log_record::{GuestLogLevel, GuestLogRecord};
 use hyperlight_host::sandbox::SandboxConfiguration;
 use hyperlight_host::{MultiUseSandbox, UninitializedSandbox};
 //use opentelemetry::global::{self};
 //use opentelemetry::trace::{Span, TraceContextExt, Tracer};
 //use opentelemetry::Context;
 
-use crate::host_functions::network_functions::http_request;
-use crate::host_functions::vm_functions::VmManager;
-use crate::mcp_server::{MCP_AGENT_REQUEST_IDS, MCP_RESPONSE_CHANNELS};
+use crate::config::{AgentConfig, HostConfig};
+use crate::host_functions::cassette::{Cassette, UnmatchedRequest};
+use crate::host_functions::kv_store::KvStore;
+use crate::host_functions::network_functions::{agent_http_request, AgentNetwork};
+use crate::host_functions::network_policy::{self, EgressError};
+use crate::host_functions::secrets::{AgentSecrets, SecretError, SecretStore};
+use crate::host_functions::vm_functions::command_stream::VmCommandEvent;
+use crate::host_functions::vm_functions::files::{VmFileContents, VmFileRequest};
+use crate::host_functions::vm_functions::sync::SyncRequest;
+use crate::host_functions::vm_functions::vm_logs::VmLogsRequest;
+use crate::host_functions::vm_functions::volumes::VolumeRequest;
+use crate::host_functions::vm_functions::{VmCommandRequest, VmManager};
+use crate::mcp_server::{
+    forward_command_output, forward_guest_log, MCP_AGENT_REQUEST_IDS, MCP_RESPONSE_CHANNELS,
+};
+use futures::StreamExt;
 use hyperlight_agents_common::{constants, Tool};
 use reqwest::Client;
 use std::sync::mpsc::{channel, Receiver, Sender};
@@ -26,11 +43,21 @@ pub struct Agent {
     pub request_id: Option<String>,             // For tracking MCP request IDs
 }
 
+/// Host-side services shared by every agent sandbox.
+#[derive(Clone)]
+pub struct AgentServices {
+    pub http_client: Arc<Client>,
+    pub vm_manager: Arc<VmManager>,
+    pub host_config: Arc<HostConfig>,
+    pub cassette: Option<Arc<Cassette>>,
+    pub kv_store: Arc<KvStore>,
+    pub secret_store: Arc<SecretStore>,
+}
+
 pub fn create_agent(
     agent_id: String,
-    http_client: Arc<Client>,
     binary_path: String,
-    vm_manager: Arc<VmManager>,
+    services: &AgentServices,
 ) -> hyperlight_host::Result<Agent> {
     // Create a channel for communication
     let (tx, rx) = channel::<(Option<String>, String)>();
@@ -47,13 +74,17 @@ pub fn create_agent(
     let mut uninitialized_sandbox =
         UninitializedSandbox::new(guest_instance, Some(sandbox_config))?;
 
+    let agent_config = services
+        .host_config
+        .agent(agent_id.split("/").last().unwrap_or(&agent_id));
+
     // Register host functions specific to this agent
     register_host_functions(
         &mut uninitialized_sandbox,
         tx.clone(),
-        http_client,
         &agent_id,
-        vm_manager,
+        &agent_config,
+        services,
     )?;
 
     // Initialize the sandbox
@@ -79,57 +110,84 @@ pub fn create_agent(
 pub fn register_host_functions(
     sandbox: &mut UninitializedSandbox,
     tx: Sender<(Option<String>, String)>,
-    http_client: Arc<Client>,
     agent_id: &str,
-    vm_manager: Arc<VmManager>,
+    agent_config: &AgentConfig,
+    services: &AgentServices,
 ) -> hyperlight_host::Result<()> {
     // Define common syscalls that guest code might need
     let all_syscalls: Vec<i64> = (0..=500).collect();
+    let vm_manager = services.vm_manager.clone();
 
     // Register HTTP fetch function with extra allowed syscalls
-    let http_client_clone = http_client.clone();
+    let http_client_clone = services.http_client.clone();
     let tx_clone = tx.clone();
+    let agent_network = Arc::new(AgentNetwork {
+        agent_id: agent_id.split("/").last().unwrap_or(agent_id).to_string(),
+        policy: agent_config.network.clone(),
+        cassette: services.cassette.clone(),
+        secrets: AgentSecrets::new(agent_config.secrets.clone(), services.secret_store.clone()),
+    });
+    let network = agent_network.clone();
 
     sandbox.register_with_extra_allowed_syscalls(
         constants::HostMethod::FetchData.as_ref(),
         move |agent_message_serialized: String| {
             let agent_message: AgentMessage = serde_json::from_str(&agent_message_serialized).unwrap();
-            let callback_name = agent_message.callback.clone().unwrap_or_default();
-            let url = agent_message.message.clone().unwrap_or_default();
-            let client = http_client_clone.clone();
-            let sender = tx_clone.clone();
-
-            // let tracer = global::tracer("host_method");
-            // let span = tracer.start("HostMethod::FetchData");
-            // let cx = Context::current_with_span(span);
+            let request = HttpRequestMessage {
+                callback: agent_message.callback.clone().unwrap_or_default(),
+                method: "GET".to_string(),
+                url: agent_message.message.clone().unwrap_or_default(),
+                headers: Default::default(),
+                body: None,
+            };
 
-            std::thread::spawn(move || {
-                //let tracer = global::tracer("host_method");
-                //let mut child_span = tracer.start_with_context("http_request", &cx);
+            spawn_agent_http_request(
+                http_client_clone.clone(),
+                network.clone(),
+                tx_clone.clone(),
+                request,
+            );
 
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                let response = rt.block_on(async {
-                    match http_request(client, &url, "GET", None, None).await {
-                        Ok(resp) => {
-                            //child_span.add_event(format!("Http Request {}", &url), vec![]);
-                            resp
-                        }
-                        Err(e) => format!("HTTP request failed: {}", e),
-                    }
-                });
+            Ok("Http Request sent".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
 
-                if let Err(e) = sender.send((Some(response), callback_name)) {
-                    log::error!("Failed to send response: {:?}", e);
-                }
+    // Register HTTP request function, which unlike FetchData accepts a method, headers and body
+    let http_client_clone = services.http_client.clone();
+    let tx_clone = tx.clone();
+    let network = agent_network.clone();
 
-                //child_span.end();
-            });
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::HttpRequest.as_ref(),
+        move |request_serialized: String| {
+            let request: HttpRequestMessage = serde_json::from_str(&request_serialized)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(format!("Invalid HTTP request: {}", e)))?;
+
+            spawn_agent_http_request(
+                http_client_clone.clone(),
+                network.clone(),
+                tx_clone.clone(),
+                request,
+            );
 
             Ok("Http Request sent".to_string())
         },
         all_syscalls.clone(),
     )?;
 
+    // Register the query for the agent's recent egress violations
+    let violations_agent_id = agent_network.agent_id.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::ListEgressViolations.as_ref(),
+        move || {
+            let violations = network_policy::egress_violations(&violations_agent_id);
+            Ok(serde_json::to_string(&violations).unwrap_or_else(|_| "[]".to_string()))
+        },
+        all_syscalls.clone(),
+    )?;
+
     // Register final result function
     let agent_id_clone = agent_id.split("/").last().unwrap_or(agent_id).to_string();
 
@@ -174,20 +232,125 @@ pub fn register_host_functions(
         all_syscalls.clone(),
     )?;
 
+    // Register guest log function, tagging records with the agent and MCP request ids
+    let agent_id_clone = agent_id.split("/").last().unwrap_or(agent_id).to_string();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::Log.as_ref(),
+        move |record_serialized: String| {
+            let record: GuestLogRecord = serde_json::from_str(&record_serialized)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(format!("Invalid log record: {}", e)))?;
+            let request_id = MCP_AGENT_REQUEST_IDS
+                .lock()
+                .ok()
+                .and_then(|request_ids| request_ids.get(&agent_id_clone).cloned());
+
+            let fields: String = record
+                .fields
+                .iter()
+                .map(|(key, value)| format!(" {}={}", key, value))
+                .collect();
+            log::log!(
+                target: "guest",
+                host_log_level(record.level),
+                "[{}][{}] {}: {}{}",
+                agent_id_clone,
+                request_id.as_deref().unwrap_or("-"),
+                record.target,
+                record.message,
+                fields
+            );
+
+            if let Some(request_id) = &request_id {
+                forward_guest_log(&agent_id_clone, request_id, &record);
+            }
+
+            Ok("OK".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    // Register key-value store functions, namespaced by agent id
+    let kv_namespace = agent_id.split("/").last().unwrap_or(agent_id).to_string();
+
+    let kv_store = services.kv_store.clone();
+    let namespace = kv_namespace.clone();
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::KvGet.as_ref(),
+        move |key: String| {
+            let value = kv_store
+                .get(&namespace, &key)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(e.to_string()))?;
+            Ok(serde_json::to_string(&value).unwrap_or_else(|_| "null".to_string()))
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let kv_store = services.kv_store.clone();
+    let namespace = kv_namespace.clone();
+    let kv_quota = agent_config.kv.clone();
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::KvPut.as_ref(),
+        move |key: String, value: String, ttl_seconds: i64| {
+            // A TTL of zero or less keeps the entry until it is deleted
+            let ttl = (ttl_seconds > 0).then_some(ttl_seconds as u64);
+            kv_store
+                .put(&namespace, &key, value, ttl, &kv_quota)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(e.to_string()))?;
+            Ok("OK".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let kv_store = services.kv_store.clone();
+    let namespace = kv_namespace.clone();
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::KvDelete.as_ref(),
+        move |key: String| {
+            let removed = kv_store
+                .delete(&namespace, &key)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(e.to_string()))?;
+            Ok(removed.to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let kv_store = services.kv_store.clone();
+    let namespace = kv_namespace;
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::KvList.as_ref(),
+        move |prefix: String| {
+            let keys = kv_store
+                .list(&namespace, &prefix)
+                .map_err(|e| hyperlight_host::HyperlightError::Error(e.to_string()))?;
+            Ok(serde_json::to_string(&keys).unwrap_or_else(|_| "[]".to_string()))
+        },
+        all_syscalls.clone(),
+    )?;
+
     // Register VM management functions
     let vm_manager_clone = vm_manager.clone();
     let tx_clone = tx.clone();
+    // VMs belong to the agent creating them unless the request names an owner
+    let vm_owner = agent_id.split("/").last().unwrap_or(agent_id).to_string();
 
     sandbox.register_with_extra_allowed_syscalls(
         constants::HostMethod::CreateVM.as_ref(),
-        move |vm_id: String, callback_name: String| {
+        move |vm_id: String, spec: String, callback_name: String| {
             let vm_manager = vm_manager_clone.clone();
             let sender = tx_clone.clone();
+            let owner = vm_owner.clone();
 
             std::thread::spawn(move || {
                 let rt = tokio::runtime::Runtime::new().unwrap();
                 let response = rt.block_on(async {
-                    match vm_manager.create_vm(vm_id).await {
+                    // An empty spec requests the default profile
+                    let mut spec = match vm_manager.spec_from_request(&spec) {
+                        Ok(spec) => spec,
+                        Err(e) => return format!("VM creation failed: {}", e),
+                    };
+                    spec.owner.get_or_insert(owner);
+                    match vm_manager.create_vm(vm_id, spec).await {
                         Ok(resp) => resp,
                         Err(e) => format!("VM creation failed: {}", e),
                     }
@@ -205,29 +368,45 @@ pub fn register_host_functions(
 
     let vm_manager_clone = vm_manager.clone();
     let tx_clone = tx.clone();
+    // Command output goes to the MCP client of the agent's current request as it is produced
+    let command_agent_id = agent_id.split("/").last().unwrap_or(agent_id).to_string();
 
     sandbox.register_with_extra_allowed_syscalls(
         constants::HostMethod::ExecuteVMCommand.as_ref(),
-        move |vm_id: String, command: String, callback_name: String| {
+        move |vm_id: String, request: String, callback_name: String| {
             let vm_manager = vm_manager_clone.clone();
             let sender = tx_clone.clone();
+            let agent_id = command_agent_id.clone();
 
             std::thread::spawn(move || {
                 let rt = tokio::runtime::Runtime::new().unwrap();
                 let response = rt.block_on(async {
-                    match vm_manager
-                        .execute_vm_command(
-                            &vm_id,
-                            command,
-                            Vec::new(),
-                            Some("/".to_string()),
-                            Some(30),
-                        )
-                        .await
-                    {
-                        Ok(resp) => resp,
-                        Err(e) => format!("VM command execution failed: {}", e),
+                    let request = match serde_json::from_str::<VmCommandRequest>(&request) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            return format!("VM command execution failed: invalid request: {}", e)
+                        }
+                    };
+                    let request_id = MCP_AGENT_REQUEST_IDS
+                        .lock()
+                        .ok()
+                        .and_then(|request_ids| request_ids.get(&agent_id).cloned());
+                    let mut events = vm_manager.stream_vm_command(&vm_id, request);
+                    let mut response = String::new();
+                    while let Some(event) = events.next().await {
+                        match event {
+                            VmCommandEvent::Output(output) => {
+                                if let Some(request_id) = &request_id {
+                                    forward_command_output(&agent_id, request_id, &vm_id, &output);
+                                }
+                            }
+                            VmCommandEvent::Finished(Ok(resp)) => response = resp,
+                            VmCommandEvent::Finished(Err(e)) => {
+                                response = format!("VM command execution failed: {}", e)
+                            }
+                        }
                     }
+                    response
                 });
 
                 if let Err(e) = sender.send((Some(response), callback_name)) {
@@ -253,9 +432,12 @@ pub fn register_host_functions(
             std::thread::spawn(move || {
                 let rt = tokio::runtime::Runtime::new().unwrap();
                 let response = rt.block_on(async {
-                    match vm_manager.spawn_command(&vm_id, process_args).await {
-                        Ok(resp) => resp,
-                        Err(e) => format!("VM process spawn failed: {}", e),
+                    match serde_json::from_str::<VmCommandRequest>(&process_args) {
+                        Ok(request) => match vm_manager.spawn_command(&vm_id, request).await {
+                            Ok(resp) => resp,
+                            Err(e) => format!("VM process spawn failed: {}", e),
+                        },
+                        Err(e) => format!("VM process spawn failed: invalid request: {}", e),
                     }
                 });
 
@@ -314,9 +496,12 @@ pub fn register_host_functions(
             std::thread::spawn(move || {
                 let rt = tokio::runtime::Runtime::new().unwrap();
                 let response = rt.block_on(async {
-                    match vm_manager.spawn_command(&vm_id, command_args).await {
-                        Ok(resp) => resp,
-                        Err(e) => format!("Spawn command failed: {}", e),
+                    match serde_json::from_str::<VmCommandRequest>(&command_args) {
+                        Ok(request) => match vm_manager.spawn_command(&vm_id, request).await {
+                            Ok(resp) => resp,
+                            Err(e) => format!("Spawn command failed: {}", e),
+                        },
+                        Err(e) => format!("Spawn command failed: invalid request: {}", e),
                     }
                 });
 
@@ -428,7 +613,8 @@ pub fn register_host_functions(
             let sender = tx_clone.clone();
 
             std::thread::spawn(move || {
-                let vms = vm_manager.list_vms();
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                let vms = rt.block_on(vm_manager.list_vms());
                 let response = serde_json::to_string(&vms).unwrap_or_else(|_| "[]".to_string());
 
                 if let Err(e) = sender.send((Some(response), callback_name)) {
@@ -441,9 +627,311 @@ pub fn register_host_functions(
         all_syscalls.clone(),
     )?;
 
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::GetVMLogs.as_ref(),
+        move |vm_id: String, request: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let request = if request.trim().is_empty() {
+                    Ok(VmLogsRequest::default())
+                } else {
+                    serde_json::from_str::<VmLogsRequest>(&request)
+                };
+                let response = match request {
+                    Ok(request) => {
+                        match vm_manager.get_vm_logs(&vm_id, request.stream, request.lines) {
+                            Ok(lines) => lines.join("\n"),
+                            Err(e) => format!("Failed to get VM logs: {}", e),
+                        }
+                    }
+                    Err(e) => format!("Failed to get VM logs: invalid request: {}", e),
+                };
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send VM logs response: {:?}", e);
+                }
+            });
+
+            Ok("VM logs request initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::CreateVolume.as_ref(),
+        move |name: String, request: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let request = if request.trim().is_empty() {
+                    Ok(VolumeRequest::default())
+                } else {
+                    serde_json::from_str::<VolumeRequest>(&request)
+                };
+                let response = match request {
+                    Ok(request) => match vm_manager.create_volume(&name, request.size_mib) {
+                        Ok(resp) => resp,
+                        Err(e) => format!("Volume creation failed: {}", e),
+                    },
+                    Err(e) => format!("Volume creation failed: invalid request: {}", e),
+                };
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send volume creation response: {:?}", e);
+                }
+            });
+
+            Ok("Volume creation initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::ListVolumes.as_ref(),
+        move |_param1: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let volumes = vm_manager.list_volumes();
+                let response = serde_json::to_string(&volumes).unwrap_or_else(|_| "[]".to_string());
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send volume list response: {:?}", e);
+                }
+            });
+
+            Ok("Volume list request initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::DeleteVolume.as_ref(),
+        move |name: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let response = match vm_manager.delete_volume(&name) {
+                    Ok(resp) => resp,
+                    Err(e) => format!("Volume deletion failed: {}", e),
+                };
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send volume deletion response: {:?}", e);
+                }
+            });
+
+            Ok("Volume deletion initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::PutFile.as_ref(),
+        move |vm_id: String, request: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                let response = rt.block_on(async {
+                    let request = serde_json::from_str::<VmFileRequest>(&request)
+                        .map_err(|e| format!("invalid request: {}", e))
+                        .and_then(|request| Ok((request.decode()?, request.path)));
+                    match request {
+                        Ok(((contents, mode), path)) => {
+                            match vm_manager.put_file(&vm_id, &path, &contents, mode).await {
+                                Ok(resp) => resp,
+                                Err(e) => format!("File upload failed: {}", e),
+                            }
+                        }
+                        Err(e) => format!("File upload failed: {}", e),
+                    }
+                });
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send file upload response: {:?}", e);
+                }
+            });
+
+            Ok("File upload initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::GetFile.as_ref(),
+        move |vm_id: String, request: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                let response = rt.block_on(async {
+                    match serde_json::from_str::<VmFileRequest>(&request) {
+                        Ok(request) => match vm_manager.get_file(&vm_id, &request.path).await {
+                            Ok(file) => {
+                                serde_json::to_string(&VmFileContents::new(&request.path, file))
+                                    .unwrap_or_else(|e| format!("File download failed: {}", e))
+                            }
+                            Err(e) => format!("File download failed: {}", e),
+                        },
+                        Err(e) => format!("File download failed: invalid request: {}", e),
+                    }
+                });
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send file download response: {:?}", e);
+                }
+            });
+
+            Ok("File download initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
+    let vm_manager_clone = vm_manager.clone();
+    let tx_clone = tx.clone();
+
+    sandbox.register_with_extra_allowed_syscalls(
+        constants::HostMethod::SyncDirectory.as_ref(),
+        move |vm_id: String, request: String, callback_name: String| {
+            let vm_manager = vm_manager_clone.clone();
+            let sender = tx_clone.clone();
+
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                let response = rt.block_on(async {
+                    match serde_json::from_str::<SyncRequest>(&request) {
+                        Ok(request) => match vm_manager
+                            .sync_directory(
+                                &vm_id,
+                                Path::new(&request.source),
+                                &request.target,
+                                request.incremental,
+                            )
+                            .await
+                        {
+                            Ok(resp) => resp,
+                            Err(e) => format!("Directory sync failed: {}", e),
+                        },
+                        Err(e) => format!("Directory sync failed: invalid request: {}", e),
+                    }
+                });
+
+                if let Err(e) = sender.send((Some(response), callback_name)) {
+                    log::error!("Failed to send directory sync response: {:?}", e);
+                }
+            });
+
+            Ok("Directory sync initiated".to_string())
+        },
+        all_syscalls.clone(),
+    )?;
+
     Ok(())
 }
 
+/// Performs an agent HTTP request on a separate thread and delivers the response, or a serialized
+/// `HostError`, to the request's callback.
+fn spawn_agent_http_request(
+    client: Arc<Client>,
+    network: Arc<AgentNetwork>,
+    sender: Sender<(Option<String>, String)>,
+    request: HttpRequestMessage,
+) {
+    // let tracer = global::tracer("host_method");
+    // let span = tracer.start("HostMethod::FetchData");
+    // let cx = Context::current_with_span(span);
+
+    std::thread::spawn(move || {
+        //let tracer = global::tracer("host_method");
+        //let mut child_span = tracer.start_with_context("http_request", &cx);
+
+        let headers: Vec<(String, String)> = request.headers.into_iter().collect();
+        let body = request.body.map(String::into_bytes);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let response = rt.block_on(async {
+            match agent_http_request(
+                client,
+                &network,
+                &request.url,
+                &request.method,
+                body.as_deref(),
+                &headers,
+            )
+            .await
+            {
+                Ok(resp) => {
+                    //child_span.add_event(format!("Http Request {}", &url), vec![]);
+                    resp
+                }
+                Err(e) => match e.downcast_ref::<EgressError>() {
+                    Some(egress_error) => {
+                        host_error_response(HostErrorKind::EgressDenied, egress_error.to_string())
+                    }
+                    None if e.is::<UnmatchedRequest>() => {
+                        host_error_response(HostErrorKind::UnmatchedReplay, e.to_string())
+                    }
+                    None if e.is::<SecretError>() => {
+                        host_error_response(HostErrorKind::SecretUnavailable, e.to_string())
+                    }
+                    None => host_error_response(
+                        HostErrorKind::RequestFailed,
+                        format!("HTTP request failed: {}", e),
+                    ),
+                },
+            }
+        });
+
+        if let Err(e) = sender.send((Some(response), request.callback)) {
+            log::error!("Failed to send response: {:?}", e);
+        }
+
+        //child_span.end();
+    });
+}
+
+fn host_log_level(level: GuestLogLevel) -> log::Level {
+    match level {
+        GuestLogLevel::Error => log::Level::Error,
+        GuestLogLevel::Warn => log::Level::Warn,
+        GuestLogLevel::Info => log::Level::Info,
+        GuestLogLevel::Debug => log::Level::Debug,
+        GuestLogLevel::Trace => log::Level::Trace,
+    }
+}
+
+/// Serializes a `HostError` for delivery to a guest callback.
+fn host_error_response(kind: HostErrorKind, message: String) -> String {
+    serde_json::to_string(&HostError { kind, message })
+        .unwrap_or_else(|_| "HTTP request failed".to_string())
+}
+
 pub fn run_agent_event_loop(agent: &mut Agent, shutdown_flag: Arc<AtomicBool>) {
     log::debug!("Agent {} event loop started", agent.id);
 
@@ -0,0 +1,87 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::host_functions::cassette::CassetteConfig;
+use crate::host_functions::kv_store::KvQuota;
+use crate::host_functions::network_policy::NetworkPolicy;
+use crate::host_functions::secrets::SecretConfig;
+use crate::host_functions::vm_functions::vm_spec::VmConfig;
+
+/// Environment variable that points at the host configuration file.
+pub const CONFIG_PATH_ENV: &str = "HYPERLIGHT_AGENTS_CONFIG";
+
+/// Configuration file picked up from the working directory when the env variable is not set.
+const DEFAULT_CONFIG_PATH: &str = "hyperlight_agents.json";
+
+/// Host-wide configuration, loaded once at startup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HostConfig {
+    /// Directory for state that outlives the host process, such as the agent key-value store.
+    pub state_dir: PathBuf,
+    /// Per-agent settings keyed by agent id (the guest binary file name).
+    pub agents: HashMap<String, AgentConfig>,
+    /// Settings for agents without an entry in `agents`.
+    pub default_agent: AgentConfig,
+    /// Records or replays agent HTTP traffic when set.
+    pub http_cassette: Option<CassetteConfig>,
+    /// Encrypted secrets file, decrypted with the key in `HYPERLIGHT_SECRETS_KEY`.
+    pub secrets_file: Option<PathBuf>,
+    /// VM profiles and the maximums for the machine configuration agents may request.
+    pub vm: VmConfig,
+}
+
+/// Settings applied to a single agent sandbox.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    pub network: NetworkPolicy,
+    pub kv: KvQuota,
+    /// Secrets the agent may reference by name in outgoing HTTP headers.
+    pub secrets: HashMap<String, SecretConfig>,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            state_dir: PathBuf::from("state"),
+            agents: HashMap::new(),
+            default_agent: AgentConfig::default(),
+            http_cassette: None,
+            secrets_file: None,
+            vm: VmConfig::default(),
+        }
+    }
+}
+
+impl HostConfig {
+    /// Loads the configuration from `HYPERLIGHT_AGENTS_CONFIG` or `./hyperlight_agents.json`.
+    /// A missing default file yields the built-in defaults; a missing explicit file is an error.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let (path, explicit) = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_string(), false),
+        };
+
+        if !explicit && !Path::new(&path).exists() {
+            log::debug!("No host configuration found at {}, using defaults", path);
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read host configuration {}: {}", path, e))?;
+        let config: HostConfig = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse host configuration {}: {}", path, e))?;
+        log::info!("Loaded host configuration from {}", path);
+        Ok(config)
+    }
+
+    /// Returns the settings for an agent, falling back to `default_agent`.
+    pub fn agent(&self, agent_id: &str) -> AgentConfig {
+        self.agents
+            .get(agent_id)
+            .cloned()
+            .unwrap_or_else(|| self.default_agent.clone())
+    }
+}
@@ -0,0 +1,321 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Whether HTTP exchanges are written to or served from the cassette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Request attribute compared when looking up a recorded exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchRule {
+    Method,
+    Url,
+    BodyHash,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: PathBuf,
+    #[serde(default = "default_match_rules")]
+    pub match_on: Vec<MatchRule>,
+    /// In replay mode, fail requests without a recorded match instead of sending them.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+fn default_match_rules() -> Vec<MatchRule> {
+    vec![MatchRule::Method, MatchRule::Url]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_sha256: Option<String>,
+}
+
+/// Outcome of a recorded exchange; exactly one of `body` and `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub agent_id: String,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+/// Returned in strict replay mode when no recorded exchange matches a request.
+#[derive(Debug, Clone)]
+pub struct UnmatchedRequest {
+    pub method: String,
+    pub url: String,
+}
+
+impl fmt::Display for UnmatchedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No recorded interaction matches {} {}",
+            self.method, self.url
+        )
+    }
+}
+
+impl std::error::Error for UnmatchedRequest {}
+
+/// Recorded HTTP exchanges between agents and the network.
+pub struct Cassette {
+    config: CassetteConfig,
+    interactions: Mutex<Vec<Interaction>>,
+    // Replay cursor: interactions already served, so repeated requests get successive answers
+    used: Mutex<Vec<bool>>,
+}
+
+impl Cassette {
+    /// Opens a cassette. Record mode starts empty and overwrites the file on the first exchange;
+    /// replay mode loads the recorded interactions.
+    pub fn open(config: CassetteConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let interactions = match config.mode {
+            CassetteMode::Record => Vec::new(),
+            CassetteMode::Replay => {
+                let contents = std::fs::read_to_string(&config.path).map_err(|e| {
+                    format!("Failed to read cassette {}: {}", config.path.display(), e)
+                })?;
+                let file: CassetteFile = serde_json::from_str(&contents).map_err(|e| {
+                    format!("Failed to parse cassette {}: {}", config.path.display(), e)
+                })?;
+                file.interactions
+            }
+        };
+        log::info!(
+            "HTTP cassette {} opened in {:?} mode with {} interactions",
+            config.path.display(),
+            config.mode,
+            interactions.len()
+        );
+
+        let used = vec![false; interactions.len()];
+        Ok(Self {
+            config,
+            interactions: Mutex::new(interactions),
+            used: Mutex::new(used),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.config.mode
+    }
+
+    /// Looks up the response recorded for a request of the same agent. Returns `None` when the
+    /// request should go to the network, which only happens outside strict mode.
+    pub fn replay(
+        &self,
+        agent_id: &str,
+        method: &str,
+        url: &str,
+        body: Option<&[u8]>,
+    ) -> Option<Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+        let request = recorded_request(method, url, body);
+        let interactions = self.interactions.lock().unwrap();
+        let mut used = self.used.lock().unwrap();
+
+        let matching: Vec<usize> = interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| {
+                interaction.agent_id == agent_id && self.matches(&interaction.request, &request)
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        // Prefer the first unused match, then fall back to the last one for repeated requests
+        let index = matching
+            .iter()
+            .copied()
+            .find(|index| !used[*index])
+            .or_else(|| matching.last().copied());
+
+        match index {
+            Some(index) => {
+                used[index] = true;
+                let response = &interactions[index].response;
+                log::debug!(
+                    "Replaying recorded response for {} {} of agent {}",
+                    method,
+                    url,
+                    agent_id
+                );
+                Some(match (&response.body, &response.error) {
+                    (Some(body), _) => Ok(body.clone()),
+                    (None, Some(error)) => Err(error.clone().into()),
+                    (None, None) => Ok(String::new()),
+                })
+            }
+            None if self.config.strict => Some(Err(Box::new(UnmatchedRequest {
+                method: request.method,
+                url: request.url,
+            }))),
+            None => {
+                log::warn!(
+                    "No recorded interaction for {} {} of agent {}, sending request to the network",
+                    method,
+                    url,
+                    agent_id
+                );
+                None
+            }
+        }
+    }
+
+    /// Appends an exchange and rewrites the cassette file.
+    pub fn record(
+        &self,
+        agent_id: &str,
+        method: &str,
+        url: &str,
+        body: Option<&[u8]>,
+        result: &Result<String, Box<dyn std::error::Error + Send + Sync>>,
+    ) {
+        let response = match result {
+            Ok(body) => RecordedResponse {
+                body: Some(body.clone()),
+                error: None,
+            },
+            Err(e) => RecordedResponse {
+                body: None,
+                error: Some(e.to_string()),
+            },
+        };
+
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.push(Interaction {
+            agent_id: agent_id.to_string(),
+            request: recorded_request(method, url, body),
+            response,
+        });
+
+        let file = CassetteFile {
+            interactions: interactions.clone(),
+        };
+        match serde_json::to_string_pretty(&file) {
+            Ok(contents) => {
+                if let Err(e) = std::fs::write(&self.config.path, contents) {
+                    log::error!(
+                        "Failed to write cassette {}: {}",
+                        self.config.path.display(),
+                        e
+                    );
+                }
+            }
+            Err(e) => log::error!("Failed to serialize cassette: {}", e),
+        }
+    }
+
+    fn matches(&self, recorded: &RecordedRequest, request: &RecordedRequest) -> bool {
+        self.config.match_on.iter().all(|rule| match rule {
+            MatchRule::Method => recorded.method.eq_ignore_ascii_case(&request.method),
+            MatchRule::Url => recorded.url == request.url,
+            MatchRule::BodyHash => recorded.body_sha256 == request.body_sha256,
+        })
+    }
+}
+
+fn recorded_request(method: &str, url: &str, body: Option<&[u8]>) -> RecordedRequest {
+    RecordedRequest {
+        method: method.to_uppercase(),
+        url: url.to_string(),
+        body_sha256: body.map(|body_data| hex::encode(Sha256::digest(body_data))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cassette(mode: CassetteMode, path: PathBuf, strict: bool) -> Cassette {
+        Cassette::open(CassetteConfig {
+            mode,
+            path,
+            match_on: default_match_rules(),
+            strict,
+        })
+        .unwrap()
+    }
+
+    fn body(result: Option<Result<String, Box<dyn std::error::Error + Send + Sync>>>) -> String {
+        result
+            .expect("no recorded interaction")
+            .expect("recorded error")
+    }
+
+    #[test]
+    fn replays_what_each_agent_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        let url = "https://example.com/";
+
+        let recorder = cassette(CassetteMode::Record, path.clone(), false);
+        recorder.record("alpha", "get", url, None, &Ok("alpha 1".to_string()));
+        recorder.record("alpha", "GET", url, None, &Ok("alpha 2".to_string()));
+        recorder.record("beta", "GET", url, None, &Err("refused".into()));
+
+        let player = cassette(CassetteMode::Replay, path, false);
+        assert_eq!(body(player.replay("alpha", "GET", url, None)), "alpha 1");
+        assert_eq!(body(player.replay("alpha", "GET", url, None)), "alpha 2");
+        // Repeated requests past the recording get the last answer
+        assert_eq!(body(player.replay("alpha", "GET", url, None)), "alpha 2");
+        let error = player
+            .replay("beta", "GET", url, None)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "refused");
+        // Other agents and requests go to the network outside strict mode
+        assert!(player.replay("gamma", "GET", url, None).is_none());
+        assert!(player.replay("alpha", "POST", url, None).is_none());
+    }
+
+    #[test]
+    fn strict_mode_fails_requests_without_a_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        let url = "https://example.com/";
+        cassette(CassetteMode::Record, path.clone(), false).record(
+            "alpha",
+            "GET",
+            url,
+            None,
+            &Ok("alpha".to_string()),
+        );
+
+        let player = cassette(CassetteMode::Replay, path, true);
+        assert_eq!(body(player.replay("alpha", "GET", url, None)), "alpha");
+        for (agent_id, method) in [("beta", "GET"), ("alpha", "DELETE")] {
+            let error = player
+                .replay(agent_id, method, url, None)
+                .expect("strict mode never goes to the network")
+                .unwrap_err();
+            assert!(error.is::<UnmatchedRequest>(), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Longest key a guest may use, in bytes.
+const MAX_KEY_BYTES: usize = 256;
+
+/// Storage limits for one agent namespace.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KvQuota {
+    pub max_keys: usize,
+    pub max_value_bytes: usize,
+    /// Sum of key and value sizes across the namespace.
+    pub max_total_bytes: usize,
+}
+
+impl Default for KvQuota {
+    fn default() -> Self {
+        Self {
+            max_keys: 1000,
+            max_value_bytes: 256 * 1024,
+            max_total_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum KvError {
+    InvalidKey(String),
+    QuotaExceeded(String),
+    Storage(String),
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::InvalidKey(e) => write!(f, "Invalid key: {}", e),
+            KvError::QuotaExceeded(e) => write!(f, "Quota exceeded: {}", e),
+            KvError::Storage(e) => write!(f, "Storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KvError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KvEntry {
+    value: String,
+    /// Unix timestamp in seconds after which the entry is gone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+}
+
+impl KvEntry {
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+type Namespace = BTreeMap<String, KvEntry>;
+
+/// Key-value store with one JSON file per agent namespace. Namespaces are loaded on first use
+/// and written back atomically after every change.
+pub struct KvStore {
+    dir: PathBuf,
+    namespaces: Mutex<HashMap<String, Namespace>>,
+}
+
+impl KvStore {
+    pub fn open(dir: PathBuf) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            format!(
+                "Failed to create KV store directory {}: {}",
+                dir.display(),
+                e
+            )
+        })?;
+        log::debug!("KV store opened at {}", dir.display());
+        Ok(Self {
+            dir,
+            namespaces: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, KvError> {
+        validate_key(key)?;
+        self.with_namespace(namespace, |entries| {
+            let now = Utc::now().timestamp();
+            Ok(entries
+                .get(key)
+                .filter(|entry| !entry.is_expired(now))
+                .map(|entry| entry.value.clone()))
+        })
+    }
+
+    pub fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: String,
+        ttl_seconds: Option<u64>,
+        quota: &KvQuota,
+    ) -> Result<(), KvError> {
+        validate_key(key)?;
+        if value.len() > quota.max_value_bytes {
+            return Err(KvError::QuotaExceeded(format!(
+                "value is {} bytes, limit is {}",
+                value.len(),
+                quota.max_value_bytes
+            )));
+        }
+
+        self.with_namespace(namespace, |entries| {
+            let now = Utc::now().timestamp();
+            // Changed on a copy, so a failed write leaves the namespace as it is on disk
+            let mut updated = entries.clone();
+            updated.retain(|_, entry| !entry.is_expired(now));
+
+            if !updated.contains_key(key) && updated.len() >= quota.max_keys {
+                return Err(KvError::QuotaExceeded(format!(
+                    "namespace already holds {} keys",
+                    quota.max_keys
+                )));
+            }
+            let used: usize = updated
+                .iter()
+                .filter(|(existing, _)| existing.as_str() != key)
+                .map(|(existing, entry)| existing.len() + entry.value.len())
+                .sum();
+            if used + key.len() + value.len() > quota.max_total_bytes {
+                return Err(KvError::QuotaExceeded(format!(
+                    "namespace would exceed {} bytes",
+                    quota.max_total_bytes
+                )));
+            }
+
+            // TTLs beyond what a timestamp holds never expire
+            let expires_at =
+                ttl_seconds.map(|ttl| now.saturating_add(i64::try_from(ttl).unwrap_or(i64::MAX)));
+            updated.insert(key.to_string(), KvEntry { value, expires_at });
+            self.persist(namespace, &updated)?;
+            *entries = updated;
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self, namespace: &str, key: &str) -> Result<bool, KvError> {
+        validate_key(key)?;
+        self.with_namespace(namespace, |entries| {
+            let now = Utc::now().timestamp();
+            let mut updated = entries.clone();
+            let removed = updated
+                .remove(key)
+                .is_some_and(|entry| !entry.is_expired(now));
+            self.persist(namespace, &updated)?;
+            *entries = updated;
+            Ok(removed)
+        })
+    }
+
+    pub fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<String>, KvError> {
+        self.with_namespace(namespace, |entries| {
+            let now = Utc::now().timestamp();
+            Ok(entries
+                .iter()
+                .filter(|(key, entry)| key.starts_with(prefix) && !entry.is_expired(now))
+                .map(|(key, _)| key.clone())
+                .collect())
+        })
+    }
+
+    fn with_namespace<T>(
+        &self,
+        namespace: &str,
+        f: impl FnOnce(&mut Namespace) -> Result<T, KvError>,
+    ) -> Result<T, KvError> {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        if !namespaces.contains_key(namespace) {
+            let loaded = self.load(namespace)?;
+            namespaces.insert(namespace.to_string(), loaded);
+        }
+        f(namespaces.get_mut(namespace).unwrap())
+    }
+
+    /// Bytes other than `[A-Za-z0-9_-]` are percent-encoded, so every namespace gets its own file.
+    fn namespace_path(&self, namespace: &str) -> PathBuf {
+        let mut file_name = String::with_capacity(namespace.len());
+        for byte in namespace.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                file_name.push(byte as char);
+            } else {
+                file_name.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        self.dir.join(format!("{}.json", file_name))
+    }
+
+    fn load(&self, namespace: &str) -> Result<Namespace, KvError> {
+        let path = self.namespace_path(namespace);
+        if !path.exists() {
+            return Ok(Namespace::new());
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| KvError::Storage(format!("failed to read {}: {}", path.display(), e)))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| KvError::Storage(format!("failed to parse {}: {}", path.display(), e)))
+    }
+
+    fn persist(&self, namespace: &str, entries: &Namespace) -> Result<(), KvError> {
+        let path = self.namespace_path(namespace);
+        let temp_path = path.with_extension("json.tmp");
+        let contents = serde_json::to_string(entries)
+            .map_err(|e| KvError::Storage(format!("failed to serialize namespace: {}", e)))?;
+        std::fs::write(&temp_path, contents)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| KvError::Storage(format!("failed to write {}: {}", path.display(), e)))
+    }
+}
+
+fn validate_key(key: &str) -> Result<(), KvError> {
+    if key.is_empty() {
+        return Err(KvError::InvalidKey("key is empty".to_string()));
+    }
+    if key.len() > MAX_KEY_BYTES {
+        return Err(KvError::InvalidKey(format!(
+            "key is longer than {} bytes",
+            MAX_KEY_BYTES
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_get_distinct_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::open(dir.path().to_path_buf()).unwrap();
+        assert_eq!(
+            store.namespace_path("top_hn-links2"),
+            dir.path().join("top_hn-links2.json")
+        );
+        assert_eq!(
+            store.namespace_path("../a.b"),
+            dir.path().join("%2E%2E%2Fa%2Eb.json")
+        );
+
+        let quota = KvQuota::default();
+        let namespaces = ["a.b", "a_b", "a/b", "a%2Eb", "a\u{e9}"];
+        for namespace in namespaces {
+            store
+                .put(namespace, "key", namespace.to_string(), None, &quota)
+                .unwrap();
+        }
+        let reopened = KvStore::open(dir.path().to_path_buf()).unwrap();
+        for namespace in namespaces {
+            assert_eq!(
+                reopened.get(namespace, "key").unwrap().as_deref(),
+                Some(namespace)
+            );
+        }
+    }
+
+    #[test]
+    fn put_enforces_the_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::open(dir.path().to_path_buf()).unwrap();
+        let quota = KvQuota {
+            max_keys: 2,
+            max_value_bytes: 8,
+            max_total_bytes: 12,
+        };
+
+        assert!(matches!(
+            store.put("ns", "a", "123456789".to_string(), None, &quota),
+            Err(KvError::QuotaExceeded(_))
+        ));
+        store
+            .put("ns", "a", "12345".to_string(), None, &quota)
+            .unwrap();
+        assert!(matches!(
+            store.put("ns", "b", "1234567".to_string(), None, &quota),
+            Err(KvError::QuotaExceeded(_))
+        ));
+        store.put("ns", "b", "1".to_string(), None, &quota).unwrap();
+        assert!(matches!(
+            store.put("ns", "c", "1".to_string(), None, &quota),
+            Err(KvError::QuotaExceeded(_))
+        ));
+        // Replacing a key does not count it twice
+        store
+            .put("ns", "a", "1234567".to_string(), None, &quota)
+            .unwrap();
+        assert!(matches!(
+            store.put("ns", "", "1".to_string(), None, &quota),
+            Err(KvError::InvalidKey(_))
+        ));
+        assert_eq!(store.list("ns", "").unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::open(dir.path().to_path_buf()).unwrap();
+        let quota = KvQuota::default();
+
+        store
+            .put("ns", "gone", "1".to_string(), Some(0), &quota)
+            .unwrap();
+        store
+            .put("ns", "kept", "2".to_string(), Some(3600), &quota)
+            .unwrap();
+        store
+            .put("ns", "forever", "3".to_string(), Some(u64::MAX), &quota)
+            .unwrap();
+        assert_eq!(store.get("ns", "gone").unwrap(), None);
+        assert_eq!(store.get("ns", "kept").unwrap().as_deref(), Some("2"));
+        assert_eq!(store.get("ns", "forever").unwrap().as_deref(), Some("3"));
+        assert_eq!(store.list("ns", "").unwrap(), vec!["forever", "kept"]);
+        assert!(!store.delete("ns", "gone").unwrap());
+    }
+
+    #[test]
+    fn failed_writes_leave_the_namespace_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::open(dir.path().to_path_buf()).unwrap();
+        let quota = KvQuota::default();
+        store
+            .put("ns", "key", "old".to_string(), None, &quota)
+            .unwrap();
+
+        // The temporary file cannot be written where a directory is
+        std::fs::create_dir(dir.path().join("ns.json.tmp")).unwrap();
+        assert!(matches!(
+            store.put("ns", "key", "new".to_string(), None, &quota),
+            Err(KvError::Storage(_))
+        ));
+        assert!(matches!(
+            store.put("ns", "other", "new".to_string(), None, &quota),
+            Err(KvError::Storage(_))
+        ));
+        assert!(matches!(
+            store.delete("ns", "key"),
+            Err(KvError::Storage(_))
+        ));
+        assert_eq!(store.get("ns", "key").unwrap().as_deref(), Some("old"));
+        assert_eq!(store.list("ns", "").unwrap(), vec!["key"]);
+    }
+}
@@ -1,2 +1,6 @@
+pub mod cassette;
+pub mod kv_store;
 pub mod network_functions;
+pub mod network_policy;
+pub mod secrets;
 pub mod vm_functions;
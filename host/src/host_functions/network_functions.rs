@@ -1,17 +1,128 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use reqwest::Method;
-use reqwest::Client;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use reqwest::{Client, ClientBuilder};
+
+use super::cassette::{Cassette, CassetteMode};
+use super::network_policy::{self, host_name, EgressError, NetworkPolicy};
+use super::secrets::AgentSecrets;
+
+/// Redirects are followed by hand so every hop is checked against the policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// Settings of the client agent requests are sent with. Redirects are followed in
+/// `http_request` so each hop goes through the network policy.
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+}
+
+/// Network settings for requests made on behalf of one agent.
+pub struct AgentNetwork {
+    pub agent_id: String,
+    pub policy: NetworkPolicy,
+    pub cassette: Option<Arc<Cassette>>,
+    pub secrets: AgentSecrets,
+}
+
+/// Sends a request for an agent, serving it from or recording it to the HTTP cassette if one is
+/// configured. Requests rejected by the network policy are recorded as violations of the agent,
+/// never in the cassette.
+///
+/// Secret references in header values are substituted here, and secret values are redacted from
+/// the response and errors before they are recorded or handed back to the guest.
+pub async fn agent_http_request(
+    client: Arc<Client>,
+    network: &AgentNetwork,
+    url: &str,
+    method: &str,
+    body: Option<&[u8]>,
+    headers: &[(String, String)],
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(cassette) = &network.cassette {
+        if cassette.mode() == CassetteMode::Replay {
+            if let Some(result) = cassette.replay(&network.agent_id, method, url, body) {
+                return result;
+            }
+        }
+    }
 
+    // Secrets are bound to hosts; a URL without one fails the policy check before sending
+    let target_host = url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host().map(|host| host_name(&host)))
+        .unwrap_or_default();
+    let resolved_headers = headers
+        .iter()
+        .map(|(name, value)| {
+            network
+                .secrets
+                .substitute(value, &target_host)
+                .map(|value| (name.clone(), value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let header_pairs: Vec<(&str, &str)> = resolved_headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let secret_headers: Vec<&str> = headers
+        .iter()
+        .zip(&resolved_headers)
+        .filter(|((_, value), (_, resolved))| value != resolved)
+        .map(|((name, _), _)| name.as_str())
+        .collect();
+
+    let result = http_request(
+        client,
+        url,
+        method,
+        body,
+        Some(&header_pairs),
+        &secret_headers,
+        &network.policy,
+    )
+    .await
+    .map(|response| network.secrets.redact(&response))
+    .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
+        if e.is::<EgressError>() || network.secrets.is_empty() {
+            e
+        } else {
+            network.secrets.redact(&e.to_string()).into()
+        }
+    });
+
+    let violation = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<EgressError>());
+    if let Some(error) = violation {
+        network_policy::record_violation(&network.agent_id, url, error);
+    }
+
+    if let Some(cassette) = &network.cassette {
+        if cassette.mode() == CassetteMode::Record && violation.is_none() {
+            cassette.record(&network.agent_id, method, url, body, &result);
+        }
+    }
+
+    result
+}
+
+/// Sends a request, following redirects that the policy allows. Headers named in
+/// `secret_headers`, and credentials, are only sent to the origin of `url`.
 pub async fn http_request(
     client: Arc<Client>,
     url: &str,
     method: &str,
     body: Option<&[u8]>,
     headers: Option<&[(&str, &str)]>,
+    secret_headers: &[&str],
+    policy: &NetworkPolicy,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let method = match method.to_uppercase().as_str() {
+    let mut method = match method.to_uppercase().as_str() {
         "GET" => Method::GET,
         "POST" => Method::POST,
         "PUT" => Method::PUT,
@@ -22,12 +133,6 @@ pub async fn http_request(
         _ => return Err("Invalid HTTP method".into()),
     };
 
-    let mut request_builder = client.request(method, url);
-
-    if let Some(body_data) = body {
-        request_builder = request_builder.body(body_data.to_vec());
-    }
-
     // Build headers, add a fallback User-Agent if not provided
     let mut header_map = HeaderMap::new();
     let mut user_agent_set = false;
@@ -51,10 +156,215 @@ pub async fn http_request(
         );
     }
 
-    request_builder = request_builder.headers(header_map);
+    let mut body = body.map(|body_data| body_data.to_vec());
+    let mut current = policy.check_url(url).await?;
+    let mut redirects = 0;
+
+    loop {
+        // Connect to the addresses the policy vetted instead of resolving the host again
+        let pinned_client;
+        let hop_client = match current.url.host_str() {
+            Some(domain) if !current.addresses.is_empty() => {
+                pinned_client = client_builder()
+                    .resolve_to_addrs(domain, &current.addresses)
+                    .build()?;
+                &pinned_client
+            }
+            _ => client.as_ref(),
+        };
+        let mut request_builder = hop_client.request(method.clone(), current.url.clone());
+
+        if let Some(body_data) = &body {
+            request_builder = request_builder.body(body_data.clone());
+        }
+
+        request_builder = request_builder.headers(header_map.clone());
+
+        let response = request_builder.send().await?;
+
+        if response.status().is_redirection() {
+            if let Some(location) = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+            {
+                if redirects >= MAX_REDIRECTS {
+                    return Err(format!("Too many redirects (max {})", MAX_REDIRECTS).into());
+                }
+                let next_url = current.url.join(location)?;
+                let next = policy.check_url(next_url.as_str()).await?;
+                if next.url.origin() != current.url.origin() {
+                    strip_credentials(&mut header_map, secret_headers);
+                }
+                current = next;
+                redirects += 1;
+
+                // 301, 302 and 303 turn into a body-less GET, 307 and 308 replay the request
+                if response.status().as_u16() <= 303 {
+                    method = Method::GET;
+                    body = None;
+                }
+                continue;
+            }
+        }
+
+        return read_body_limited(response, policy.max_response_bytes).await;
+    }
+}
+
+/// Removes the headers that must not follow a redirect to another scheme, host or port.
+fn strip_credentials(header_map: &mut HeaderMap, secret_headers: &[&str]) {
+    header_map.remove(reqwest::header::AUTHORIZATION);
+    header_map.remove(reqwest::header::PROXY_AUTHORIZATION);
+    header_map.remove(reqwest::header::COOKIE);
+    for name in secret_headers {
+        header_map.remove(*name);
+    }
+}
+
+/// Reads the response body, failing as soon as it grows past `limit` bytes.
+async fn read_body_limited(
+    mut response: reqwest::Response,
+    limit: usize,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(length) = response.content_length() {
+        if length > limit as u64 {
+            return Err(Box::new(EgressError::ResponseTooLarge { limit }));
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(Box::new(EgressError::ResponseTooLarge { limit }));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_functions::secrets::{SecretConfig, SecretSource, SecretStore};
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
 
-    let response = request_builder.send().await?;
+    /// Answers one request with `response` and returns the request head it received.
+    async fn serve_once(listener: TcpListener, response: String) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut head = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await.unwrap();
+            assert!(read > 0, "connection closed before the request head");
+            head.extend_from_slice(&buf[..read]);
+        }
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8_lossy(&head).to_ascii_lowercase()
+    }
+
+    #[tokio::test]
+    async fn cross_origin_redirect_drops_secrets_and_credentials() {
+        std::env::set_var("NETWORK_FUNCTIONS_TEST_TOKEN", "s3cr3t-token");
+        let secrets = AgentSecrets::new(
+            HashMap::from([(
+                "TOKEN".to_string(),
+                SecretConfig {
+                    source: SecretSource::Env("NETWORK_FUNCTIONS_TEST_TOKEN".to_string()),
+                    allowed_hosts: vec!["127.0.0.1".to_string()],
+                },
+            )]),
+            Arc::new(SecretStore::default()),
+        );
+        let network = AgentNetwork {
+            agent_id: "agent".to_string(),
+            policy: NetworkPolicy {
+                allowed_ports: vec![],
+                block_private_ranges: false,
+                ..NetworkPolicy::default()
+            },
+            cassette: None,
+            secrets,
+        };
 
-    let body_text = response.text().await?;
-    Ok(body_text)
+        let origin = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let other = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let other_port = other.local_addr().unwrap().port();
+        let url = format!(
+            "http://127.0.0.1:{}/start",
+            origin.local_addr().unwrap().port()
+        );
+        let origin_request = tokio::spawn(serve_once(
+            origin,
+            format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/next\r\nContent-Length: 0\r\n\r\n",
+                other_port
+            ),
+        ));
+        let other_request = tokio::spawn(serve_once(
+            other,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_string(),
+        ));
+
+        let headers = vec![
+            ("X-Api-Key".to_string(), "{{secret:TOKEN}}".to_string()),
+            ("Authorization".to_string(), "Bearer plain".to_string()),
+            ("Cookie".to_string(), "session=1".to_string()),
+            ("X-Trace".to_string(), "kept".to_string()),
+        ];
+        let response = agent_http_request(
+            Arc::new(client_builder().build().unwrap()),
+            &network,
+            &url,
+            "GET",
+            None,
+            &headers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response, "ok");
+
+        let origin_request = origin_request.await.unwrap();
+        assert!(origin_request.contains("x-api-key: s3cr3t-token"));
+        assert!(origin_request.contains("authorization: bearer plain"));
+
+        let other_request = other_request.await.unwrap();
+        assert!(!other_request.contains("s3cr3t-token"));
+        assert!(!other_request.contains("x-api-key"));
+        assert!(!other_request.contains("authorization"));
+        assert!(!other_request.contains("cookie"));
+        assert!(other_request.contains("x-trace: kept"));
+    }
+
+    #[tokio::test]
+    async fn denied_requests_are_recorded_as_violations() {
+        let network = AgentNetwork {
+            agent_id: "denied-agent".to_string(),
+            policy: NetworkPolicy::default(),
+            cassette: None,
+            secrets: AgentSecrets::new(HashMap::new(), Arc::new(SecretStore::default())),
+        };
+        let error = agent_http_request(
+            Arc::new(client_builder().build().unwrap()),
+            &network,
+            "http://127.0.0.1/admin",
+            "GET",
+            None,
+            &[],
+        )
+        .await
+        .unwrap_err();
+        assert!(error.is::<EgressError>());
+
+        let violations = network_policy::egress_violations("denied-agent");
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].url, "http://127.0.0.1/admin");
+        assert!(matches!(
+            violations[0].error,
+            EgressError::BlockedAddress { .. }
+        ));
+    }
 }
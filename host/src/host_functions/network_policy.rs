@@ -0,0 +1,385 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use url::{Host, Url};
+
+/// Most recent egress violations kept in memory per agent.
+const MAX_VIOLATIONS_PER_AGENT: usize = 100;
+
+lazy_static::lazy_static! {
+    static ref EGRESS_VIOLATIONS: Mutex<HashMap<String, VecDeque<EgressViolation>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Outbound network policy applied to every HTTP request an agent makes through the host.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NetworkPolicy {
+    /// URL schemes the agent may use, e.g. `["https"]`.
+    pub allowed_schemes: Vec<String>,
+    /// Host name globs, e.g. `["news.ycombinator.com", "*.github.com"]`. `*` matches any host.
+    pub allowed_hosts: Vec<String>,
+    /// Destination ports. An empty list allows any port.
+    pub allowed_ports: Vec<u16>,
+    /// Reject hosts that resolve to loopback, private, link-local or otherwise non-public addresses.
+    pub block_private_ranges: bool,
+    /// Maximum size of a response body in bytes.
+    pub max_response_bytes: usize,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allowed_hosts: vec!["*".to_string()],
+            allowed_ports: vec![80, 443],
+            block_private_ranges: true,
+            max_response_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Reason a request was rejected by a `NetworkPolicy`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "detail")]
+pub enum EgressError {
+    InvalidUrl(String),
+    SchemeNotAllowed(String),
+    HostNotAllowed(String),
+    PortNotAllowed(u16),
+    ResolutionFailed(String),
+    BlockedAddress { host: String, address: IpAddr },
+    ResponseTooLarge { limit: usize },
+}
+
+impl fmt::Display for EgressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EgressError::InvalidUrl(e) => write!(f, "Invalid URL: {}", e),
+            EgressError::SchemeNotAllowed(scheme) => {
+                write!(f, "Scheme '{}' is not allowed", scheme)
+            }
+            EgressError::HostNotAllowed(host) => write!(f, "Host '{}' is not allowed", host),
+            EgressError::PortNotAllowed(port) => write!(f, "Port {} is not allowed", port),
+            EgressError::ResolutionFailed(e) => write!(f, "Failed to resolve host: {}", e),
+            EgressError::BlockedAddress { host, address } => {
+                write!(f, "Host '{}' resolves to blocked address {}", host, address)
+            }
+            EgressError::ResponseTooLarge { limit } => {
+                write!(f, "Response exceeds the maximum size of {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EgressError {}
+
+/// A rejected request, kept for auditing.
+#[derive(Debug, Clone, Serialize)]
+pub struct EgressViolation {
+    pub url: String,
+    pub error: EgressError,
+    pub timestamp: String,
+}
+
+/// A URL that passed a `NetworkPolicy`.
+#[derive(Debug, Clone)]
+pub struct AllowedUrl {
+    pub url: Url,
+    /// Addresses the host resolved to when it was checked against the blocked ranges. The
+    /// request has to connect to these rather than resolve the name again, which could give a
+    /// different answer. Empty when nothing was resolved.
+    pub addresses: Vec<SocketAddr>,
+}
+
+impl NetworkPolicy {
+    /// Validates a URL against the policy, resolving the host when private ranges are blocked.
+    pub async fn check_url(&self, url: &str) -> Result<AllowedUrl, EgressError> {
+        let parsed = Url::parse(url).map_err(|e| EgressError::InvalidUrl(e.to_string()))?;
+
+        let scheme = parsed.scheme().to_ascii_lowercase();
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&scheme))
+        {
+            return Err(EgressError::SchemeNotAllowed(scheme));
+        }
+
+        let host = parsed
+            .host()
+            .ok_or_else(|| EgressError::InvalidUrl(format!("URL has no host: {}", url)))?;
+        let host_name = host_name(&host);
+        if !host_allowed(&self.allowed_hosts, &host_name) {
+            return Err(EgressError::HostNotAllowed(host_name));
+        }
+
+        let port = parsed
+            .port_or_known_default()
+            .ok_or_else(|| EgressError::InvalidUrl(format!("URL has no port: {}", url)))?;
+        if !self.allowed_ports.is_empty() && !self.allowed_ports.contains(&port) {
+            return Err(EgressError::PortNotAllowed(port));
+        }
+
+        let mut addresses = Vec::new();
+        if self.block_private_ranges {
+            let ips: Vec<IpAddr> = match host {
+                Host::Ipv4(ip) => vec![IpAddr::V4(ip)],
+                Host::Ipv6(ip) => vec![IpAddr::V6(ip)],
+                Host::Domain(domain) => {
+                    addresses = tokio::net::lookup_host((domain, port))
+                        .await
+                        .map_err(|e| EgressError::ResolutionFailed(e.to_string()))?
+                        .collect();
+                    addresses.iter().map(SocketAddr::ip).collect()
+                }
+            };
+            if let Some(address) = ips.into_iter().find(is_blocked_address) {
+                return Err(EgressError::BlockedAddress {
+                    host: host_name,
+                    address,
+                });
+            }
+        }
+
+        Ok(AllowedUrl {
+            url: parsed,
+            addresses,
+        })
+    }
+}
+
+/// Records a rejected request in the host log and among the agent's recent violations.
+pub fn record_violation(agent_id: &str, url: &str, error: &EgressError) {
+    log::warn!(
+        "Egress violation by agent {} for {}: {}",
+        agent_id,
+        url,
+        error
+    );
+    let mut violations = EGRESS_VIOLATIONS.lock().unwrap();
+    let agent_violations = violations.entry(agent_id.to_string()).or_default();
+    if agent_violations.len() >= MAX_VIOLATIONS_PER_AGENT {
+        agent_violations.pop_front();
+    }
+    agent_violations.push_back(EgressViolation {
+        url: url.to_string(),
+        error: error.clone(),
+        timestamp: Utc::now().to_rfc3339(),
+    });
+}
+
+/// The most recent violations of an agent, oldest first.
+pub fn egress_violations(agent_id: &str) -> Vec<EgressViolation> {
+    EGRESS_VIOLATIONS
+        .lock()
+        .unwrap()
+        .get(agent_id)
+        .map(|violations| violations.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// The name host globs are matched against: a lowercase domain or an IP address without brackets.
+pub(crate) fn host_name(host: &Host<&str>) -> String {
+    match host {
+        Host::Domain(domain) => domain.to_ascii_lowercase(),
+        Host::Ipv4(ip) => ip.to_string(),
+        Host::Ipv6(ip) => ip.to_string(),
+    }
+}
+
+/// Whether `host_name`, lowercase, matches one of the host name globs in `patterns`.
+pub(crate) fn host_allowed(patterns: &[String], host_name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| glob_match(&pattern.to_ascii_lowercase(), host_name))
+}
+
+/// Matches `text` against a pattern where `*` stands for any sequence of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn is_blocked_address(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(ip) => is_blocked_ipv4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_blocked_ipv4(&mapped);
+            }
+            is_blocked_ipv6(ip)
+        }
+    }
+}
+
+fn is_blocked_ipv4(ip: &Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        // "This network", 0.0.0.0/8
+        || octets[0] == 0
+}
+
+fn is_blocked_ipv6(ip: &Ipv6Addr) -> bool {
+    let first_segment = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first_segment & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first_segment & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_wildcard_subdomains() {
+        assert!(glob_match("*.example.com", "api.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(!glob_match("*.example.com", "evil-example.com"));
+        assert!(glob_match("*", "anything.test"));
+    }
+
+    #[test]
+    fn blocks_non_public_addresses() {
+        for blocked in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.254",
+            "0.1.2.3",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            let address: IpAddr = blocked.parse().unwrap();
+            assert!(
+                is_blocked_address(&address),
+                "{} should be blocked",
+                blocked
+            );
+        }
+        for allowed in [
+            "93.184.216.34",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            let address: IpAddr = allowed.parse().unwrap();
+            assert!(
+                !is_blocked_address(&address),
+                "{} should be allowed",
+                allowed
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn check_url_rejects_schemes_ports_and_private_hosts() {
+        let policy = NetworkPolicy::default();
+
+        assert!(matches!(
+            policy.check_url("ftp://example.com/file").await,
+            Err(EgressError::SchemeNotAllowed(scheme)) if scheme == "ftp"
+        ));
+        assert!(matches!(
+            policy.check_url("http://93.184.216.34:8080/").await,
+            Err(EgressError::PortNotAllowed(8080))
+        ));
+        assert!(matches!(
+            policy
+                .check_url("http://169.254.169.254/latest/meta-data")
+                .await,
+            Err(EgressError::BlockedAddress { .. })
+        ));
+        assert!(matches!(
+            policy.check_url("http://[::ffff:7f00:1]/").await,
+            Err(EgressError::BlockedAddress { .. })
+        ));
+
+        let allowed = policy.check_url("https://93.184.216.34/").await.unwrap();
+        assert_eq!(allowed.url.as_str(), "https://93.184.216.34/");
+        assert!(allowed.addresses.is_empty());
+    }
+
+    #[tokio::test]
+    async fn check_url_checks_domains_by_name_and_resolved_address() {
+        let policy = NetworkPolicy {
+            allowed_ports: vec![],
+            block_private_ranges: true,
+            ..NetworkPolicy::default()
+        };
+        assert!(matches!(
+            policy.check_url("http://localhost:3000/").await,
+            Err(EgressError::BlockedAddress { .. })
+        ));
+
+        let policy = NetworkPolicy {
+            allowed_hosts: vec!["*.example.com".to_string()],
+            ..NetworkPolicy::default()
+        };
+        assert!(matches!(
+            policy.check_url("https://evil-example.com/").await,
+            Err(EgressError::HostNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn violations_are_kept_per_agent_and_bounded() {
+        let error = EgressError::PortNotAllowed(22);
+        for i in 0..MAX_VIOLATIONS_PER_AGENT + 5 {
+            record_violation(
+                "bounded-agent",
+                &format!("http://example.com:22/{}", i),
+                &error,
+            );
+        }
+        record_violation("other-agent", "http://example.com:22/", &error);
+
+        let violations = egress_violations("bounded-agent");
+        assert_eq!(violations.len(), MAX_VIOLATIONS_PER_AGENT);
+        assert_eq!(violations[0].url, "http://example.com:22/5");
+        assert_eq!(
+            violations.last().unwrap().url,
+            format!("http://example.com:22/{}", MAX_VIOLATIONS_PER_AGENT + 4)
+        );
+        assert_eq!(egress_violations("other-agent").len(), 1);
+        assert!(egress_violations("unknown-agent").is_empty());
+    }
+}
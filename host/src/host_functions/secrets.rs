@@ -0,0 +1,362 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::network_policy::host_allowed;
+
+/// Environment variable holding the hex-encoded 32-byte key for the encrypted secrets file.
+pub const SECRETS_KEY_ENV: &str = "HYPERLIGHT_SECRETS_KEY";
+
+/// Marker guests put in header values to reference a secret: `{{secret:NAME}}`.
+const SECRET_REF_PREFIX: &str = "{{secret:";
+const SECRET_REF_SUFFIX: &str = "}}";
+
+/// Replaces secret values in anything that leaves the host towards a guest or a log.
+const REDACTED: &str = "[REDACTED]";
+
+const NONCE_LEN: usize = 12;
+
+/// Where the value of a named secret comes from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    /// Read from a host environment variable at request time.
+    Env(String),
+    /// Read from the encrypted secrets file under the given name.
+    File(String),
+}
+
+/// A secret an agent may reference: where its value comes from and where it may be sent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecretConfig {
+    #[serde(flatten)]
+    pub source: SecretSource,
+    /// Host name globs, as in the network policy, of the hosts requests referencing the secret
+    /// may go to. Required, so a secret is never sent to whatever host the policy allows.
+    pub allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SecretError {
+    UnknownSecret(String),
+    Unavailable(String),
+    MalformedReference(String),
+    HostNotAllowed { name: String, host: String },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::UnknownSecret(name) => {
+                write!(f, "Secret '{}' is not configured for this agent", name)
+            }
+            SecretError::Unavailable(name) => write!(f, "Secret '{}' has no value", name),
+            SecretError::MalformedReference(e) => write!(f, "Malformed secret reference: {}", e),
+            SecretError::HostNotAllowed { name, host } => {
+                write!(f, "Secret '{}' may not be sent to host '{}'", name, host)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Secrets decrypted from the local secrets file, shared by all agents.
+#[derive(Default)]
+pub struct SecretStore {
+    file_secrets: HashMap<String, String>,
+}
+
+impl SecretStore {
+    /// Decrypts the secrets file. Each entry maps a name to the hex encoding of a 12-byte nonce
+    /// followed by the ChaCha20-Poly1305 ciphertext, with the name as associated data.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let key_hex = std::env::var(SECRETS_KEY_ENV).map_err(|_| {
+            format!(
+                "{} must be set to decrypt the secrets file {}",
+                SECRETS_KEY_ENV,
+                path.display()
+            )
+        })?;
+        let key = hex::decode(key_hex.trim())
+            .map_err(|e| format!("{} is not valid hex: {}", SECRETS_KEY_ENV, e))?;
+        if key.len() != 32 {
+            return Err(format!("{} must be 32 bytes, got {}", SECRETS_KEY_ENV, key.len()).into());
+        }
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read secrets file {}: {}", path.display(), e))?;
+        let encrypted: HashMap<String, String> = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse secrets file {}: {}", path.display(), e))?;
+
+        let mut file_secrets = HashMap::new();
+        for (name, sealed_hex) in encrypted {
+            let sealed = hex::decode(&sealed_hex)
+                .map_err(|e| format!("Secret '{}' is not valid hex: {}", name, e))?;
+            if sealed.len() <= NONCE_LEN {
+                return Err(format!("Secret '{}' is too short", name).into());
+            }
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            let plaintext = cipher
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: name.as_bytes(),
+                    },
+                )
+                .map_err(|_| format!("Failed to decrypt secret '{}'", name))?;
+            let value = String::from_utf8(plaintext)
+                .map_err(|_| format!("Secret '{}' is not valid UTF-8", name))?;
+            file_secrets.insert(name, value);
+        }
+
+        log::info!(
+            "Loaded {} secrets from {}",
+            file_secrets.len(),
+            path.display()
+        );
+        Ok(Self { file_secrets })
+    }
+}
+
+/// The secrets one agent may reference.
+pub struct AgentSecrets {
+    secrets: HashMap<String, SecretConfig>,
+    store: Arc<SecretStore>,
+}
+
+impl AgentSecrets {
+    pub fn new(secrets: HashMap<String, SecretConfig>, store: Arc<SecretStore>) -> Self {
+        Self { secrets, store }
+    }
+
+    pub fn resolve(&self, name: &str) -> Result<String, SecretError> {
+        let secret = self
+            .secrets
+            .get(name)
+            .ok_or_else(|| SecretError::UnknownSecret(name.to_string()))?;
+        let value = match &secret.source {
+            SecretSource::Env(variable) => std::env::var(variable).ok(),
+            SecretSource::File(file_name) => self.store.file_secrets.get(file_name).cloned(),
+        };
+        value
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| SecretError::Unavailable(name.to_string()))
+    }
+
+    /// Replaces every `{{secret:NAME}}` reference in `value` with the secret, failing if a
+    /// referenced secret may not be sent to `host_name`.
+    pub fn substitute(&self, value: &str, host_name: &str) -> Result<String, SecretError> {
+        let mut result = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find(SECRET_REF_PREFIX) {
+            result.push_str(&rest[..start]);
+            let after_prefix = &rest[start + SECRET_REF_PREFIX.len()..];
+            let end = after_prefix.find(SECRET_REF_SUFFIX).ok_or_else(|| {
+                SecretError::MalformedReference("missing closing '}}'".to_string())
+            })?;
+            let name = after_prefix[..end].trim();
+            let secret = self
+                .secrets
+                .get(name)
+                .ok_or_else(|| SecretError::UnknownSecret(name.to_string()))?;
+            if !host_allowed(&secret.allowed_hosts, host_name) {
+                return Err(SecretError::HostNotAllowed {
+                    name: name.to_string(),
+                    host: host_name.to_string(),
+                });
+            }
+            result.push_str(&self.resolve(name)?);
+            rest = &after_prefix[end + SECRET_REF_SUFFIX.len()..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    /// Masks the values of every secret this agent can reference.
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for name in self.secrets.keys() {
+            if let Ok(value) = self.resolve(name) {
+                redacted = redacted.replace(&value, REDACTED);
+            }
+        }
+        redacted
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_secret(variable: &str, value: &str, allowed_hosts: &[&str]) -> SecretConfig {
+        std::env::set_var(variable, value);
+        SecretConfig {
+            source: SecretSource::Env(variable.to_string()),
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    fn agent_secrets() -> AgentSecrets {
+        AgentSecrets::new(
+            HashMap::from([
+                (
+                    "token".to_string(),
+                    env_secret("SECRETS_TEST_TOKEN", "t0ken", &["api.example.com"]),
+                ),
+                (
+                    "key".to_string(),
+                    env_secret("SECRETS_TEST_KEY", "k3y", &["*.example.com"]),
+                ),
+                (
+                    "empty".to_string(),
+                    env_secret("SECRETS_TEST_EMPTY", "", &["*"]),
+                ),
+            ]),
+            Arc::new(SecretStore::default()),
+        )
+    }
+
+    #[test]
+    fn substitute_replaces_references() {
+        let secrets = agent_secrets();
+        assert_eq!(
+            secrets
+                .substitute("Bearer {{secret:token}}", "api.example.com")
+                .unwrap(),
+            "Bearer t0ken"
+        );
+        assert_eq!(
+            secrets
+                .substitute("{{secret: token }}:{{secret:key}}", "api.example.com")
+                .unwrap(),
+            "t0ken:k3y"
+        );
+        assert_eq!(
+            secrets.substitute("no references", "other.test").unwrap(),
+            "no references"
+        );
+    }
+
+    #[test]
+    fn substitute_rejects_hosts_the_secret_is_not_bound_to() {
+        let secrets = agent_secrets();
+        assert!(matches!(
+            secrets.substitute("{{secret:token}}", "evil.test"),
+            Err(SecretError::HostNotAllowed { name, host }) if name == "token" && host == "evil.test"
+        ));
+        assert!(matches!(
+            secrets.substitute("{{secret:key}}", "example.com"),
+            Err(SecretError::HostNotAllowed { .. })
+        ));
+        assert!(matches!(
+            secrets.substitute("{{secret:token}}", ""),
+            Err(SecretError::HostNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn substitute_rejects_bad_references() {
+        let secrets = agent_secrets();
+        assert!(matches!(
+            secrets.substitute("{{secret:missing}}", "api.example.com"),
+            Err(SecretError::UnknownSecret(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            secrets.substitute("{{secret:empty}}", "api.example.com"),
+            Err(SecretError::Unavailable(name)) if name == "empty"
+        ));
+        assert!(matches!(
+            secrets.substitute("{{secret:token", "api.example.com"),
+            Err(SecretError::MalformedReference(_))
+        ));
+    }
+
+    #[test]
+    fn redact_masks_every_secret_value() {
+        let secrets = agent_secrets();
+        assert_eq!(
+            secrets.redact("token t0ken, key k3y, t0ken again"),
+            "token [REDACTED], key [REDACTED], [REDACTED] again"
+        );
+        assert_eq!(secrets.redact("nothing secret"), "nothing secret");
+    }
+
+    #[test]
+    fn config_needs_allowed_hosts() {
+        let secret: SecretConfig = serde_json::from_str(
+            r#"{ "file": "github_token", "allowed_hosts": ["api.github.com"] }"#,
+        )
+        .unwrap();
+        assert!(matches!(secret.source, SecretSource::File(name) if name == "github_token"));
+        assert_eq!(secret.allowed_hosts, vec!["api.github.com"]);
+        assert!(serde_json::from_str::<SecretConfig>(r#"{ "env": "TOKEN" }"#).is_err());
+    }
+
+    fn seal(cipher: &ChaCha20Poly1305, name: &str, value: &str) -> String {
+        let nonce = [7u8; NONCE_LEN];
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: value.as_bytes(),
+                    aad: name.as_bytes(),
+                },
+            )
+            .unwrap();
+        hex::encode([&nonce[..], &ciphertext].concat())
+    }
+
+    // The only test touching SECRETS_KEY_ENV, as tests share the environment
+    #[test]
+    fn open_decrypts_and_authenticates_the_secrets_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+        let key = [42u8; 32];
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        std::env::set_var(SECRETS_KEY_ENV, hex::encode(key));
+
+        let sealed = seal(&cipher, "github_token", "ghp_value");
+        std::fs::write(
+            &path,
+            serde_json::json!({ "github_token": sealed }).to_string(),
+        )
+        .unwrap();
+        let store = SecretStore::open(&path).unwrap();
+        assert_eq!(store.file_secrets["github_token"], "ghp_value");
+
+        // The name is associated data, so an entry moved to another name does not decrypt
+        std::fs::write(&path, serde_json::json!({ "other": sealed }).to_string()).unwrap();
+        assert!(SecretStore::open(&path).is_err());
+
+        let mut tampered = hex::decode(&sealed).unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        std::fs::write(
+            &path,
+            serde_json::json!({ "github_token": hex::encode(tampered) }).to_string(),
+        )
+        .unwrap();
+        assert!(SecretStore::open(&path).is_err());
+
+        std::fs::write(
+            &path,
+            serde_json::json!({ "github_token": sealed }).to_string(),
+        )
+        .unwrap();
+        std::env::set_var(SECRETS_KEY_ENV, hex::encode([1u8; 32]));
+        assert!(SecretStore::open(&path).is_err());
+        std::env::set_var(SECRETS_KEY_ENV, "abcd");
+        assert!(SecretStore::open(&path).is_err());
+        std::env::remove_var(SECRETS_KEY_ENV);
+    }
+}
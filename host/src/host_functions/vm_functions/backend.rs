@@ -0,0 +1,91 @@
+//! Backends that run VMs: Firecracker microVMs, or the vm-agent as a local process on machines
+//! without KVM. Both expose the vm-agent through Unix sockets in the VM directory that speak
+//! Firecracker's hybrid vsock protocol, so everything past starting and stopping a VM is shared.
+
+use super::firecracker::{BootedVm, FirecrackerBackend};
+use super::health::VmHealth;
+use super::local::LocalBackend;
+use super::readiness;
+use super::registry::VmProcess;
+use super::vm_dir::VmDir;
+use super::vm_spec::{ResolvedVmSpec, VmConfig};
+use super::{VmManager, VSOCK_SOCKET_FILE};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// Backend selected by `vm.backend`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VmBackendKind {
+    #[default]
+    Firecracker,
+    Local,
+}
+
+#[async_trait]
+pub(crate) trait VmBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Checks whether this machine can run VMs with the backend.
+    fn check_available(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Starts a VM for `spec` and waits for its vm-agent to announce itself. On failure the VM is
+    /// stopped and its output is kept for `get_vm_logs`.
+    async fn start_vm(
+        &self,
+        manager: &VmManager,
+        vm_id: &str,
+        spec: &ResolvedVmSpec,
+    ) -> Result<BootedVm, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Stops a VM and waits for it to exit. Its directory stays until dropped, for the logs.
+    fn stop_vm(&self, process: &mut VmProcess, vm_dir: &mut VmDir);
+
+    /// Connects to a port of the vm-agent.
+    async fn connect_agent(
+        &self,
+        vm_dir: &Path,
+        port: u32,
+    ) -> Result<UnixStream, Box<dyn std::error::Error + Send + Sync>> {
+        let mut stream = UnixStream::connect(vm_dir.join(VSOCK_SOCKET_FILE)).await?;
+        stream
+            .write_all(format!("CONNECT {}\n", port).as_bytes())
+            .await?;
+        // Read byte by byte so nothing the agent sends after the handshake is lost
+        let mut handshake = Vec::new();
+        while handshake.last() != Some(&b'\n') {
+            handshake.push(stream.read_u8().await?);
+        }
+        let handshake = String::from_utf8_lossy(&handshake);
+        if !handshake.starts_with("OK") {
+            return Err(format!("Handshake failed: {}", handshake.trim()).into());
+        }
+        Ok(stream)
+    }
+
+    /// Probes the vm-agent of a VM whose process is running, which is checked by the caller
+    /// through `VmProcess`. Both backends answer the ping through `connect_agent`.
+    async fn check_health(&self, vm_dir: &Path, timeout: Duration) -> VmHealth {
+        match tokio::time::timeout(timeout, readiness::ping(self, vm_dir)).await {
+            Ok(Ok(_)) => VmHealth::Healthy,
+            Ok(Err(e)) => VmHealth::Unresponsive(format!("vm-agent did not answer: {}", e)),
+            Err(_) => VmHealth::Unresponsive(format!(
+                "vm-agent did not answer within {}s",
+                timeout.as_secs()
+            )),
+        }
+    }
+}
+
+/// Creates the backend selected in `config`.
+pub(crate) fn create(config: &VmConfig) -> Arc<dyn VmBackend> {
+    match config.backend {
+        VmBackendKind::Firecracker => Arc::new(FirecrackerBackend),
+        VmBackendKind::Local => Arc::new(LocalBackend::new(config.local.clone())),
+    }
+}
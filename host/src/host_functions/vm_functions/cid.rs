@@ -0,0 +1,161 @@
+//! Guest CIDs of the VMs. CIDs are handed out round-robin from `vm.min_cid` to `vm.max_cid`, so a
+//! CID freed by a destroyed VM is reused last, and CIDs held by other VMs on the host are skipped.
+//!
+//! The CIDs in use are persisted with the VMs in the registry, and the position of the round-robin
+//! next to them, so a restarted host neither hands out the CIDs of reattached VMs nor starts over.
+
+use super::registry::VmRegistry;
+use super::vm_spec::VmConfig;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+
+/// Lowest CID a guest may have; 0 to 2 are the hypervisor, local and host addresses.
+const FIRST_GUEST_CID: u32 = 3;
+
+/// Device through which vhost-vsock VMs such as QEMU's register their CID with the kernel.
+const VHOST_VSOCK_DEVICE: &str = "/dev/vhost-vsock";
+
+// VHOST_VSOCK_SET_GUEST_CID from linux/vhost.h
+nix::ioctl_write_ptr!(vhost_vsock_set_guest_cid, 0xAF, 0x60, u64);
+
+/// Hands out the guest CIDs of the VMs.
+pub(crate) struct CidAllocator {
+    first: u32,
+    last: u32,
+    registry: Arc<VmRegistry>,
+    state: Mutex<CidState>,
+}
+
+struct CidState {
+    /// Where the search for a free CID continues.
+    next: u32,
+    /// Number of VMs using each CID.
+    leases: HashMap<u32, usize>,
+}
+
+impl CidAllocator {
+    pub(crate) fn new(config: &VmConfig, registry: Arc<VmRegistry>) -> Self {
+        let first = config.min_cid.max(FIRST_GUEST_CID);
+        // u32::MAX is VMADDR_CID_ANY
+        let last = config.max_cid.min(u32::MAX - 1);
+        let next = registry.load_next_cid().unwrap_or(first);
+        Self {
+            first,
+            last,
+            registry,
+            state: Mutex::new(CidState {
+                next,
+                leases: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Leases the next CID that no VM of this host uses and no other VM on the host holds.
+    pub(crate) fn allocate(
+        self: &Arc<Self>,
+    ) -> Result<CidLease, Box<dyn std::error::Error + Send + Sync>> {
+        let mut state = self.state.lock().unwrap();
+        let mut cid = state.next.clamp(self.first, self.last);
+        let (mut used_here, mut held_elsewhere) = (0, 0);
+        for _ in self.first..=self.last {
+            let candidate = cid;
+            cid = if cid >= self.last {
+                self.first
+            } else {
+                cid + 1
+            };
+            if state.leases.contains_key(&candidate) {
+                used_here += 1;
+                continue;
+            }
+            if held_by_vhost_vsock(candidate) {
+                log::debug!(
+                    "Skipping CID {}, another VM on the host holds it",
+                    candidate
+                );
+                held_elsewhere += 1;
+                continue;
+            }
+            state.leases.insert(candidate, 1);
+            state.next = cid;
+            self.registry.save_next_cid(cid);
+            return Ok(CidLease {
+                cid: candidate,
+                allocator: self.clone(),
+            });
+        }
+        Err(format!(
+            "No free vsock CID between {} and {}: {} used by VMs of this host, {} by other VMs",
+            self.first, self.last, used_here, held_elsewhere
+        )
+        .into())
+    }
+
+    /// Leases `cid` for a VM that already uses it, one reattached after a restart. It is not
+    /// checked for conflicts.
+    pub(crate) fn acquire(self: &Arc<Self>, cid: u32) -> CidLease {
+        *self.state.lock().unwrap().leases.entry(cid).or_default() += 1;
+        CidLease {
+            cid,
+            allocator: self.clone(),
+        }
+    }
+
+    fn release(&self, cid: u32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(count) = state.leases.get_mut(&cid) {
+            *count -= 1;
+            if *count == 0 {
+                state.leases.remove(&cid);
+            }
+        }
+    }
+}
+
+/// A CID in use by a VM, returned to the allocator when dropped.
+pub struct CidLease {
+    cid: u32,
+    allocator: Arc<CidAllocator>,
+}
+
+impl CidLease {
+    pub fn get(&self) -> u32 {
+        self.cid
+    }
+}
+
+impl Drop for CidLease {
+    fn drop(&mut self) {
+        self.allocator.release(self.cid);
+    }
+}
+
+/// Whether a vhost-vsock VM holds `cid`, found by claiming it on a fresh device, which fails with
+/// EADDRINUSE if it is taken. The claim ends when the device is closed. Firecracker's vsock does
+/// not go through the kernel, so other Firecracker VMs are not found; without the device nothing
+/// is.
+fn held_by_vhost_vsock(cid: u32) -> bool {
+    let Ok(device) = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(VHOST_VSOCK_DEVICE)
+    else {
+        return false;
+    };
+    let guest_cid = cid as u64;
+    match unsafe { vhost_vsock_set_guest_cid(device.as_raw_fd(), &guest_cid) } {
+        Ok(_) => false,
+        Err(nix::errno::Errno::EADDRINUSE) => true,
+        Err(e) => {
+            log::debug!(
+                "Could not check CID {} with {}: {}",
+                cid,
+                VHOST_VSOCK_DEVICE,
+                e
+            );
+            false
+        }
+    }
+}
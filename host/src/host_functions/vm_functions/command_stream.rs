@@ -0,0 +1,62 @@
+//! Output of a foreground command as the vm-agent streams it, followed by the command's result.
+
+use futures::Stream;
+use hyperlight_agents_common::CommandOutput;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+type CommandResult = Result<String, Box<dyn std::error::Error + Send + Sync>>;
+
+/// What a command run with `VmManager::stream_vm_command` produces.
+#[derive(Debug)]
+pub enum VmCommandEvent {
+    /// Output as the command writes it.
+    Output(CommandOutput),
+    /// The last event: the command's standard output, or an error with its exit code and
+    /// standard error, as `execute_vm_command` returns them.
+    Finished(CommandResult),
+}
+
+/// Yields a command's output and then its result. The command runs while the stream is polled.
+pub struct VmCommandStream<'a> {
+    run: Option<Pin<Box<dyn Future<Output = CommandResult> + Send + 'a>>>,
+    output: UnboundedReceiver<CommandOutput>,
+    result: Option<CommandResult>,
+}
+
+impl<'a> VmCommandStream<'a> {
+    pub(crate) fn new(
+        run: impl Future<Output = CommandResult> + Send + 'a,
+        output: UnboundedReceiver<CommandOutput>,
+    ) -> Self {
+        Self {
+            run: Some(Box::pin(run)),
+            output,
+            result: None,
+        }
+    }
+}
+
+impl Stream for VmCommandStream<'_> {
+    type Item = VmCommandEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(run) = this.run.as_mut() {
+            if let Poll::Ready(result) = run.as_mut().poll(cx) {
+                this.run = None;
+                this.result = Some(result);
+            }
+        }
+        // Output is sent before the result, so once the command finished it is all queued
+        if let Poll::Ready(Some(output)) = this.output.poll_recv(cx) {
+            return Poll::Ready(Some(VmCommandEvent::Output(output)));
+        }
+        if this.run.is_some() {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.result.take().map(VmCommandEvent::Finished))
+    }
+}
@@ -0,0 +1,252 @@
+//! Files copied between the host and a VM. Contents travel base64 encoded in chunks of
+//! `CHUNK_SIZE`, one vsock request each, and are checked against their SHA-256 checksum once
+//! complete.
+
+use super::readiness;
+use super::{VmManager, VsockRequest, VsockResponse};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyperlight_agents_common::{
+    FileCommitRequest, FileInfo, FileReadRequest, FileResponse, FileWriteRequest,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Mode of files written without one.
+pub const DEFAULT_FILE_MODE: u32 = 0o644;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// A file read from a VM.
+pub struct VmFile {
+    pub contents: Vec<u8>,
+    pub info: FileInfo,
+}
+
+/// How file contents are passed to and from guests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+/// Parameters of a `put_file` or `get_file` request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VmFileRequest {
+    pub path: String,
+    /// Contents to write, for `put_file`.
+    pub content: String,
+    pub encoding: FileEncoding,
+    /// Octal permission bits such as `755`, `644` by default.
+    pub mode: Option<String>,
+}
+
+impl VmFileRequest {
+    /// The contents and mode to write.
+    pub fn decode(&self) -> Result<(Vec<u8>, u32), String> {
+        let contents = match self.encoding {
+            FileEncoding::Utf8 => self.content.clone().into_bytes(),
+            FileEncoding::Base64 => BASE64
+                .decode(&self.content)
+                .map_err(|e| format!("Invalid base64 content: {}", e))?,
+        };
+        let mode = match &self.mode {
+            Some(mode) => u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or_else(|| format!("Invalid mode '{}', expected octal such as 644", mode))?,
+            None => DEFAULT_FILE_MODE,
+        };
+        Ok((contents, mode))
+    }
+}
+
+/// A file read from a VM as reported by `get_file`: as text if it is valid UTF-8, base64 encoded
+/// otherwise.
+#[derive(Debug, Clone, Serialize)]
+pub struct VmFileContents {
+    pub path: String,
+    pub size: u64,
+    pub mode: String,
+    pub sha256: String,
+    pub encoding: FileEncoding,
+    pub content: String,
+}
+
+impl VmFileContents {
+    pub fn new(path: &str, file: VmFile) -> Self {
+        let (encoding, content) = match String::from_utf8(file.contents) {
+            Ok(text) => (FileEncoding::Utf8, text),
+            Err(e) => (FileEncoding::Base64, BASE64.encode(e.as_bytes())),
+        };
+        Self {
+            path: path.to_string(),
+            size: file.info.size,
+            mode: format!("{:o}", file.info.mode),
+            sha256: file.info.sha256,
+            encoding,
+            content,
+        }
+    }
+}
+
+/// Writes `contents` to `path` in the VM, replacing the file only once all of it arrived intact.
+pub(crate) async fn put_file(
+    manager: &VmManager,
+    vm_id: &str,
+    path: &str,
+    contents: &[u8],
+    mode: u32,
+) -> Result<FileInfo, Box<dyn std::error::Error + Send + Sync>> {
+    check_size(manager, contents.len() as u64)?;
+    if mode > 0o7777 {
+        return Err(format!("Invalid mode {:o}", mode).into());
+    }
+    let vm_dir = agent_dir(manager, vm_id)?;
+    let info = FileInfo {
+        size: contents.len() as u64,
+        mode,
+        sha256: hex::encode(Sha256::digest(contents)),
+    };
+    let written = upload(manager, &vm_dir, path, &mut &contents[..], &info).await?;
+    log::info!("Wrote {} ({} bytes) to VM {}", path, written.size, vm_id);
+    Ok(written)
+}
+
+/// Sends what `contents` reads to `path` in the VM of `vm_dir` and has the vm-agent check it
+/// against `info` before it replaces the file. A failed upload leaves nothing behind.
+pub(crate) async fn upload(
+    manager: &VmManager,
+    vm_dir: &Path,
+    path: &str,
+    contents: &mut impl Read,
+    info: &FileInfo,
+) -> Result<FileInfo, Box<dyn std::error::Error + Send + Sync>> {
+    let transfer_id = uuid::Uuid::new_v4().simple().to_string();
+    let result = send_chunks(manager, vm_dir, path, &transfer_id, contents).await;
+    let commit = FileCommitRequest {
+        path: path.to_string(),
+        transfer_id,
+        info: result.is_ok().then(|| info.clone()),
+    };
+    let committed = file_request(manager, vm_dir, &VsockRequest::FileCommit(commit)).await;
+    result?;
+    Ok(committed?
+        .info
+        .ok_or("The vm-agent did not report the written file")?)
+}
+
+async fn send_chunks(
+    manager: &VmManager,
+    vm_dir: &Path,
+    path: &str,
+    transfer_id: &str,
+    contents: &mut impl Read,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut offset = 0;
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        contents
+            .by_ref()
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        // An empty file still needs its partial file created
+        if chunk.is_empty() && offset > 0 {
+            return Ok(());
+        }
+        let request = VsockRequest::FileWrite(FileWriteRequest {
+            path: path.to_string(),
+            transfer_id: transfer_id.to_string(),
+            offset,
+            data: BASE64.encode(&chunk),
+        });
+        file_request(manager, vm_dir, &request).await?;
+        offset += chunk.len() as u64;
+        if chunk.len() < CHUNK_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads `path` from the VM, failing if it changes while it is read.
+pub(crate) async fn get_file(
+    manager: &VmManager,
+    vm_id: &str,
+    path: &str,
+) -> Result<VmFile, Box<dyn std::error::Error + Send + Sync>> {
+    let vm_dir = agent_dir(manager, vm_id)?;
+    let stat = VsockRequest::FileStat {
+        path: path.to_string(),
+    };
+    let info = file_request(manager, &vm_dir, &stat)
+        .await?
+        .info
+        .ok_or("The vm-agent did not report the file")?;
+    check_size(manager, info.size)?;
+
+    let mut contents = Vec::with_capacity(info.size as usize);
+    while (contents.len() as u64) < info.size {
+        let request = VsockRequest::FileRead(FileReadRequest {
+            path: path.to_string(),
+            offset: contents.len() as u64,
+            length: CHUNK_SIZE as u64,
+        });
+        let data = file_request(manager, &vm_dir, &request).await?.data;
+        let chunk = BASE64.decode(data.unwrap_or_default())?;
+        if chunk.is_empty() {
+            break;
+        }
+        contents.extend_from_slice(&chunk);
+    }
+    if contents.len() as u64 != info.size || hex::encode(Sha256::digest(&contents)) != info.sha256 {
+        return Err(format!("{} changed while it was read from VM {}", path, vm_id).into());
+    }
+    Ok(VmFile { contents, info })
+}
+
+fn check_size(
+    manager: &VmManager,
+    size: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let max_size = manager.config.max_file_size_mib as u64 * 1024 * 1024;
+    if size > max_size {
+        return Err(format!(
+            "File of {} bytes exceeds the limit of {} MiB",
+            size, manager.config.max_file_size_mib
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Directory of the VM whose agent takes the requests. A transfer counts as activity.
+pub(crate) fn agent_dir(
+    manager: &VmManager,
+    vm_id: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let instances = manager.instances.lock().unwrap();
+    let vm_instance = instances
+        .get(vm_id)
+        .ok_or_else(|| manager.vm_not_found(vm_id))?;
+    vm_instance.lifetime.touch();
+    Ok(vm_instance.vm_dir.path().to_path_buf())
+}
+
+async fn file_request(
+    manager: &VmManager,
+    vm_dir: &Path,
+    request: &VsockRequest,
+) -> Result<FileResponse, Box<dyn std::error::Error + Send + Sync>> {
+    match readiness::agent_request(&*manager.backend, vm_dir, request).await? {
+        VsockResponse::File(FileResponse {
+            error: Some(error), ..
+        }) => Err(error.into()),
+        VsockResponse::File(response) => Ok(response),
+        other => Err(format!("Unexpected response to file request: {:?}", other).into()),
+    }
+}
@@ -1,128 +1,392 @@
-use super::{VmInstance, VmManager};
+use super::backend::VmBackend;
+use super::cid::CidLease;
+use super::firecracker_api;
+use super::lifetime::VmLifetime;
+use super::listeners::VmListeners;
+use super::readiness;
+use super::registry::{self, VmProcess, VmRecord};
+use super::vm_dir::VmDir;
+use super::vm_logs::{self, RetiredVmLogs, VmLogs};
+use super::vm_spec::{self, ResolvedVmSpec};
+use super::volumes::{self, PreparedVolume, VolumeLease};
+use super::{
+    BootingVm, VmCommandRequest, VmInstance, VmManager, VmState, VmSummary, VSOCK_SOCKET_FILE,
+};
+use async_trait::async_trait;
 use chrono::Utc;
-use hyperlight_agents_common::{VmCommand, VmCommandMode, VmCommandResult};
+use hyperlight_agents_common::{
+    AgentReady, CommandOutput, VmCommand, VmCommandMode, VmCommandResult, VmSpec,
+    COMMAND_OUTPUT_TAIL_BYTES, DEFAULT_COMMAND_TIMEOUT_SECS, VM_AGENT_COMMAND_PORT,
+};
 use memfd::{Memfd, MemfdOptions};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
-use tempfile::TempDir;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Firecracker config file, relative to the VM directory.
+const CONFIG_FILE: &str = "firecracker-config.json";
+
+/// Longest `list_vms` waits for a vm-agent to report its spawned processes.
+const LIST_STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Seconds a foreground command's reply may take after its timeout.
+const COMMAND_REPLY_GRACE_SECS: u64 = 5;
 
 pub(crate) async fn create_vm_internal(
     manager: &VmManager,
     vm_id: String,
+    spec: VmSpec,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let cid = {
-        let mut next_cid = manager.next_cid.lock().unwrap();
-        let current_cid = *next_cid;
-        *next_cid += 1;
-        current_cid
-    };
+    let resolved_spec = vm_spec::resolve_spec(&spec, &manager.config)?;
+    let profile = spec
+        .profile
+        .clone()
+        .unwrap_or_else(|| vm_spec::DEFAULT_PROFILE.to_string());
 
-    let temp_dir = TempDir::new()?;
-    let (command_sender, command_receiver) = mpsc::channel::<VmCommand>();
+    {
+        // The id stays in `booting` until the VM is in `instances`, so holding the booting lock
+        // keeps another create_vm from taking the id in between
+        let mut booting = manager.booting.lock().unwrap();
+        if booting.contains_key(&vm_id) || manager.instances.lock().unwrap().contains_key(&vm_id) {
+            return Err(format!("VM {} already exists", vm_id).into());
+        }
+        booting.insert(
+            vm_id.clone(),
+            BootingVm {
+                spec: spec.clone(),
+                started_at: Utc::now(),
+            },
+        );
+    }
+    let booted = match manager.warm_pool.take(&profile, &resolved_spec) {
+        Some(booted) => Ok(booted),
+        None => {
+            manager
+                .backend
+                .start_vm(manager, &vm_id, &resolved_spec)
+                .await
+        }
+    };
+    let booted = match booted {
+        Ok(booted) => booted,
+        Err(e) => {
+            manager.booting.lock().unwrap().remove(&vm_id);
+            return Err(e);
+        }
+    };
+    log::info!(
+        "VM {} ready: vm-agent {} with capabilities {:?}",
+        vm_id,
+        booted.ready.version,
+        booted.ready.capabilities
+    );
 
-    let (vm_process, rootfs_path) = start_firecracker_vm(temp_dir.path(), &vm_id, cid)?;
+    let lifetime = Arc::new(VmLifetime::new(&spec, &manager.config));
+    let listeners = match VmListeners::start(manager, &vm_id, &booted.vm_dir, &lifetime) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            booted.stop(&*manager.backend);
+            manager.booting.lock().unwrap().remove(&vm_id);
+            return Err(format!("VM {} failed to start its listeners: {}", vm_id, e).into());
+        }
+    };
 
+    let (command_sender, command_receiver) = mpsc::channel::<VmCommand>();
+    let cid = booted.cid.get();
     let vm_instance = VmInstance {
         vm_id: vm_id.clone(),
-        cid,
-        pid: vm_process,
-        temp_dir,
+        cid: booted.cid,
+        pid: Some(booted.child.id()),
+        process: VmProcess::Child(booted.child),
+        logs: booted.logs,
+        vm_dir: booted.vm_dir,
+        volumes: booted.volumes,
         command_sender,
         result_receiver: Arc::new(Mutex::new(HashMap::new())),
+        output_senders: Arc::new(Mutex::new(HashMap::new())),
         memfd_rootfs: None,
-        rootfs_symlink: rootfs_path,
+        rootfs_symlink: None,
+        lifetime,
+        listeners,
+        state: VmState::Ready,
+        spec,
     };
 
+    manager
+        .registry
+        .save(&VmRecord::for_instance(&*manager.backend, &vm_instance));
     {
         let mut instances = manager.instances.lock().unwrap();
         instances.insert(vm_id.clone(), vm_instance);
     }
+    manager.booting.lock().unwrap().remove(&vm_id);
 
     start_command_processor(
         manager.instances.clone(),
+        manager.backend.clone(),
         manager.shutdown_flag.clone(),
         vm_id.clone(),
         command_receiver,
     );
 
-    Ok(format!("VM {} created with CID {}", vm_id, cid))
+    Ok(format!(
+        "VM {} created with CID {} from profile {} ({} vCPUs, {} MiB, {})",
+        vm_id,
+        cid,
+        profile,
+        resolved_spec.vcpu_count,
+        resolved_spec.mem_size_mib,
+        booted.origin.as_str()
+    ))
+}
+
+/// How a VM was started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BootOrigin {
+    ColdBoot,
+    WarmPool,
+}
+
+impl BootOrigin {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            BootOrigin::ColdBoot => "cold boot",
+            BootOrigin::WarmPool => "from warm pool",
+        }
+    }
+}
+
+/// A VM whose agent is ready to take commands.
+pub(crate) struct BootedVm {
+    pub vm_dir: VmDir,
+    pub logs: Arc<VmLogs>,
+    pub child: Child,
+    pub cid: CidLease,
+    /// Named volumes attached to the VM.
+    pub volumes: Vec<VolumeLease>,
+    pub ready: AgentReady,
+    pub origin: BootOrigin,
+}
+
+impl BootedVm {
+    /// Stops a VM that was never handed out.
+    pub(crate) fn stop(mut self, backend: &dyn VmBackend) {
+        backend.stop_vm(&mut VmProcess::Child(self.child), &mut self.vm_dir);
+    }
+}
+
+/// Runs VMs as Firecracker microVMs, optionally through the jailer.
+pub(crate) struct FirecrackerBackend;
+
+#[async_trait]
+impl VmBackend for FirecrackerBackend {
+    fn name(&self) -> &'static str {
+        "firecracker"
+    }
+
+    fn check_available(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let output = Command::new(firecracker_binary()?)
+            .arg("--version")
+            .output()?;
+        if !output.status.success() {
+            return Err("firecracker --version failed".into());
+        }
+        if !Path::new("/dev/kvm").exists() {
+            return Err("/dev/kvm not found".into());
+        }
+        Ok(())
+    }
+
+    async fn start_vm(
+        &self,
+        manager: &VmManager,
+        vm_id: &str,
+        spec: &ResolvedVmSpec,
+    ) -> Result<BootedVm, Box<dyn std::error::Error + Send + Sync>> {
+        start_vm(manager, vm_id, spec).await
+    }
+
+    fn stop_vm(&self, process: &mut VmProcess, vm_dir: &mut VmDir) {
+        process.kill();
+        process.wait();
+        vm_dir.release_jail();
+    }
+}
+
+/// Boots a VM for `spec` and waits for its agent. On failure the VM is stopped and its output is
+/// kept for `get_vm_logs`.
+pub(crate) async fn start_vm(
+    manager: &VmManager,
+    vm_id: &str,
+    spec: &ResolvedVmSpec,
+) -> Result<BootedVm, Box<dyn std::error::Error + Send + Sync>> {
+    let firecracker_bin = firecracker_binary()?;
+    let cid = manager.cids.allocate()?;
+    let mut dir = VmDir::new(&manager.config, vm_id, &firecracker_bin, spec)?;
+    let vm_dir = &dir.path().to_path_buf();
+    let logs = Arc::new(VmLogs::new(vm_dir, manager.config.log_buffer_lines)?);
+    for fifo in [vm_logs::FIRECRACKER_LOG_FIFO, vm_logs::METRICS_FIFO] {
+        dir.grant(&vm_dir.join(fifo))?;
+    }
+    let volumes = volumes::prepare(&manager.volumes, vm_id, &dir, &spec.volumes)?;
+    let boot_timeout = Duration::from_secs(manager.config.boot_timeout_secs);
+
+    let ready_listener = readiness::bind_ready_listener(vm_dir)?;
+    dir.grant(&readiness::ready_socket_path(vm_dir))?;
+    let mut child = start_firecracker_vm(&dir, &firecracker_bin, vm_id, cid.get(), spec, &volumes)?;
+    logs.capture_process(&mut child, vm_dir)?;
+    let ready =
+        readiness::wait_for_ready(ready_listener, &mut child, vm_dir, &logs, boot_timeout).await;
+
+    match ready {
+        Ok(ready) => Ok(BootedVm {
+            vm_dir: dir,
+            logs,
+            child,
+            cid,
+            volumes: volumes
+                .into_iter()
+                .filter_map(|volume| volume.lease)
+                .collect(),
+            ready,
+            origin: BootOrigin::ColdBoot,
+        }),
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            dir.release_jail();
+            volumes::remove_images(dir.path());
+            // Keep the output of the failed boot for get_vm_logs
+            manager.retire_logs(vm_id, RetiredVmLogs::new(logs, dir));
+            Err(format!("VM {} failed to boot: {}", vm_id, e).into())
+        }
+    }
+}
+
+/// Firecracker's logger and metrics settings. Paths are relative to the VM directory, which is
+/// the working directory of Firecracker.
+fn logger_config() -> Value {
+    serde_json::json!({
+        "log_path": vm_logs::FIRECRACKER_LOG_FIFO,
+        "level": "Info",
+        "show_level": true
+    })
+}
+
+fn metrics_config() -> Value {
+    serde_json::json!({ "metrics_path": vm_logs::METRICS_FIFO })
 }
 
 pub(crate) fn start_firecracker_vm(
-    vm_dir: &Path,
+    dir: &VmDir,
+    firecracker_bin: &Path,
     vm_id: &str,
     cid: u32,
-) -> Result<(Option<u32>, Option<PathBuf>), Box<dyn std::error::Error + Send + Sync>> {
-    let vm_images_dir = Path::new("firecracker");
-    let kernel_path = vm_images_dir.join("vmlinux");
-    let source_rootfs_path = vm_images_dir.join("rootfs.squashfs");
-    let config_path = vm_dir.join("firecracker-config.json");
-
-    if !kernel_path.exists() {
-        return Err(format!("Kernel image not found at: {}", kernel_path.display()).into());
-    }
-    if !source_rootfs_path.exists() {
-        return Err(format!(
-            "Rootfs image not found at: {}",
-            source_rootfs_path.display()
-        )
-        .into());
+    spec: &ResolvedVmSpec,
+    volumes: &[PreparedVolume],
+) -> Result<Child, Box<dyn std::error::Error + Send + Sync>> {
+    log::debug!("Starting VM {} with {:?}", vm_id, spec);
+
+    // A jailed Firecracker only sees the files in its chroot
+    let (kernel_path, rootfs_path) = match dir.jail() {
+        Some(jail) => (
+            jail.add_image(&spec.kernel_path, "vmlinux")?,
+            jail.add_image(&spec.rootfs_path, "rootfs")?,
+        ),
+        None => (
+            spec.kernel_path.to_str().unwrap().to_string(),
+            spec.rootfs_path.to_str().unwrap().to_string(),
+        ),
+    };
+
+    // Attached in order after the rootfs, as the devices the boot args tell the vm-agent to mount
+    let mut drives = vec![serde_json::json!({
+        "drive_id": "rootfs",
+        "path_on_host": rootfs_path,
+        "is_root_device": true,
+        "is_read_only": true
+    })];
+    for (index, volume) in volumes.iter().enumerate() {
+        drives.push(serde_json::json!({
+            "drive_id": format!("volume{}", index),
+            "path_on_host": volume.path,
+            "is_root_device": false,
+            "is_read_only": volume.read_only
+        }));
     }
 
+    // The vsock path is relative to the VM directory
     let config = serde_json::json!({
         "boot-source": {
-            "kernel_image_path": kernel_path.to_str().unwrap(),
-            "boot_args": "console=ttyS0 reboot=k panic=1 pci=off init=/sbin/init root=/dev/vda rootfstype=squashfs ro"
+            "kernel_image_path": kernel_path,
+            "boot_args": spec.boot_args
         },
-        "drives": [{
-            "drive_id": "rootfs",
-            "path_on_host": source_rootfs_path.to_str().unwrap(),
-            "is_root_device": true,
-            "is_read_only": true
-        }],
+        "drives": drives,
         "machine-config": {
-            "vcpu_count": 1,
-            "mem_size_mib": 512,
-            "smt": false
+            "vcpu_count": spec.vcpu_count,
+            "mem_size_mib": spec.mem_size_mib,
+            "smt": spec.smt
         },
         "vsock": {
             "guest_cid": cid,
-            "uds_path": format!("{}/vsock.sock", vm_dir.display())
-        }
+            "uds_path": VSOCK_SOCKET_FILE
+        },
+        "logger": logger_config(),
+        "metrics": metrics_config()
     });
 
-    std::fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+    std::fs::write(
+        dir.path().join(CONFIG_FILE),
+        serde_json::to_string_pretty(&config)?,
+    )?;
+    spawn_firecracker(dir, firecracker_bin, CONFIG_FILE)
+}
 
-    let devnull = File::create("/dev/null")?;
-    // Prefer system firecracker if available, else fallback to local binary
-    let firecracker_bin = if which::which("firecracker").is_ok() {
-        "firecracker"
-    } else {
-        "firecracker/firecracker"
+/// Firecracker binary, preferring the system one over the bundled one.
+pub(crate) fn firecracker_binary() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    match which::which("firecracker") {
+        Ok(path) => Ok(path),
+        // Resolved up front, as the process runs in the VM directory
+        Err(_) => Ok(Path::new("firecracker/firecracker").canonicalize()?),
+    }
+}
+
+/// Starts Firecracker in the VM directory, through the jailer in jailer mode, with its output
+/// piped for capture. Paths are relative to the VM directory, which is also the jail's root.
+pub(crate) fn spawn_firecracker(
+    dir: &VmDir,
+    firecracker_bin: &Path,
+    config_file: &str,
+) -> Result<Child, Box<dyn std::error::Error + Send + Sync>> {
+    let devnull = File::open("/dev/null")?;
+    let mut cmd = match dir.jail() {
+        Some(jail) => jail.command(firecracker_bin),
+        None => Command::new(firecracker_bin),
     };
-    let mut cmd = Command::new(firecracker_bin);
-    cmd.arg("--api-sock")
-        .arg(format!("{}/firecracker.sock", vm_dir.display()))
+    cmd.current_dir(dir.path())
+        .arg("--api-sock")
+        .arg(firecracker_api::API_SOCKET_FILE)
         .arg("--config-file")
-        .arg(&config_path)
-        .stdout(devnull.try_clone()?)
-        .stderr(devnull.try_clone()?)
-        .stdin(devnull);
+        .arg(config_file);
+    // Outside the host's process group, so the VM survives a host killed from its terminal and
+    // can be reattached
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdin(devnull)
+        .process_group(0);
 
     match cmd.spawn() {
-        Ok(child) => {
-            thread::sleep(Duration::from_secs(2));
-            Ok((Some(child.id()), Some(source_rootfs_path)))
-        }
+        Ok(child) => Ok(child),
         Err(e) => {
             log::error!("Failed to start Firecracker VM: {}", e);
             Err(e.into())
@@ -132,96 +396,80 @@ pub(crate) fn start_firecracker_vm(
 
 // Removed: create_memfd_rootfs, not needed for squashfs readonly rootfs.
 
-fn start_command_processor(
+pub(crate) fn start_command_processor(
     instances: Arc<Mutex<HashMap<String, VmInstance>>>,
+    backend: Arc<dyn VmBackend>,
     shutting_down: Arc<AtomicBool>,
     vm_id: String,
     receiver: mpsc::Receiver<VmCommand>,
 ) {
     thread::spawn(move || {
+        // Only for connecting; the request itself is blocking I/O on this thread
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
         for command in receiver {
             if shutting_down.load(Ordering::SeqCst) {
                 break;
             }
 
-            let (vsock_socket_path, result_sender) = {
+            let (vm_dir, result_sender, output_sender, lifetime) = {
                 let instances_guard = instances.lock().unwrap();
                 if let Some(vm_instance) = instances_guard.get(&vm_id) {
-                    let socket_path =
-                        format!("{}/vsock.sock", vm_instance.temp_dir.path().display());
                     let sender = vm_instance
                         .result_receiver
                         .lock()
                         .unwrap()
                         .get(&command.id)
                         .cloned();
-                    (socket_path, sender)
+                    let output_sender = vm_instance
+                        .output_senders
+                        .lock()
+                        .unwrap()
+                        .get(&command.id)
+                        .cloned();
+                    (
+                        vm_instance.vm_dir.path().to_path_buf(),
+                        sender,
+                        output_sender,
+                        vm_instance.lifetime.clone(),
+                    )
                 } else {
                     continue;
                 }
             };
 
+            let in_flight = lifetime.command_in_flight();
+
             let mut vm_result = VmCommandResult {
                 id: command.id.clone(),
                 exit_code: -1,
                 stdout: String::new(),
                 stderr: String::new(),
                 error: None,
+                stdout_truncated: false,
+                stderr_truncated: false,
             };
 
-            if !Path::new(&vsock_socket_path).exists() {
-                vm_result.error = Some(format!("VSOCK socket not found: {}", vsock_socket_path));
-                if let Some(sender) = result_sender {
-                    sender.send(vm_result).ok();
-                }
-                continue;
-            }
-
-            match std::os::unix::net::UnixStream::connect(&vsock_socket_path) {
+            let stream = rt
+                .block_on(backend.connect_agent(&vm_dir, VM_AGENT_COMMAND_PORT))
+                .and_then(|stream| Ok(stream.into_std()?));
+            match stream {
                 Ok(mut stream) => {
                     stream.set_nonblocking(false).ok();
-                    let handshake = "CONNECT 1234\n";
-                    if stream.write_all(handshake.as_bytes()).is_err() {
-                        vm_result.error = Some("Handshake send failed".to_string());
-                    } else {
-                        let mut h_buf = [0; 256];
-                        if stream.read(&mut h_buf).is_ok() {
-                            let vsock_request =
-                                crate::host_functions::vm_functions::VsockRequest::Command(command);
-                            let command_str = serde_json::to_string(&vsock_request).unwrap();
-
-                            if stream.write_all(command_str.as_bytes()).is_ok()
-                                && stream.flush().is_ok()
-                            {
-                                let mut response_buffer = Vec::new();
-                                if stream.read_to_end(&mut response_buffer).is_ok() {
-                                    if let Ok(response_str) = String::from_utf8(response_buffer) {
-                                        if let Ok(json) =
-                                            serde_json::from_str::<Value>(&response_str)
-                                        {
-                                            vm_result.exit_code =
-                                                json["exit_code"].as_i64().unwrap_or(-1) as i32;
-                                            vm_result.stdout =
-                                                json["stdout"].as_str().unwrap_or("").to_string();
-                                            vm_result.stderr =
-                                                json["stderr"].as_str().unwrap_or("").to_string();
-                                        } else {
-                                            vm_result.error =
-                                                Some("Failed to parse JSON response".to_string());
-                                        }
-                                    } else {
-                                        vm_result.error =
-                                            Some("Invalid UTF-8 in response".to_string());
-                                    }
-                                } else {
-                                    vm_result.error = Some("Failed to read response".to_string());
-                                }
-                            } else {
-                                vm_result.error = Some("Failed to send command".to_string());
-                            }
-                        } else {
-                            vm_result.error = Some("Handshake read failed".to_string());
+                    let vsock_request =
+                        crate::host_functions::vm_functions::VsockRequest::Command(command);
+                    let command_str = serde_json::to_string(&vsock_request).unwrap();
+
+                    if stream.write_all(command_str.as_bytes()).is_ok() && stream.flush().is_ok() {
+                        if let Err(e) =
+                            read_command_response(stream, &mut vm_result, output_sender.as_ref())
+                        {
+                            vm_result.error = Some(e);
                         }
+                    } else {
+                        vm_result.error = Some("Failed to send command".to_string());
                     }
                 }
                 Err(e) => {
@@ -229,6 +477,16 @@ fn start_command_processor(
                 }
             }
 
+            drop(in_flight);
+            if let Some(vm_instance) = instances.lock().unwrap().get_mut(&vm_id) {
+                if vm_instance.state != VmState::Stopping {
+                    vm_instance.state = if vm_result.error.is_some() {
+                        VmState::Unhealthy
+                    } else {
+                        VmState::Ready
+                    };
+                }
+            }
             if let Some(sender) = result_sender {
                 sender.send(vm_result).ok();
             }
@@ -236,13 +494,45 @@ fn start_command_processor(
     });
 }
 
+/// Reads the vm-agent's reply to a foreground command into `vm_result`: a JSON line for each
+/// output chunk, passed on to `output_sender`, then the command's response.
+fn read_command_response(
+    stream: std::os::unix::net::UnixStream,
+    vm_result: &mut VmCommandResult,
+    output_sender: Option<&UnboundedSender<CommandOutput>>,
+) -> Result<(), String> {
+    let mut response = None;
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(|_| "Failed to read response".to_string())?;
+        let json = serde_json::from_str::<Value>(&line)
+            .map_err(|_| "Failed to parse JSON response".to_string())?;
+        if json["type"] == "CommandOutput" {
+            if let (Some(sender), Ok(output)) =
+                (output_sender, serde_json::from_value::<CommandOutput>(json))
+            {
+                // Nobody may be listening any more
+                sender.send(output).ok();
+            }
+        } else {
+            response = Some(json);
+        }
+    }
+    let json = response.ok_or_else(|| "Failed to read response".to_string())?;
+    vm_result.exit_code = json["exit_code"].as_i64().unwrap_or(-1) as i32;
+    vm_result.stdout = json["stdout"].as_str().unwrap_or("").to_string();
+    vm_result.stderr = json["stderr"].as_str().unwrap_or("").to_string();
+    vm_result.stdout_truncated = json["stdout_truncated"].as_bool().unwrap_or(false);
+    vm_result.stderr_truncated = json["stderr_truncated"].as_bool().unwrap_or(false);
+    Ok(())
+}
+
+/// Runs a foreground command and returns its standard output, passing its output to `output` as
+/// the vm-agent streams it.
 pub(crate) async fn execute_command_in_vm_internal(
     manager: &VmManager,
     vm_id: &str,
-    command: String,
-    args: Vec<String>,
-    working_dir: Option<String>,
-    timeout_seconds: Option<u64>,
+    request: &VmCommandRequest,
+    output: &UnboundedSender<CommandOutput>,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let cmd_id = format!("cmd_{}", Utc::now().timestamp_nanos_opt().unwrap_or(0));
 
@@ -255,55 +545,65 @@ pub(crate) async fn execute_command_in_vm_internal(
                 .lock()
                 .unwrap()
                 .insert(cmd_id.clone(), tx);
+            vm_instance
+                .output_senders
+                .lock()
+                .unwrap()
+                .insert(cmd_id.clone(), output.clone());
             (vm_instance.command_sender.clone(), rx)
         } else {
-            return Err(format!("VM {} not found", vm_id).into());
+            return Err(manager.vm_not_found(vm_id));
         }
     };
 
+    let timeout_seconds = request
+        .timeout_seconds
+        .unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS);
     let vm_command = VmCommand {
         id: cmd_id.clone(),
-        command,
-        args,
-        working_dir,
-        timeout_seconds,
+        command: request.command.clone(),
+        args: request.args.clone(),
+        working_dir: request.working_dir.clone(),
+        timeout_seconds: Some(timeout_seconds),
         mode: VmCommandMode::Foreground,
+        env: request.env.clone(),
+        stdin: request.stdin.clone(),
+        shell: request.shell,
     };
 
     command_sender
         .send(vm_command)
         .map_err(|e| format!("Failed to send command to VM: {}", e))?;
 
-    let timeout_duration = Duration::from_secs(timeout_seconds.unwrap_or(30));
+    // The vm-agent kills the command at its timeout; allow for its reply to arrive
+    let timeout_duration = Duration::from_secs(timeout_seconds + COMMAND_REPLY_GRACE_SECS);
     let start_time = Instant::now();
 
+    let forget_command = || {
+        if let Some(vm) = manager.instances.lock().unwrap().get(vm_id) {
+            vm.result_receiver.lock().unwrap().remove(&cmd_id);
+            vm.output_senders.lock().unwrap().remove(&cmd_id);
+        }
+    };
     loop {
         match result_receiver.try_recv() {
             Ok(result) => {
-                manager
-                    .instances
-                    .lock()
-                    .unwrap()
-                    .get(vm_id)
-                    .map(|vm| vm.result_receiver.lock().unwrap().remove(&cmd_id));
+                forget_command();
+                let stdout = output_tail(result.stdout, result.stdout_truncated);
+                let stderr = output_tail(result.stderr, result.stderr_truncated);
                 if result.exit_code == 0 {
-                    return Ok(result.stdout);
+                    return Ok(stdout);
                 } else {
                     return Err(format!(
                         "Command failed with exit code {}: {}",
-                        result.exit_code, result.stderr
+                        result.exit_code, stderr
                     )
                     .into());
                 }
             }
             Err(mpsc::TryRecvError::Empty) => {
                 if start_time.elapsed() > timeout_duration {
-                    manager
-                        .instances
-                        .lock()
-                        .unwrap()
-                        .get(vm_id)
-                        .map(|vm| vm.result_receiver.lock().unwrap().remove(&cmd_id));
+                    forget_command();
                     return Err("Command execution timed out".into());
                 }
                 tokio::time::sleep(Duration::from_millis(100)).await;
@@ -315,14 +615,23 @@ pub(crate) async fn execute_command_in_vm_internal(
     }
 }
 
+/// Output the vm-agent cut to its last `COMMAND_OUTPUT_TAIL_BYTES`, marked as such.
+fn output_tail(text: String, truncated: bool) -> String {
+    if truncated {
+        format!(
+            "[earlier output truncated, showing the last {} bytes]\n{}",
+            COMMAND_OUTPUT_TAIL_BYTES, text
+        )
+    } else {
+        text
+    }
+}
+
 /// Spawns a command in the VM agent and returns the command ID (or PID if agent returns it)
 pub(crate) async fn spawn_command_internal(
     manager: &VmManager,
     vm_id: &str,
-    command: String,
-    args: Vec<String>,
-    working_dir: Option<String>,
-    timeout_seconds: Option<u64>,
+    request: &VmCommandRequest,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let cmd_id = format!("cmd_{}", Utc::now().timestamp_nanos_opt().unwrap_or(0));
     let (command_sender, result_receiver) = {
@@ -336,17 +645,20 @@ pub(crate) async fn spawn_command_internal(
                 .insert(cmd_id.clone(), tx);
             (vm_instance.command_sender.clone(), rx)
         } else {
-            return Err(format!("VM {} not found", vm_id).into());
+            return Err(manager.vm_not_found(vm_id));
         }
     };
 
     let vm_command = VmCommand {
         id: cmd_id.clone(),
-        command,
-        args,
-        working_dir,
-        timeout_seconds,
+        command: request.command.clone(),
+        args: request.args.clone(),
+        working_dir: request.working_dir.clone(),
+        timeout_seconds: request.timeout_seconds,
         mode: VmCommandMode::Spawn,
+        env: request.env.clone(),
+        stdin: request.stdin.clone(),
+        shell: request.shell,
     };
 
     command_sender
@@ -374,7 +686,7 @@ pub(crate) async fn list_spawned_processes_internal(
                 .insert("list_spawned_processes".to_string(), tx);
             (vm_instance.command_sender.clone(), rx)
         } else {
-            return Err(format!("VM {} not found", vm_id).into());
+            return Err(manager.vm_not_found(vm_id));
         }
     };
 
@@ -385,6 +697,9 @@ pub(crate) async fn list_spawned_processes_internal(
         working_dir: None,
         timeout_seconds: Some(30),
         mode: VmCommandMode::Foreground,
+        env: BTreeMap::new(),
+        stdin: None,
+        shell: false,
     };
 
     command_sender
@@ -447,7 +762,7 @@ pub(crate) async fn stop_spawned_process_internal(
                 .insert(cmd_id.clone(), tx);
             (vm_instance.command_sender.clone(), rx)
         } else {
-            return Err(format!("VM {} not found", vm_id).into());
+            return Err(manager.vm_not_found(vm_id));
         }
     };
 
@@ -458,6 +773,9 @@ pub(crate) async fn stop_spawned_process_internal(
         working_dir: None,
         timeout_seconds: Some(30),
         mode: VmCommandMode::Foreground,
+        env: BTreeMap::new(),
+        stdin: None,
+        shell: false,
     };
 
     command_sender
@@ -497,37 +815,144 @@ pub(crate) async fn destroy_vm_internal(
     manager: &VmManager,
     vm_id: &str,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let mut instances = manager.instances.lock().unwrap();
-    if let Some(vm_instance) = instances.remove(vm_id) {
-        if let Some(pid) = vm_instance.pid {
-            terminate_process(pid, "KILL").ok();
-        }
-        if let Some(symlink_path) = &vm_instance.rootfs_symlink {
-            std::fs::remove_file(symlink_path).ok();
+    stop_vm(manager, vm_id, None, None)?;
+    Ok(format!("VM {} destroyed", vm_id))
+}
+
+/// Kills a VM, listing it as stopping until then, and keeps its output for `get_vm_logs` along
+/// with the reason it was destroyed if it did not come from `destroy_vm`. With `lifetime`, only
+/// the instance it belongs to is stopped, not a newer VM created under the same id.
+pub(crate) fn stop_vm(
+    manager: &VmManager,
+    vm_id: &str,
+    lifetime: Option<&Arc<VmLifetime>>,
+    reason: Option<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let pid = {
+        let mut instances = manager.instances.lock().unwrap();
+        match instances.get_mut(vm_id) {
+            Some(vm_instance)
+                if lifetime
+                    .is_some_and(|lifetime| !Arc::ptr_eq(lifetime, &vm_instance.lifetime)) =>
+            {
+                return Err(format!("VM {} was replaced", vm_id).into());
+            }
+            Some(vm_instance) if vm_instance.state == VmState::Stopping => {
+                return Err(format!("VM {} is already stopping", vm_id).into());
+            }
+            Some(vm_instance) => {
+                vm_instance.state = VmState::Stopping;
+                vm_instance.pid
+            }
+            None => return Err(manager.vm_not_found(vm_id)),
         }
-        Ok(format!("VM {} destroyed", vm_id))
-    } else {
-        Err(format!("VM {} not found", vm_id).into())
+    };
+
+    if let Some(pid) = pid {
+        terminate_process(pid, "KILL").ok();
+    }
+    let Some(mut vm_instance) = manager.instances.lock().unwrap().remove(vm_id) else {
+        return Ok(());
+    };
+    manager
+        .backend
+        .stop_vm(&mut vm_instance.process, &mut vm_instance.vm_dir);
+    drop(vm_instance.listeners);
+    drop(vm_instance.volumes);
+    volumes::remove_images(vm_instance.vm_dir.path());
+    manager.registry.remove(&registry::vm_key(vm_id));
+    if let Some(symlink_path) = &vm_instance.rootfs_symlink {
+        std::fs::remove_file(symlink_path).ok();
     }
+    manager.retire_logs(
+        vm_id,
+        RetiredVmLogs::new(vm_instance.logs, vm_instance.vm_dir).with_reason(reason),
+    );
+    Ok(())
 }
 
-pub(crate) fn list_vms_internal(manager: &VmManager) -> Vec<String> {
-    manager.instances.lock().unwrap().keys().cloned().collect()
-}
+/// Lists booting and running VMs, asking the agents of running ones for their spawned processes.
+pub(crate) async fn list_vms_internal(manager: &VmManager) -> Vec<VmSummary> {
+    let profile_of = |spec: &VmSpec| {
+        spec.profile
+            .clone()
+            .unwrap_or_else(|| vm_spec::DEFAULT_PROFILE.to_string())
+    };
 
-pub(crate) async fn check_vm_health_internal(manager: &VmManager, vm_id: &str) -> bool {
-    if let Some(vm_instance) = manager.instances.lock().unwrap().get(vm_id) {
-        let health_cmd = VmCommand {
-            id: "health-check".to_string(),
-            command: "echo".to_string(),
-            args: vec!["healthy".to_string()],
-            working_dir: None,
-            timeout_seconds: Some(30),
-            mode: VmCommandMode::Foreground,
-        };
-        return vm_instance.command_sender.send(health_cmd).is_ok();
+    let (mut vms, vm_dirs): (Vec<VmSummary>, Vec<Option<PathBuf>>) = manager
+        .instances
+        .lock()
+        .unwrap()
+        .values()
+        .map(|vm_instance| {
+            let lifetime = &vm_instance.lifetime;
+            let summary = VmSummary {
+                vm_id: vm_instance.vm_id.clone(),
+                state: vm_instance.state,
+                profile: profile_of(&vm_instance.spec),
+                owner: vm_instance.spec.owner.clone(),
+                labels: vm_instance.spec.labels.clone(),
+                cid: Some(vm_instance.cid.get()),
+                pid: vm_instance.pid,
+                vcpu_count: vm_instance.spec.vcpu_count,
+                mem_size_mib: vm_instance.spec.mem_size_mib,
+                created_at: lifetime.created_at(),
+                last_activity: lifetime.last_activity_at(),
+                age_secs: lifetime.age().as_secs(),
+                idle_secs: lifetime.idle().as_secs(),
+                expires_in_secs: lifetime.remaining().map(|remaining| remaining.as_secs()),
+                spawned_processes: None,
+            };
+            let vm_dir = (vm_instance.state != VmState::Stopping)
+                .then(|| vm_instance.vm_dir.path().to_path_buf());
+            (summary, vm_dir)
+        })
+        .unzip();
+
+    let statuses = futures::future::join_all(vm_dirs.iter().map(|vm_dir| async move {
+        let vm_dir = vm_dir.as_ref()?;
+        tokio::time::timeout(
+            LIST_STATUS_TIMEOUT,
+            readiness::agent_status(&*manager.backend, vm_dir),
+        )
+        .await
+        .ok()?
+        .ok()
+    }))
+    .await;
+    for (vm, status) in vms.iter_mut().zip(statuses) {
+        vm.spawned_processes = status.map(|status| status.running_processes);
+    }
+
+    let running: Vec<String> = vms.iter().map(|vm| vm.vm_id.clone()).collect();
+    for (vm_id, booting) in manager.booting.lock().unwrap().iter() {
+        // A VM that just booted is in both maps for a moment
+        if running.contains(vm_id) {
+            continue;
+        }
+        let age = (Utc::now() - booting.started_at)
+            .to_std()
+            .unwrap_or_default();
+        vms.push(VmSummary {
+            vm_id: vm_id.clone(),
+            state: VmState::Booting,
+            profile: profile_of(&booting.spec),
+            owner: booting.spec.owner.clone(),
+            labels: booting.spec.labels.clone(),
+            cid: None,
+            pid: None,
+            vcpu_count: booting.spec.vcpu_count,
+            mem_size_mib: booting.spec.mem_size_mib,
+            created_at: booting.started_at,
+            last_activity: booting.started_at,
+            age_secs: age.as_secs(),
+            idle_secs: age.as_secs(),
+            expires_in_secs: None,
+            spawned_processes: None,
+        });
     }
-    false
+    vms.sort_by(|a, b| a.vm_id.cmp(&b.vm_id));
+    vms
 }
 
 pub(crate) fn terminate_process(pid: u32, signal: &str) -> Result<(), std::io::Error> {
@@ -0,0 +1,2 @@
+/// Firecracker API socket, relative to the VM directory.
+pub(crate) const API_SOCKET_FILE: &str = "firecracker.sock";
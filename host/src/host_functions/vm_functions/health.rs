@@ -0,0 +1,116 @@
+//! Health of running VMs: whether the VM process is still running and the vm-agent answers a
+//! ping.
+
+use super::firecracker;
+use super::lifetime::VmLifetime;
+use super::{VmInstance, VmManager, VmState};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// Longest the vm-agent may take to answer a health probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Verdict of a health check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VmHealth {
+    /// The VM process runs and the vm-agent answered.
+    Healthy,
+    /// The VM process runs but the vm-agent did not answer.
+    Unresponsive(String),
+    /// The VM process exited.
+    Dead(String),
+    /// There is no VM with this id.
+    NotFound,
+}
+
+/// Checks a VM and updates its state from the verdict.
+pub(crate) async fn check_vm_health(manager: &VmManager, vm_id: &str) -> VmHealth {
+    let vm_dir = {
+        let mut instances = manager.instances.lock().unwrap();
+        let Some(vm_instance) = instances.get_mut(vm_id) else {
+            return VmHealth::NotFound;
+        };
+        // Also reaps the process if it exited
+        match vm_instance.process.try_wait() {
+            Ok(Some(status)) => {
+                // The pid may be reused once the process is reaped, so it must not be signalled
+                vm_instance.pid = None;
+                let health = VmHealth::Dead(format!("VM process exited ({})", status));
+                update_state(vm_instance, &health);
+                return health;
+            }
+            Ok(None) => {}
+            Err(e) => log::debug!("Failed to check process of VM {}: {}", vm_id, e),
+        }
+        vm_instance.vm_dir.path().to_path_buf()
+    };
+
+    let health = manager.backend.check_health(&vm_dir, PROBE_TIMEOUT).await;
+    if let Some(vm_instance) = manager.instances.lock().unwrap().get_mut(vm_id) {
+        update_state(vm_instance, &health);
+    }
+    health
+}
+
+fn update_state(vm_instance: &mut VmInstance, health: &VmHealth) {
+    if vm_instance.state == VmState::Stopping {
+        return;
+    }
+    vm_instance.state = match health {
+        VmHealth::Healthy => VmState::Ready,
+        _ => VmState::Unhealthy,
+    };
+}
+
+/// Checks all running VMs every `interval` until the manager shuts down. Dead VMs are destroyed,
+/// keeping their output and the reason for `get_vm_logs` and later commands.
+pub(crate) fn start_health_checks(manager: Weak<VmManager>, interval: Duration) {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        loop {
+            thread::sleep(interval);
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+            if manager.shutdown_flag.load(Ordering::SeqCst) {
+                break;
+            }
+            let vm_ids: Vec<(String, VmState, Arc<VmLifetime>)> = manager
+                .instances
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|vm_instance| vm_instance.state != VmState::Stopping)
+                .map(|vm_instance| {
+                    (
+                        vm_instance.vm_id.clone(),
+                        vm_instance.state,
+                        vm_instance.lifetime.clone(),
+                    )
+                })
+                .collect();
+            for (vm_id, previous_state, lifetime) in vm_ids {
+                match rt.block_on(check_vm_health(&manager, &vm_id)) {
+                    VmHealth::Healthy if previous_state == VmState::Unhealthy => {
+                        log::info!("VM {} is healthy again", vm_id);
+                    }
+                    VmHealth::Unresponsive(reason) => {
+                        log::warn!("VM {} is unhealthy: {}", vm_id, reason);
+                    }
+                    VmHealth::Dead(reason) => reap_dead_vm(&manager, &vm_id, &lifetime, reason),
+                    VmHealth::Healthy | VmHealth::NotFound => {}
+                }
+            }
+        }
+    });
+}
+
+/// Destroys a VM whose process exited, unless it was replaced or is being destroyed meanwhile.
+fn reap_dead_vm(manager: &VmManager, vm_id: &str, lifetime: &Arc<VmLifetime>, reason: String) {
+    log::warn!("VM {} died, destroying it: {}", vm_id, reason);
+    if let Err(e) = firecracker::stop_vm(manager, vm_id, Some(lifetime), Some(reason)) {
+        log::debug!("Not destroying VM {}: {}", vm_id, e);
+    }
+}
@@ -7,11 +7,11 @@ use std::net::TcpStream;
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
-use super::{VmInstance, VsockRequest, VsockResponse};
+use super::lifetime::VmLifetime;
+use super::{VsockRequest, VsockResponse};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HttpProxyRequest {
@@ -29,96 +29,51 @@ pub struct HttpProxyResponse {
     pub error: Option<String>,
 }
 
-pub(crate) fn start_http_proxy_server_internal(
-    instances: Arc<Mutex<HashMap<String, VmInstance>>>,
+/// Serves the HTTP proxy of the VM `vm_id` on `listener` in the background until `stop` is set.
+/// The VM is not reaped while any of its connections is open.
+pub(crate) fn start_http_proxy(
+    listener: UnixListener,
+    vm_id: String,
     http_client: Arc<Client>,
-    shutdown_flag: Arc<AtomicBool>,
-    port: u32,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    lifetime: Arc<VmLifetime>,
+    stop: Arc<AtomicBool>,
+) {
     thread::spawn(move || {
-        log::debug!("Host proxy thread started for handling HTTP proxy requests.");
-        // Wait for a VM to be created to determine the socket path.
-        loop {
-            if shutdown_flag.load(Ordering::Relaxed) {
-                log::debug!("Shutdown flag detected, stopping server initialization.");
+        for stream in listener.incoming() {
+            if stop.load(Ordering::Relaxed) {
                 break;
             }
 
-            let socket_path = {
-                let instances_guard = instances.lock().unwrap();
-                if let Some((_, vm_instance)) = instances_guard.iter().next() {
-                    let base_path = vm_instance.temp_dir.path().join("vsock.sock");
-                    Some(format!("{}_{}", base_path.display(), port))
-                } else {
-                    None
+            match stream {
+                Ok(mut stream) => {
+                    let client = http_client.clone();
+                    let lifetime = lifetime.clone();
+                    let vm_id = vm_id.clone();
+                    lifetime.touch();
+                    thread::spawn(move || {
+                        // A long CONNECT tunnel keeps the VM alive until it closes
+                        let _open = lifetime.connection_open();
+                        if let Err(e) = handle_http_proxy_or_connect(&mut stream, &vm_id, client) {
+                            log::error!("[{}] Error handling HTTP proxy connection: {}", vm_id, e);
+                        }
+                    });
                 }
-            };
-
-            if let Some(socket_path) = socket_path {
-                log::debug!("Computed socket path: {}", socket_path);
-                log::debug!(
-                    "Attempting to start HTTP proxy Unix server at socket path: {}",
-                    socket_path
-                );
-                if let Err(e) = run_http_proxy_unix_server(
-                    &socket_path,
-                    http_client.clone(),
-                    shutdown_flag.clone(),
-                ) {
-                    log::error!("Failed to start HTTP proxy Unix server: {}", e);
-                    log::error!("HTTP proxy Unix server failed: {}", e);
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("[{}] Error accepting HTTP proxy connection: {}", vm_id, e);
                 }
-                break;
-            } else {
-                thread::sleep(Duration::from_millis(200));
             }
         }
+        log::debug!("[{}] HTTP proxy stopped", vm_id);
     });
-
-    Ok(())
-}
-
-fn run_http_proxy_unix_server(
-    socket_path: &str,
-    http_client: Arc<Client>,
-    shutdown_flag: Arc<AtomicBool>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let _ = std::fs::remove_file(socket_path);
-
-    let listener = UnixListener::bind(socket_path)?;
-    log::info!("HTTP Proxy listening on Unix socket: {}", socket_path);
-
-    listener.set_nonblocking(true)?;
-
-    for stream in listener.incoming() {
-        if shutdown_flag.load(Ordering::Relaxed) {
-            break;
-        }
-
-        match stream {
-            Ok(mut stream) => {
-                let client = http_client.clone();
-                thread::spawn(move || {
-                    if let Err(e) = handle_http_proxy_or_connect(&mut stream, client) {
-                        log::error!("Error handling HTTP proxy connection: {}", e);
-                    }
-                });
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                thread::sleep(Duration::from_millis(100));
-                continue;
-            }
-            Err(e) => {
-                log::error!("Error accepting HTTP proxy connection: {}", e);
-            }
-        }
-    }
-
-    Ok(())
 }
 
 fn handle_http_proxy_or_connect(
     stream: &mut UnixStream,
+    vm_id: &str,
     http_client: Arc<Client>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Peek at the first few bytes to determine if this is a CONNECT or JSON request
@@ -141,19 +96,19 @@ fn handle_http_proxy_or_connect(
             return Ok(());
         }
         let target = parts[1];
-        log::debug!("CONNECT method received. Target: {}", target);
+        log::debug!("[{}] CONNECT method received. Target: {}", vm_id, target);
 
         // Connect to the target server
         match TcpStream::connect(target) {
             Ok(mut target_stream) => {
                 // Send 200 Connection Established
-                log::debug!("Connected to target {}", target);
+                log::debug!("[{}] Connected to target {}", vm_id, target);
                 let _ = stream.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n");
                 // Relay data in both directions
                 relay_bidirectional(stream, &mut target_stream)?;
             }
             Err(e) => {
-                log::error!("Failed to connect to target {}: {}", target, e);
+                log::error!("[{}] Failed to connect to target {}: {}", vm_id, target, e);
                 let _ = stream.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n");
             }
         }
@@ -170,7 +125,7 @@ fn handle_http_proxy_or_connect(
                 buffer.extend_from_slice(&chunk[..n]);
                 if let Ok(vsock_request) = serde_json::from_slice::<VsockRequest>(&buffer) {
                     if let VsockRequest::HttpProxy(proxy_request) = vsock_request {
-                        let response = execute_http_request(proxy_request, &http_client);
+                        let response = execute_http_request(proxy_request, vm_id, &http_client);
                         let vsock_response = VsockResponse::HttpProxy(response);
                         let response_json = serde_json::to_string(&vsock_response)?;
                         stream.write_all(response_json.as_bytes())?;
@@ -180,7 +135,11 @@ fn handle_http_proxy_or_connect(
                 }
             }
             Err(e) => {
-                log::error!("Error reading from HTTP proxy unix stream: {}", e);
+                log::error!(
+                    "[{}] Error reading from HTTP proxy unix stream: {}",
+                    vm_id,
+                    e
+                );
                 break;
             }
         }
@@ -190,6 +149,7 @@ fn handle_http_proxy_or_connect(
 
 fn execute_http_request(
     proxy_request: HttpProxyRequest,
+    vm_id: &str,
     http_client: &Client,
 ) -> HttpProxyResponse {
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -199,7 +159,8 @@ fn execute_http_request(
 
     rt.block_on(async {
         log::debug!(
-            "Executing HTTP request: {} {}",
+            "[{}] Executing HTTP request: {} {}",
+            vm_id,
             proxy_request.method,
             proxy_request.url
         );
@@ -232,7 +193,11 @@ fn execute_http_request(
                         headers.insert(name.to_string(), value_str.to_string());
                     }
                 }
-                log::debug!("Received response with status: {}", response.status());
+                log::debug!(
+                    "[{}] Received response with status: {}",
+                    vm_id,
+                    response.status()
+                );
                 match response.bytes().await {
                     Ok(body_bytes) => HttpProxyResponse {
                         status_code,
@@ -241,7 +206,7 @@ fn execute_http_request(
                         error: None,
                     },
                     Err(e) => {
-                        log::error!("HTTP request failed: {}", e);
+                        log::error!("[{}] HTTP request failed: {}", vm_id, e);
                         HttpProxyResponse {
                             status_code: 500,
                             headers: HashMap::new(),
@@ -252,7 +217,7 @@ fn execute_http_request(
                 }
             }
             Err(e) => {
-                log::error!("HTTP request failed: {}", e);
+                log::error!("[{}] HTTP request failed: {}", vm_id, e);
                 HttpProxyResponse {
                     status_code: 500,
                     headers: HashMap::new(),
@@ -0,0 +1,269 @@
+//! Optional jailer mode: Firecracker is started through the Firecracker jailer, which chroots it
+//! into the VM directory, drops to a dedicated uid/gid and puts it in its own cgroup v2 and
+//! network namespace. The host must run as root.
+
+use super::vm_spec::ResolvedVmSpec;
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::chown;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Prefix of the network namespaces created for jailed VMs.
+const NETNS_PREFIX: &str = "hla-";
+
+/// Period of the `cpu.max` limit.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// Where cgroup v2 is mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Jailer settings from `vm.jailer` in the host configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JailerConfig {
+    pub enabled: bool,
+    /// Jailer binary, looked up in `PATH` like Firecracker.
+    pub jailer_path: PathBuf,
+    /// User and group Firecracker runs as.
+    pub uid: u32,
+    pub gid: u32,
+    /// Directory the jails are created in, as `<chroot_base_dir>/firecracker/<id>/root`.
+    pub chroot_base_dir: PathBuf,
+    /// Cgroup under which each VM gets its own.
+    pub parent_cgroup: String,
+    /// CPU time each vCPU may use, in percent of one core.
+    pub cpu_percent_per_vcpu: u32,
+    /// Memory Firecracker may use on top of the guest memory.
+    pub memory_overhead_mib: u32,
+    /// Run each VM in a new, empty network namespace.
+    pub new_netns: bool,
+}
+
+impl Default for JailerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            jailer_path: PathBuf::from("jailer"),
+            uid: 10000,
+            gid: 10000,
+            chroot_base_dir: PathBuf::from("/srv/hyperlight-agents"),
+            parent_cgroup: "hyperlight-agents".to_string(),
+            cpu_percent_per_vcpu: 100,
+            memory_overhead_mib: 128,
+            new_netns: true,
+        }
+    }
+}
+
+/// What the registry keeps of a jail, so a restarted host can remove it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JailRecord {
+    pub id: String,
+    pub dir: PathBuf,
+    pub netns: Option<String>,
+    pub parent_cgroup: String,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// The jail of one VM. Its cgroup and network namespace are removed by `release`, the jail
+/// directory when the jail is dropped.
+pub(crate) struct Jail {
+    id: String,
+    config: JailerConfig,
+    /// `<chroot_base_dir>/<exec file name>/<id>`
+    dir: PathBuf,
+    cpu_max: String,
+    memory_max: u64,
+    netns: Option<String>,
+}
+
+impl Jail {
+    /// Creates the jail directory, owned by the jail user, and the network namespace.
+    pub(crate) fn create(
+        config: &JailerConfig,
+        vm_id: &str,
+        firecracker_bin: &Path,
+        spec: &ResolvedVmSpec,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let id = jail_id(vm_id);
+        let exec_name = firecracker_bin
+            .file_name()
+            .ok_or("Firecracker binary has no file name")?;
+        let dir = config.chroot_base_dir.join(exec_name).join(&id);
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root)?;
+        chown(&root, Some(config.uid), Some(config.gid))?;
+
+        let quota =
+            CPU_PERIOD_US * spec.vcpu_count as u64 * config.cpu_percent_per_vcpu as u64 / 100;
+        let mut jail = Self {
+            id: id.clone(),
+            config: config.clone(),
+            dir,
+            cpu_max: format!("{} {}", quota, CPU_PERIOD_US),
+            memory_max: (spec.mem_size_mib as u64 + config.memory_overhead_mib as u64) << 20,
+            netns: None,
+        };
+        if config.new_netns {
+            let netns = format!("{}{}", NETNS_PREFIX, id);
+            run("ip", &["netns", "add", &netns])?;
+            jail.netns = Some(netns);
+        }
+        Ok(jail)
+    }
+
+    /// The jail of a VM reattached from the registry, which can be released and removed but not
+    /// used to start Firecracker.
+    pub(crate) fn adopt(record: &JailRecord) -> Self {
+        Self {
+            id: record.id.clone(),
+            config: JailerConfig {
+                enabled: true,
+                uid: record.uid,
+                gid: record.gid,
+                parent_cgroup: record.parent_cgroup.clone(),
+                ..JailerConfig::default()
+            },
+            dir: record.dir.clone(),
+            cpu_max: String::new(),
+            memory_max: 0,
+            netns: record.netns.clone(),
+        }
+    }
+
+    pub(crate) fn record(&self) -> JailRecord {
+        JailRecord {
+            id: self.id.clone(),
+            dir: self.dir.clone(),
+            netns: self.netns.clone(),
+            parent_cgroup: self.config.parent_cgroup.clone(),
+            uid: self.config.uid,
+            gid: self.config.gid,
+        }
+    }
+
+    /// The chroot, which is the VM directory.
+    pub(crate) fn root(&self) -> PathBuf {
+        self.dir.join("root")
+    }
+
+    pub(crate) fn owner(&self) -> (u32, u32) {
+        (self.config.uid, self.config.gid)
+    }
+
+    /// Makes an image available in the jail under `name`, hard linked or copied if it is on
+    /// another file system. Returns its path inside the jail.
+    pub(crate) fn add_image(
+        &self,
+        image: &Path,
+        name: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let target = self.root().join(name);
+        if std::fs::hard_link(image, &target).is_err() {
+            std::fs::copy(image, &target)?;
+        }
+        Ok(name.to_string())
+    }
+
+    /// Makes a named volume available in the jail under `name`. Only hard linked, as writes to a
+    /// copy would be lost, so volumes must be on the file system of the jails.
+    pub(crate) fn add_volume(
+        &self,
+        image: &Path,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        std::fs::hard_link(image, self.root().join(name)).map_err(|e| {
+            format!(
+                "Failed to link volume {} into the jail, is it on another file system? {}",
+                image.display(),
+                e
+            )
+        })?;
+        Ok(())
+    }
+
+    /// The jailer command for `firecracker_bin`; Firecracker's own arguments follow.
+    pub(crate) fn command(&self, firecracker_bin: &Path) -> Command {
+        let mut cmd = Command::new(&self.config.jailer_path);
+        cmd.arg("--id")
+            .arg(&self.id)
+            .arg("--exec-file")
+            .arg(firecracker_bin)
+            .arg("--uid")
+            .arg(self.config.uid.to_string())
+            .arg("--gid")
+            .arg(self.config.gid.to_string())
+            .arg("--chroot-base-dir")
+            .arg(&self.config.chroot_base_dir)
+            .arg("--cgroup-version")
+            .arg("2")
+            .arg("--parent-cgroup")
+            .arg(&self.config.parent_cgroup)
+            .arg("--cgroup")
+            .arg(format!("cpu.max={}", self.cpu_max))
+            .arg("--cgroup")
+            .arg(format!("memory.max={}", self.memory_max));
+        if let Some(netns) = &self.netns {
+            cmd.arg("--netns").arg(format!("/var/run/netns/{}", netns));
+        }
+        cmd.arg("--");
+        cmd
+    }
+
+    /// Removes the cgroup and network namespace. Must be called after Firecracker has exited.
+    pub(crate) fn release(&mut self) {
+        let cgroup = Path::new(CGROUP_ROOT)
+            .join(&self.config.parent_cgroup)
+            .join(&self.id);
+        if cgroup.exists() {
+            if let Err(e) = std::fs::remove_dir(&cgroup) {
+                log::warn!("Failed to remove cgroup {}: {}", cgroup.display(), e);
+            }
+        }
+        if let Some(netns) = self.netns.take() {
+            if let Err(e) = run("ip", &["netns", "delete", &netns]) {
+                log::warn!("Failed to delete network namespace {}: {}", netns, e);
+            }
+        }
+    }
+}
+
+impl Drop for Jail {
+    fn drop(&mut self) {
+        self.release();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Gives the jail user ownership of a file the host created for a jailed Firecracker.
+pub(crate) fn grant(path: &Path, owner: Option<(u32, u32)>) -> std::io::Result<()> {
+    match owner {
+        Some((uid, gid)) => chown(path, Some(uid), Some(gid)),
+        None => Ok(()),
+    }
+}
+
+/// Jail ids may only contain alphanumerics and hyphens, and at most 64 of them.
+fn jail_id(vm_id: &str) -> String {
+    let name: String = vm_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(27)
+        .collect();
+    format!("{}-{}", name, uuid::Uuid::new_v4().simple())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Time-to-live and idle timeouts of VMs, and the reaper that destroys VMs once either passes.
+//!
+//! A VM is active while it receives commands or proxy traffic, or while one of its spawned
+//! processes is running. Spawned processes are only asked for once the idle timeout has passed.
+//! A VM is never reaped while a foreground command runs in it or one of its proxy connections,
+//! such as a CONNECT tunnel, is open, whatever its TTL.
+
+use super::firecracker;
+use super::readiness;
+use super::vm_spec::VmConfig;
+use super::VmManager;
+use chrono::{DateTime, Utc};
+use hyperlight_agents_common::VmSpec;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often VMs are checked for an expired TTL or idle timeout.
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest the vm-agent may take to report its running processes.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why the reaper destroyed a VM.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ReapReason {
+    Ttl(Duration),
+    Idle(Duration),
+}
+
+impl fmt::Display for ReapReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReapReason::Ttl(ttl) => write!(f, "TTL of {}s expired", ttl.as_secs()),
+            ReapReason::Idle(timeout) => write!(f, "idle for {}s", timeout.as_secs()),
+        }
+    }
+}
+
+/// Creation time, limits and last activity of a VM.
+pub(crate) struct VmLifetime {
+    created_at: Instant,
+    created_at_utc: DateTime<Utc>,
+    ttl: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    last_activity: Mutex<Instant>,
+    commands_in_flight: AtomicUsize,
+    connections_open: AtomicUsize,
+}
+
+/// Keeps a VM from expiring while a foreground command runs or a proxy connection is open.
+/// Dropping it records activity.
+pub(crate) struct InFlight<'a> {
+    lifetime: &'a VmLifetime,
+    count: &'a AtomicUsize,
+}
+
+impl<'a> InFlight<'a> {
+    fn new(lifetime: &'a VmLifetime, count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        lifetime.touch();
+        Self { lifetime, count }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        // Touch first so the VM never looks idle between the two
+        self.lifetime.touch();
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl VmLifetime {
+    /// Takes the limits from `spec`, falling back to the host defaults. A limit of 0 disables it.
+    pub(crate) fn new(spec: &VmSpec, config: &VmConfig) -> Self {
+        let limit = |secs: Option<u64>| secs.filter(|&secs| secs > 0).map(Duration::from_secs);
+        let now = Instant::now();
+        Self {
+            created_at: now,
+            created_at_utc: Utc::now(),
+            ttl: limit(spec.ttl_secs.or(config.ttl_secs)),
+            idle_timeout: limit(spec.idle_timeout_secs.or(config.idle_timeout_secs)),
+            last_activity: Mutex::new(now),
+            commands_in_flight: AtomicUsize::new(0),
+            connections_open: AtomicUsize::new(0),
+        }
+    }
+
+    /// The lifetime of a VM reattached after a host restart, which keeps its creation time. Its
+    /// idle time starts over.
+    pub(crate) fn resumed(spec: &VmSpec, config: &VmConfig, created_at: DateTime<Utc>) -> Self {
+        let mut lifetime = Self::new(spec, config);
+        let age = (Utc::now() - created_at).to_std().unwrap_or_default();
+        if let Some(created_at) = lifetime.created_at.checked_sub(age) {
+            lifetime.created_at = created_at;
+        }
+        lifetime.created_at_utc = created_at;
+        lifetime
+    }
+
+    /// Records activity, restarting the idle timeout.
+    pub(crate) fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Marks a foreground command as running until the returned guard is dropped.
+    pub(crate) fn command_in_flight(&self) -> InFlight<'_> {
+        InFlight::new(self, &self.commands_in_flight)
+    }
+
+    /// Marks a proxy connection as open until the returned guard is dropped.
+    pub(crate) fn connection_open(&self) -> InFlight<'_> {
+        InFlight::new(self, &self.connections_open)
+    }
+
+    pub(crate) fn created_at(&self) -> DateTime<Utc> {
+        self.created_at_utc
+    }
+
+    pub(crate) fn last_activity_at(&self) -> DateTime<Utc> {
+        Utc::now() - chrono::Duration::from_std(self.idle()).unwrap_or_default()
+    }
+
+    pub(crate) fn age(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+
+    pub(crate) fn idle(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    /// Time until the VM is reaped unless it becomes active, `None` without limits.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let ttl_left = self.ttl.map(|ttl| ttl.saturating_sub(self.age()));
+        let idle_left = self
+            .idle_timeout
+            .map(|timeout| timeout.saturating_sub(self.idle()));
+        match (ttl_left, idle_left) {
+            (Some(ttl_left), Some(idle_left)) => Some(ttl_left.min(idle_left)),
+            (ttl_left, idle_left) => ttl_left.or(idle_left),
+        }
+    }
+
+    fn expired(&self) -> Option<ReapReason> {
+        if self.commands_in_flight.load(Ordering::SeqCst) > 0
+            || self.connections_open.load(Ordering::SeqCst) > 0
+        {
+            return None;
+        }
+        if let Some(ttl) = self.ttl.filter(|&ttl| self.age() >= ttl) {
+            return Some(ReapReason::Ttl(ttl));
+        }
+        self.idle_timeout
+            .filter(|&timeout| self.idle() >= timeout)
+            .map(ReapReason::Idle)
+    }
+}
+
+/// Destroys expired VMs in the background until the manager shuts down.
+pub(crate) fn start_reaper(manager: Weak<VmManager>) {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        loop {
+            thread::sleep(REAP_INTERVAL);
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+            if manager.shutdown_flag.load(Ordering::SeqCst) {
+                break;
+            }
+            let candidates: Vec<(String, Arc<VmLifetime>, PathBuf)> = manager
+                .instances
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, vm_instance)| vm_instance.lifetime.expired().is_some())
+                .map(|(vm_id, vm_instance)| {
+                    (
+                        vm_id.clone(),
+                        vm_instance.lifetime.clone(),
+                        vm_instance.vm_dir.path().to_path_buf(),
+                    )
+                })
+                .collect();
+            for (vm_id, lifetime, vm_dir) in candidates {
+                rt.block_on(reap(&manager, &vm_id, &lifetime, vm_dir));
+            }
+        }
+    });
+}
+
+async fn reap(manager: &VmManager, vm_id: &str, lifetime: &Arc<VmLifetime>, vm_dir: PathBuf) {
+    if let Some(ReapReason::Idle(_)) = lifetime.expired() {
+        match tokio::time::timeout(
+            STATUS_TIMEOUT,
+            readiness::agent_status(&*manager.backend, &vm_dir),
+        )
+        .await
+        {
+            Ok(Ok(status)) if status.running_processes > 0 => {
+                lifetime.touch();
+                return;
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => log::debug!("Status request to idle VM {} failed: {}", vm_id, e),
+            Err(_) => log::debug!("Status request to idle VM {} timed out", vm_id),
+        }
+    }
+    // Activity may have arrived while the agent was asked
+    let Some(reason) = lifetime.expired() else {
+        return;
+    };
+
+    log::info!("Destroying VM {}: {}", vm_id, reason);
+    if let Err(e) = firecracker::stop_vm(manager, vm_id, Some(lifetime), Some(reason.to_string())) {
+        log::debug!("Not destroying VM {}: {}", vm_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A lifetime that has expired unless something is in flight.
+    fn expired_lifetime() -> VmLifetime {
+        let now = Instant::now();
+        VmLifetime {
+            created_at: now,
+            created_at_utc: Utc::now(),
+            ttl: Some(Duration::ZERO),
+            idle_timeout: Some(Duration::ZERO),
+            last_activity: Mutex::new(now),
+            commands_in_flight: AtomicUsize::new(0),
+            connections_open: AtomicUsize::new(0),
+        }
+    }
+
+    #[test]
+    fn command_in_flight_keeps_the_vm_from_expiring() {
+        let lifetime = expired_lifetime();
+        assert!(lifetime.expired().is_some());
+
+        let first = lifetime.command_in_flight();
+        let second = lifetime.command_in_flight();
+        assert!(lifetime.expired().is_none());
+        drop(first);
+        assert!(lifetime.expired().is_none());
+        drop(second);
+        assert!(lifetime.expired().is_some());
+    }
+
+    #[test]
+    fn open_connection_keeps_the_vm_from_expiring() {
+        let lifetime = expired_lifetime();
+        let tunnel = lifetime.connection_open();
+        let command = lifetime.command_in_flight();
+        drop(command);
+        assert!(lifetime.expired().is_none());
+        drop(tunnel);
+        assert!(lifetime.expired().is_some());
+    }
+}
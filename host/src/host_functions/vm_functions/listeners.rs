@@ -0,0 +1,76 @@
+//! The Unix sockets the vm-agent of a VM connects to for its HTTP proxy and to forward its log.
+//! Each VM gets its own, bound in its directory where Firecracker's hybrid vsock forwards guest
+//! connections, so every request and log line is attributed to the VM it came from.
+
+use super::http_proxy;
+use super::lifetime::VmLifetime;
+use super::log_listener;
+use super::vm_dir::VmDir;
+use super::{VmManager, VSOCK_SOCKET_FILE};
+use hyperlight_agents_common::{VM_AGENT_LOG_PORT, VM_AGENT_PROXY_PORT};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The proxy and log listeners of a VM, stopped and their sockets removed when dropped.
+pub struct VmListeners {
+    stop: Arc<AtomicBool>,
+    sockets: Vec<PathBuf>,
+}
+
+impl VmListeners {
+    /// Binds the sockets of the VM `vm_id` and serves them in the background.
+    pub(crate) fn start(
+        manager: &VmManager,
+        vm_id: &str,
+        vm_dir: &VmDir,
+        lifetime: &Arc<VmLifetime>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut listeners = Self {
+            stop: Arc::new(AtomicBool::new(false)),
+            sockets: Vec::new(),
+        };
+        http_proxy::start_http_proxy(
+            listeners.bind(vm_dir, VM_AGENT_PROXY_PORT)?,
+            vm_id.to_string(),
+            manager.http_client.clone(),
+            lifetime.clone(),
+            listeners.stop.clone(),
+        );
+        log_listener::start_log_listener(
+            listeners.bind(vm_dir, VM_AGENT_LOG_PORT)?,
+            vm_id.to_string(),
+            listeners.stop.clone(),
+        );
+        Ok(listeners)
+    }
+
+    fn bind(
+        &mut self,
+        vm_dir: &VmDir,
+        port: u32,
+    ) -> Result<UnixListener, Box<dyn std::error::Error + Send + Sync>> {
+        let path = vm_dir
+            .path()
+            .join(format!("{}_{}", VSOCK_SOCKET_FILE, port));
+        // Left behind by the previous run for a reattached VM
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        self.sockets.push(path.clone());
+        // A jailed Firecracker connects as the jail user
+        vm_dir.grant(&path)?;
+        // Accepted in a polling loop so the listener notices when it is stopped
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }
+}
+
+impl Drop for VmListeners {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for socket in &self.sockets {
+            let _ = std::fs::remove_file(socket);
+        }
+    }
+}
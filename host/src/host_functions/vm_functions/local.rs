@@ -0,0 +1,126 @@
+//! Local backend: runs the vm-agent as a plain child process in a temporary directory, talking to
+//! it over Unix sockets instead of vsock. Commands run on the host as the host user, without any
+//! isolation, and the kernel and rootfs of the spec are not used. Meant for machines without KVM,
+//! such as CI runners.
+
+use super::backend::VmBackend;
+use super::firecracker::{BootOrigin, BootedVm};
+use super::readiness;
+use super::registry::VmProcess;
+use super::vm_dir::VmDir;
+use super::vm_logs::{RetiredVmLogs, VmLogs};
+use super::vm_spec::ResolvedVmSpec;
+use super::VmManager;
+use async_trait::async_trait;
+use hyperlight_agents_common::{VM_AGENT_HTTP_PROXY_ADDR_ENV, VM_AGENT_UNIX_DIR_ENV};
+use serde::Deserialize;
+use std::fs::File;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings of the local backend from `vm.local` in the host configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LocalBackendConfig {
+    /// vm-agent binary. The static one `build-vm-agent` puts next to the images runs on the host.
+    pub agent_path: PathBuf,
+}
+
+impl Default for LocalBackendConfig {
+    fn default() -> Self {
+        Self {
+            agent_path: PathBuf::from("firecracker/vm-agent"),
+        }
+    }
+}
+
+pub(crate) struct LocalBackend {
+    config: LocalBackendConfig,
+}
+
+impl LocalBackend {
+    pub(crate) fn new(config: LocalBackendConfig) -> Self {
+        Self { config }
+    }
+
+    fn agent_binary(&self) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+        // Resolved up front, as the process runs in the VM directory
+        self.config.agent_path.canonicalize().map_err(|e| {
+            format!(
+                "vm-agent not found at {}: {}",
+                self.config.agent_path.display(),
+                e
+            )
+            .into()
+        })
+    }
+}
+
+#[async_trait]
+impl VmBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn check_available(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.agent_binary().map(|_| ())
+    }
+
+    async fn start_vm(
+        &self,
+        manager: &VmManager,
+        vm_id: &str,
+        _spec: &ResolvedVmSpec,
+    ) -> Result<BootedVm, Box<dyn std::error::Error + Send + Sync>> {
+        let agent_bin = self.agent_binary()?;
+        // Agents do not use their CID, but it is allocated like for any VM
+        let cid = manager.cids.allocate()?;
+        let dir = VmDir::temp()?;
+        let vm_dir = &dir.path().to_path_buf();
+        let logs = Arc::new(VmLogs::new(vm_dir, manager.config.log_buffer_lines)?);
+        let ready_listener = readiness::bind_ready_listener(vm_dir)?;
+
+        let mut child = Command::new(agent_bin)
+            .current_dir(vm_dir)
+            .env(VM_AGENT_UNIX_DIR_ENV, vm_dir)
+            // Agents share the host's network, so each proxy takes a free port
+            .env(VM_AGENT_HTTP_PROXY_ADDR_ENV, "127.0.0.1:0")
+            .stdin(File::open("/dev/null")?)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // Outside the host's process group, so the agent survives a host killed from its
+            // terminal and can be reattached
+            .process_group(0)
+            .spawn()?;
+        logs.capture_process(&mut child, vm_dir)?;
+
+        let boot_timeout = Duration::from_secs(manager.config.boot_timeout_secs);
+        match readiness::wait_for_ready(ready_listener, &mut child, vm_dir, &logs, boot_timeout)
+            .await
+        {
+            Ok(ready) => Ok(BootedVm {
+                vm_dir: dir,
+                logs,
+                child,
+                cid,
+                volumes: Vec::new(),
+                ready,
+                origin: BootOrigin::ColdBoot,
+            }),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                manager.retire_logs(vm_id, RetiredVmLogs::new(logs, dir));
+                Err(format!("VM {} failed to boot: {}", vm_id, e).into())
+            }
+        }
+    }
+
+    fn stop_vm(&self, process: &mut VmProcess, _vm_dir: &mut VmDir) {
+        process.kill();
+        process.wait();
+    }
+}
@@ -1,100 +1,44 @@
-use std::collections::HashMap;
 use std::io::Read;
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-/// Starts the log listener server lazily, waiting for a VM to exist to determine the socket path.
-/// This matches the pattern used by the HTTP proxy server.
-pub(crate) fn start_log_listener_server(
-    instances: Arc<Mutex<HashMap<String, super::VmInstance>>>,
-    shutdown_flag: Arc<AtomicBool>,
-    port: u32,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Logs the lines the vm-agent of the VM `vm_id` sends to `listener`, in the background until
+/// `stop` is set.
+pub(crate) fn start_log_listener(listener: UnixListener, vm_id: String, stop: Arc<AtomicBool>) {
     thread::spawn(move || {
-        loop {
-            if shutdown_flag.load(Ordering::Relaxed) {
+        for stream in listener.incoming() {
+            if stop.load(Ordering::Relaxed) {
                 break;
             }
 
-            let (socket_path, vm_id_opt) = {
-                let instances_guard = instances.lock().unwrap();
-                if let Some((vm_id, vm_instance)) = instances_guard.iter().next() {
-                    let base_path = vm_instance.temp_dir.path().join("vsock.sock");
-                    (
-                        Some(format!("{}_{}", base_path.display(), port)),
-                        Some(vm_id.clone()),
-                    )
-                } else {
-                    (None, None)
+            match stream {
+                Ok(mut stream) => {
+                    let vm_id = vm_id.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_log_listener_unix_connection(&mut stream, &vm_id) {
+                            log::error!(
+                                "[{}] Error handling log listener connection: {}",
+                                vm_id,
+                                e
+                            );
+                        }
+                    });
                 }
-            };
-
-            if let (Some(socket_path), Some(vm_id)) = (socket_path, vm_id_opt) {
-                if let Err(e) =
-                    run_log_listener_unix_server(&socket_path, &vm_id, shutdown_flag.clone())
-                {
-                    log::error!("[host] Log listener Unix server failed: {}", e);
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // No incoming connection, sleep and check for shutdown again.
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("[{}] Error accepting log listener connection: {}", vm_id, e);
                 }
-                // Once we've started (or failed), break the loop.
-                break;
-            } else {
-                // No VMs yet, wait a bit before checking again.
-                thread::sleep(Duration::from_millis(200));
             }
         }
+        log::debug!("[{}] Log listener stopped", vm_id);
     });
-
-    Ok(())
-}
-
-/// Runs the Unix socket server for the log listener.
-fn run_log_listener_unix_server(
-    socket_path: &str,
-    vm_id: &str,
-    shutdown_flag: Arc<AtomicBool>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Clean up any old socket file.
-    let _ = std::fs::remove_file(socket_path);
-
-    let listener = UnixListener::bind(socket_path)?;
-    log::debug!(
-        "[host] Log Listener listening on Unix socket: {}",
-        socket_path
-    );
-
-    // Set a timeout so the accept loop doesn't block forever, allowing shutdown check.
-    listener.set_nonblocking(true)?;
-
-    for stream in listener.incoming() {
-        if shutdown_flag.load(Ordering::Relaxed) {
-            break;
-        }
-
-        match stream {
-            Ok(mut stream) => {
-                let vm_id = vm_id.to_string();
-                thread::spawn(move || {
-                    if let Err(e) = handle_log_listener_unix_connection(&mut stream, &vm_id) {
-                        log::error!("[host] Error handling log listener connection: {}", e);
-                    }
-                });
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No incoming connection, sleep and check for shutdown again.
-                thread::sleep(Duration::from_millis(100));
-                continue;
-            }
-            Err(e) => {
-                log::error!("[host] Error accepting log listener connection: {}", e);
-                // Potentially break here if the listener is in an unrecoverable state.
-            }
-        }
-    }
-
-    Ok(())
 }
 
 /// Handles an individual connection to the log listener.
@@ -130,7 +74,11 @@ fn handle_log_listener_unix_connection(
                 }
             }
             Err(e) => {
-                log::error!("[host] Error reading from log listener unix stream: {}", e);
+                log::error!(
+                    "[{}] Error reading from log listener unix stream: {}",
+                    vm_id,
+                    e
+                );
                 break;
             }
         }
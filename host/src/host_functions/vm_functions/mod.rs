@@ -1,40 +1,161 @@
+pub mod backend;
+pub mod cid;
+pub mod command_stream;
+pub mod files;
 pub mod firecracker;
+pub mod firecracker_api;
+pub mod health;
 pub mod http_proxy;
+pub mod jailer;
+pub mod lifetime;
+pub mod listeners;
+pub mod local;
 pub mod log_listener;
-
+pub mod readiness;
+pub mod registry;
+pub mod sync;
+pub mod vm_dir;
+pub mod vm_logs;
+pub mod vm_spec;
+pub mod volumes;
+pub mod warm_pool;
+
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use tempfile::TempDir;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use vsock::{VsockListener, VsockStream};
 
+/// Firecracker's vsock socket, relative to the VM directory.
+pub(crate) const VSOCK_SOCKET_FILE: &str = "vsock.sock";
+
+/// Where a VM is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VmState {
+    /// `create_vm` is still starting the VM.
+    Booting,
+    Ready,
+    /// Firecracker exited, or the vm-agent did not answer the last command or health check.
+    Unhealthy,
+    /// Being destroyed.
+    Stopping,
+}
+
+/// A VM as reported by `list_vms`.
+#[derive(Debug, Clone, Serialize)]
+pub struct VmSummary {
+    pub vm_id: String,
+    pub state: VmState,
+    pub profile: String,
+    pub owner: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// Not known while booting.
+    pub cid: Option<u32>,
+    pub pid: Option<u32>,
+    pub vcpu_count: u8,
+    pub mem_size_mib: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    pub age_secs: u64,
+    /// Seconds since the last command, running spawned process or proxy request.
+    pub idle_secs: u64,
+    /// Seconds until the VM is destroyed for its TTL or idle timeout, `None` without either.
+    pub expires_in_secs: Option<u64>,
+    /// Spawned processes still running, `None` if the vm-agent was not asked or did not answer.
+    pub spawned_processes: Option<usize>,
+}
+
 // Structs used across the module
 pub struct VmInstance {
     pub vm_id: String,
-    pub cid: u32,
+    pub cid: CidLease,
     pub pid: Option<u32>,
-    pub temp_dir: TempDir,
+    /// Firecracker or the local agent, waited on when the VM is stopped.
+    pub process: VmProcess,
+    // Declared before vm_dir so the FIFO readers are stopped while the FIFOs still exist
+    pub logs: Arc<VmLogs>,
+    pub vm_dir: VmDir,
+    /// Named volumes attached to the VM.
+    pub volumes: Vec<VolumeLease>,
     pub command_sender: mpsc::Sender<VmCommand>,
     pub result_receiver: Arc<Mutex<HashMap<String, mpsc::Sender<VmCommandResult>>>>,
+    /// Where the output of streamed foreground commands goes, by command id.
+    pub output_senders: Arc<Mutex<HashMap<String, UnboundedSender<CommandOutput>>>>,
     pub memfd_rootfs: Option<memfd::Memfd>,
     pub rootfs_symlink: Option<PathBuf>,
+    pub lifetime: Arc<VmLifetime>,
+    /// The VM's HTTP proxy and log sockets.
+    pub listeners: VmListeners,
+    pub state: VmState,
+    pub spec: VmSpec,
+}
+
+/// A command to run in a VM, as passed to `execute_vm_command` and `spawn_command`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VmCommandRequest {
+    /// Program executed with `args` as its arguments, or with `shell` a command line run through
+    /// `sh -c` with `args` as its positional parameters.
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: Option<String>,
+    /// Seconds after which the command is killed, `DEFAULT_COMMAND_TIMEOUT_SECS` if not set.
+    pub timeout_seconds: Option<u64>,
+    /// Set for the command in addition to the VM's environment.
+    pub env: BTreeMap<String, String>,
+    /// Written to the command's standard input.
+    pub stdin: Option<String>,
+    pub shell: bool,
 }
 
-use hyperlight_agents_common::{VmCommand, VmCommandMode, VmCommandResult};
+/// A VM `create_vm` is starting, listed before it becomes an instance.
+pub(crate) struct BootingVm {
+    pub spec: VmSpec,
+    pub started_at: DateTime<Utc>,
+}
+
+use backend::VmBackend;
+use cid::{CidAllocator, CidLease};
+use command_stream::VmCommandStream;
+use files::VmFile;
+use health::VmHealth;
+use hyperlight_agents_common::{
+    AgentReady, AgentStatus, CommandOutput, FileCommitRequest, FileReadRequest, FileResponse,
+    FileWriteRequest, SyncResponse, SyncUnpackRequest, VmCommand, VmCommandMode, VmCommandResult,
+    VmSpec,
+};
+use lifetime::VmLifetime;
+use listeners::VmListeners;
+use registry::{VmProcess, VmRegistry};
+use vm_dir::VmDir;
+use vm_logs::{RetiredVmLogs, VmLogStream, VmLogs};
+use vm_spec::VmConfig;
+use volumes::{VolumeLease, VolumeStore, VolumeSummary};
+use warm_pool::WarmPool;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub(crate) enum VsockRequest {
     Command(VmCommand),
     HttpProxy(http_proxy::HttpProxyRequest),
+    Ping,
+    Status,
+    FileStat { path: String },
+    FileRead(FileReadRequest),
+    FileWrite(FileWriteRequest),
+    FileCommit(FileCommitRequest),
+    SyncPrepare { target: String },
+    SyncUnpack(SyncUnpackRequest),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,48 +163,77 @@ pub(crate) enum VsockRequest {
 pub(crate) enum VsockResponse {
     Command(serde_json::Value),
     HttpProxy(http_proxy::HttpProxyResponse),
+    Pong(AgentReady),
+    Status(AgentStatus),
+    File(FileResponse),
+    Sync(SyncResponse),
 }
 
 // The main VmManager struct
 pub struct VmManager {
     pub(crate) instances: Arc<Mutex<HashMap<String, VmInstance>>>,
-    pub(crate) next_cid: Arc<Mutex<u32>>,
+    pub(crate) booting: Mutex<HashMap<String, BootingVm>>,
+    pub(crate) cids: Arc<CidAllocator>,
     pub(crate) shutdown_flag: Arc<AtomicBool>,
     vsock_listener: Arc<Mutex<Option<VsockListener>>>,
     pub(crate) http_client: Arc<Client>,
+    pub(crate) config: VmConfig,
+    retired_logs: Arc<Mutex<HashMap<String, RetiredVmLogs>>>,
+    pub(crate) warm_pool: WarmPool,
+    pub(crate) backend: Arc<dyn VmBackend>,
+    pub(crate) registry: Arc<VmRegistry>,
+    pub(crate) volumes: Arc<VolumeStore>,
 }
 
 impl VmManager {
-    pub fn new() -> Self {
-        let firecracker_path = if which::which("firecracker").is_ok() {
-            "firecracker"
-        } else {
-            "firecracker/firecracker"
-        };
-
-        let firecracker_available = Command::new(firecracker_path)
-            .arg("--version")
-            .output()
-            .is_ok();
-        if !firecracker_available {
-            panic!("Firecracker executable not found or not runnable.");
+    /// Creates the manager for the backend in `vm.backend`. If that backend cannot run VMs on
+    /// this machine, the reason is logged and `create_vm` fails.
+    pub fn new(config: VmConfig, state_dir: &Path) -> Self {
+        let backend = backend::create(&config);
+        if let Err(e) = backend.check_available() {
+            log::error!("VM backend {} is not available: {}", backend.name(), e);
         }
+        let registry = Arc::new(VmRegistry::new(state_dir));
+        let cids = Arc::new(CidAllocator::new(&config, registry.clone()));
+        let volumes = Arc::new(VolumeStore::new(state_dir, &config));
         Self {
             instances: Arc::new(Mutex::new(HashMap::new())),
-            next_cid: Arc::new(Mutex::new(100)),
+            booting: Mutex::new(HashMap::new()),
+            cids,
             shutdown_flag: Arc::new(AtomicBool::new(false)),
             vsock_listener: Arc::new(Mutex::new(None)),
             http_client: Arc::new(Client::new()),
+            config,
+            retired_logs: Arc::new(Mutex::new(HashMap::new())),
+            warm_pool: WarmPool::new(registry.clone()),
+            backend,
+            registry,
+            volumes,
         }
     }
 
+    /// Reattaches to the VMs the previous run of the host left running, and stops and removes
+    /// those that cannot be used. Call once at startup, before VMs are created.
+    pub async fn reattach_vms(&self) {
+        registry::reattach(self).await;
+    }
+
     // --- Public API ---
 
     pub async fn create_vm(
         &self,
         vm_id: String,
+        spec: VmSpec,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        firecracker::create_vm_internal(self, vm_id).await
+        firecracker::create_vm_internal(self, vm_id, spec).await
+    }
+
+    /// Builds the spec for a `create_vm` request from its JSON and the named profile.
+    pub fn spec_from_request(
+        &self,
+        request: &str,
+    ) -> Result<VmSpec, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(vm_spec::spec_from_request(request, &self.config)?)
     }
 
     pub async fn destroy_vm(
@@ -93,40 +243,49 @@ impl VmManager {
         firecracker::destroy_vm_internal(self, vm_id).await
     }
 
-    pub fn list_vms(&self) -> Vec<String> {
-        firecracker::list_vms_internal(self)
+    pub async fn list_vms(&self) -> Vec<VmSummary> {
+        firecracker::list_vms_internal(self).await
     }
 
-    pub async fn execute_vm_command(
+    /// Returns the most recent `lines` of a VM's output stream. Output of destroyed VMs stays
+    /// available for `log_retention_secs`.
+    pub fn get_vm_logs(
         &self,
         vm_id: &str,
-        command: String,
-        args: Vec<String>,
-        working_dir: Option<String>,
-        timeout_seconds: Option<u64>,
-    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        self.execute_command_with_retry(vm_id, command, args, working_dir, timeout_seconds, 30)
-            .await
+        stream: VmLogStream,
+        lines: usize,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(vm_instance) = self.instances.lock().unwrap().get(vm_id) {
+            return Ok(vm_instance.logs.tail(stream, lines));
+        }
+        self.purge_retired_logs();
+        match self.retired_logs.lock().unwrap().get(vm_id) {
+            Some(retired) => Ok(retired.logs.tail(stream, lines)),
+            None => Err(format!("VM {} not found", vm_id).into()),
+        }
     }
 
-    pub fn start_http_proxy_server(
-        &self,
-        port: u32,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        http_proxy::start_http_proxy_server_internal(
-            self.instances.clone(),
-            self.http_client.clone(),
-            self.shutdown_flag.clone(),
-            port,
-        )
+    /// Runs a foreground command, yielding its output as the vm-agent streams it and then its
+    /// result. Output of attempts that are retried is yielded as well.
+    pub fn stream_vm_command<'a>(
+        &'a self,
+        vm_id: &'a str,
+        request: VmCommandRequest,
+    ) -> VmCommandStream<'a> {
+        let (output_sender, output_receiver) = unbounded_channel();
+        let run = async move {
+            self.execute_command_with_retry(vm_id, &request, &output_sender, 3)
+                .await
+        };
+        VmCommandStream::new(run, output_receiver)
     }
 
     pub async fn spawn_command(
         &self,
         vm_id: &str,
-        command: String,
+        request: VmCommandRequest,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        firecracker::spawn_command_internal(self, vm_id, command, vec![], None, Some(30)).await
+        firecracker::spawn_command_internal(self, vm_id, &request).await
     }
 
     pub async fn list_spawned_processes(
@@ -144,15 +303,97 @@ impl VmManager {
         firecracker::stop_spawned_process_internal(self, vm_id, process_id).await
     }
 
-    pub fn start_log_listener_server(
+    /// Creates an empty named volume that VMs can attach by name.
+    pub fn create_volume(
         &self,
-        port: u32,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        log_listener::start_log_listener_server(
-            self.instances.clone(),
-            self.shutdown_flag.clone(),
-            port,
-        )
+        name: &str,
+        size_mib: u32,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.volumes.create(name, size_mib)?;
+        Ok(format!("Volume {} created ({} MiB)", name, size_mib))
+    }
+
+    pub fn list_volumes(&self) -> Vec<VolumeSummary> {
+        self.volumes.list()
+    }
+
+    /// Deletes a named volume and its data. Fails while a VM has it attached.
+    pub fn delete_volume(
+        &self,
+        name: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.volumes.delete(name)?;
+        Ok(format!("Volume {} deleted", name))
+    }
+
+    /// Writes `contents` to the absolute `path` in the VM with permission bits `mode`.
+    pub async fn put_file(
+        &self,
+        vm_id: &str,
+        path: &str,
+        contents: &[u8],
+        mode: u32,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let info = files::put_file(self, vm_id, path, contents, mode).await?;
+        Ok(format!(
+            "Wrote {} ({} bytes, mode {:o}) to VM {}",
+            path, info.size, info.mode, vm_id
+        ))
+    }
+
+    /// Reads the file at the absolute `path` in the VM.
+    pub async fn get_file(
+        &self,
+        vm_id: &str,
+        path: &str,
+    ) -> Result<VmFile, Box<dyn std::error::Error + Send + Sync>> {
+        files::get_file(self, vm_id, path).await
+    }
+
+    /// Copies a host directory, or unpacks a host tarball, into the absolute directory `target`
+    /// in the VM. An incremental sync only sends files that changed since they were last synced.
+    pub async fn sync_directory(
+        &self,
+        vm_id: &str,
+        source: &Path,
+        target: &str,
+        incremental: bool,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let summary = sync::sync_directory(self, vm_id, source, target, incremental).await?;
+        let mut response = format!(
+            "Synced {} files ({} bytes) from {} to {} in VM {}",
+            summary.files,
+            summary.bytes,
+            source.display(),
+            target,
+            vm_id
+        );
+        if summary.unchanged > 0 {
+            response.push_str(&format!(", {} unchanged", summary.unchanged));
+        }
+        Ok(response)
+    }
+
+    /// Starts keeping the warm pools configured in `vm.warm_pool` filled.
+    pub fn start_warm_pool(self: &Arc<Self>) {
+        if !self.config.warm_pool.is_empty() {
+            warm_pool::start_refill(Arc::downgrade(self));
+        }
+    }
+
+    /// Starts checking the health of running VMs every `health_check_interval_secs`.
+    pub fn start_health_checks(self: &Arc<Self>) {
+        if self.config.health_check_interval_secs > 0 {
+            health::start_health_checks(
+                Arc::downgrade(self),
+                Duration::from_secs(self.config.health_check_interval_secs),
+            );
+        }
+    }
+
+    /// Starts destroying VMs whose TTL or idle timeout has passed.
+    pub fn start_reaper(self: &Arc<Self>) {
+        lifetime::start_reaper(Arc::downgrade(self));
     }
 
     pub fn start_vsock_server(
@@ -188,11 +429,15 @@ impl VmManager {
 
     pub fn shutdown(&self) {
         self.shutdown_flag.store(true, Ordering::SeqCst);
+        self.warm_pool.clear(&*self.backend);
         let vm_pids: Vec<(String, Option<u32>)> = {
-            let instances_guard = self.instances.lock().unwrap();
+            let mut instances_guard = self.instances.lock().unwrap();
             instances_guard
-                .iter()
-                .map(|(id, instance)| (id.clone(), instance.pid))
+                .iter_mut()
+                .map(|(id, instance)| {
+                    instance.state = VmState::Stopping;
+                    (id.clone(), instance.pid)
+                })
                 .collect()
         };
 
@@ -211,75 +456,105 @@ impl VmManager {
                     }
                 }
             }
+            self.registry.remove(&registry::vm_key(&vm_id));
         }
         self.instances.lock().unwrap().clear();
+        self.retired_logs.lock().unwrap().clear();
         *self.vsock_listener.lock().unwrap() = None;
     }
 
-    pub fn emergency_cleanup() {
-        if let Ok(output) = Command::new("pgrep").arg("-f").arg("firecracker").output() {
-            if output.status.success() {
-                String::from_utf8_lossy(&output.stdout)
-                    .lines()
-                    .filter_map(|line| line.trim().parse::<u32>().ok())
-                    .for_each(|pid| {
-                        Self::terminate_process(pid, "KILL").ok();
-                    });
-            }
+    /// Kills the VMs in the registry that are still running and removes them. VM processes the
+    /// host did not start are left alone.
+    pub fn emergency_cleanup(&self) {
+        for record in self.registry.load() {
+            registry::remove_orphan(&self.registry, &record);
         }
     }
 
     // --- Internal Logic ---
 
+    /// Keeps the output of a destroyed VM for the retention period.
+    pub(crate) fn retire_logs(&self, vm_id: &str, retired: RetiredVmLogs) {
+        self.purge_retired_logs();
+        self.retired_logs
+            .lock()
+            .unwrap()
+            .insert(vm_id.to_string(), retired);
+    }
+
+    /// Error for a VM that is not running, saying why it was destroyed if the reaper did it.
+    pub(crate) fn vm_not_found(&self, vm_id: &str) -> Box<dyn std::error::Error + Send + Sync> {
+        self.purge_retired_logs();
+        let reason = self
+            .retired_logs
+            .lock()
+            .unwrap()
+            .get(vm_id)
+            .and_then(|retired| retired.reason.clone());
+        match reason {
+            Some(reason) => format!("VM {} was destroyed: {}", vm_id, reason).into(),
+            None => format!("VM {} not found", vm_id).into(),
+        }
+    }
+
+    fn purge_retired_logs(&self) {
+        let retention = Duration::from_secs(self.config.log_retention_secs);
+        self.retired_logs
+            .lock()
+            .unwrap()
+            .retain(|_, retired| !retired.is_expired(retention));
+    }
+
     async fn execute_command_with_retry(
         &self,
         vm_id: &str,
-        command: String,
-        args: Vec<String>,
-        working_dir: Option<String>,
-        timeout_seconds: Option<u64>,
+        request: &VmCommandRequest,
+        output: &UnboundedSender<CommandOutput>,
         max_retries: u32,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let mut retries = 0;
         loop {
-            match firecracker::execute_command_in_vm_internal(
-                self,
-                vm_id,
-                command.clone(),
-                args.clone(),
-                working_dir.clone(),
-                timeout_seconds,
-            )
-            .await
-            {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    let error_msg = e.to_string();
-                    if (error_msg.contains("sending on a closed channel")
-                        || error_msg.contains("VM disconnected"))
-                        && retries < max_retries
-                    {
-                        self.reconnect_vm_channels(vm_id).await.ok();
-                        let delay = Duration::from_millis(1000 * 2u64.pow(retries));
-                        tokio::time::sleep(delay).await;
-                        retries += 1;
-                    } else {
-                        return Err(e);
-                    }
+            let error =
+                match firecracker::execute_command_in_vm_internal(self, vm_id, request, output)
+                    .await
+                {
+                    Ok(result) => return Ok(result),
+                    Err(e) => e,
+                };
+            if retries >= max_retries {
+                return Err(error);
+            }
+            // A healthy VM means the command itself failed
+            match health::check_vm_health(self, vm_id).await {
+                VmHealth::Healthy | VmHealth::NotFound => return Err(error),
+                VmHealth::Unresponsive(reason) => {
+                    log::warn!("VM {} is unresponsive, retrying: {}", vm_id, reason);
+                }
+                VmHealth::Dead(reason) => {
+                    log::warn!("VM {} died, recreating it: {}", vm_id, reason);
+                    self.recreate_vm(vm_id).await?;
                 }
             }
+            let delay = Duration::from_millis(1000 * 2u64.pow(retries));
+            tokio::time::sleep(delay).await;
+            retries += 1;
         }
     }
 
-    async fn reconnect_vm_channels(
+    /// Replaces a VM with a new one created from the same spec.
+    async fn recreate_vm(
         &self,
         vm_id: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if firecracker::check_vm_health_internal(self, vm_id).await {
-            return Ok(());
-        }
+        let spec = self
+            .instances
+            .lock()
+            .unwrap()
+            .get(vm_id)
+            .map(|vm_instance| vm_instance.spec.clone())
+            .unwrap_or_default();
         self.destroy_vm(vm_id).await.ok();
-        self.create_vm(vm_id.to_string()).await.map(|_| ())
+        self.create_vm(vm_id.to_string(), spec).await.map(|_| ())
     }
 
     fn handle_vm_connection(
@@ -0,0 +1,130 @@
+use super::backend::VmBackend;
+use super::vm_logs::{VmLogStream, VmLogs};
+use super::{VsockRequest, VsockResponse, VSOCK_SOCKET_FILE};
+use hyperlight_agents_common::{
+    AgentReady, AgentStatus, VM_AGENT_COMMAND_PORT, VM_AGENT_READY_PORT,
+};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+
+/// Number of console lines included in boot failure errors.
+const CONSOLE_TAIL_LINES: usize = 40;
+
+/// Firecracker forwards guest connections to host port P to `<uds_path>_P`.
+pub(crate) fn ready_socket_path(vm_dir: &Path) -> PathBuf {
+    vm_dir.join(format!("{}_{}", VSOCK_SOCKET_FILE, VM_AGENT_READY_PORT))
+}
+
+/// Binds the readiness socket. This must happen before the VM boots so the announcement of a
+/// fast-starting agent is not lost.
+pub(crate) fn bind_ready_listener(
+    vm_dir: &Path,
+) -> Result<UnixListener, Box<dyn std::error::Error + Send + Sync>> {
+    let socket_path = ready_socket_path(vm_dir);
+    let _ = std::fs::remove_file(&socket_path);
+    Ok(UnixListener::bind(&socket_path)?)
+}
+
+/// Waits for the vm-agent to announce itself. Fails if the VM process exits or the timeout passes,
+/// with the tail of the serial console in the error.
+pub(crate) async fn wait_for_ready(
+    listener: UnixListener,
+    child: &mut Child,
+    vm_dir: &Path,
+    logs: &VmLogs,
+    timeout: Duration,
+) -> Result<AgentReady, Box<dyn std::error::Error + Send + Sync>> {
+    let deadline = Instant::now() + timeout;
+    let result = loop {
+        if let Some(status) = child.try_wait()? {
+            // Let the console readers catch up with the last output
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            break Err(format!(
+                "VM process exited during boot ({}){}",
+                status,
+                console_section(logs)
+            ));
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break Err(format!(
+                "VM did not become ready within {}s{}",
+                timeout.as_secs(),
+                console_section(logs)
+            ));
+        }
+
+        // Accept in short slices so a crashed VM is noticed quickly
+        let poll_interval = remaining.min(Duration::from_millis(250));
+        match tokio::time::timeout(poll_interval, listener.accept()).await {
+            Ok(Ok((stream, _))) => {
+                let mut line = String::new();
+                let mut reader = BufReader::new(stream);
+                match tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut line))
+                    .await
+                {
+                    Ok(Ok(_)) => match serde_json::from_str::<AgentReady>(line.trim()) {
+                        Ok(ready) => break Ok(ready),
+                        Err(e) => log::warn!("Ignoring malformed readiness message: {}", e),
+                    },
+                    Ok(Err(e)) => log::warn!("Failed to read readiness message: {}", e),
+                    Err(_) => log::warn!("Timed out reading readiness message"),
+                }
+            }
+            Ok(Err(e)) => break Err(format!("Failed to accept readiness connection: {}", e)),
+            Err(_) => continue,
+        }
+    };
+
+    let _ = std::fs::remove_file(ready_socket_path(vm_dir));
+    result.map_err(Into::into)
+}
+
+/// Sends a ping to the vm-agent command port.
+pub(crate) async fn ping(
+    backend: &(impl VmBackend + ?Sized),
+    vm_dir: &Path,
+) -> Result<AgentReady, Box<dyn std::error::Error + Send + Sync>> {
+    match agent_request(backend, vm_dir, &VsockRequest::Ping).await? {
+        VsockResponse::Pong(ready) => Ok(ready),
+        other => Err(format!("Unexpected response to ping: {:?}", other).into()),
+    }
+}
+
+/// Asks the vm-agent what it is busy with.
+pub(crate) async fn agent_status(
+    backend: &(impl VmBackend + ?Sized),
+    vm_dir: &Path,
+) -> Result<AgentStatus, Box<dyn std::error::Error + Send + Sync>> {
+    match agent_request(backend, vm_dir, &VsockRequest::Status).await? {
+        VsockResponse::Status(status) => Ok(status),
+        other => Err(format!("Unexpected response to status request: {:?}", other).into()),
+    }
+}
+
+pub(crate) async fn agent_request(
+    backend: &(impl VmBackend + ?Sized),
+    vm_dir: &Path,
+    request: &VsockRequest,
+) -> Result<VsockResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let mut stream = backend.connect_agent(vm_dir, VM_AGENT_COMMAND_PORT).await?;
+    let request = serde_json::to_string(request)?;
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(serde_json::from_slice::<VsockResponse>(&response)?)
+}
+
+fn console_section(logs: &VmLogs) -> String {
+    let tail = logs
+        .tail(VmLogStream::Console, CONSOLE_TAIL_LINES)
+        .join("\n");
+    if tail.trim().is_empty() {
+        ". The serial console is empty.".to_string()
+    } else {
+        format!(". Serial console tail:\n{}", tail)
+    }
+}
@@ -0,0 +1,412 @@
+//! Registry of the VMs the host runs, one JSON file per VM in `<state_dir>/vms`. A restarted host
+//! reattaches to the VMs of its previous run that are still alive, and stops and removes the
+//! others. VM processes that are not in the registry are never touched.
+
+use super::backend::VmBackend;
+use super::firecracker::{self, BootedVm};
+use super::firecracker_api::API_SOCKET_FILE;
+use super::jailer::JailRecord;
+use super::lifetime::VmLifetime;
+use super::listeners::VmListeners;
+use super::readiness;
+use super::vm_dir::VmDir;
+use super::vm_logs::VmLogs;
+use super::{VmInstance, VmManager, VmState, VSOCK_SOCKET_FILE};
+use chrono::{DateTime, Utc};
+use hyperlight_agents_common::VmSpec;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Position of the CID allocator, next to the records.
+const CID_STATE_FILE: &str = "cids.json";
+
+/// Longest the vm-agent of a VM from the previous run may take to answer before it is removed.
+const REATTACH_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest to wait for a killed VM process that is not a child of this host to exit.
+const ADOPTED_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A VM process started by the host, as persisted in the registry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct VmRecord {
+    /// `None` for an idle warm pool VM.
+    pub vm_id: Option<String>,
+    /// Backend that started the VM.
+    pub backend: String,
+    pub cid: u32,
+    pub pid: u32,
+    /// Start time of the process in clock ticks since boot, which tells it apart from a later
+    /// process with the same pid.
+    pub pid_start_time: u64,
+    pub vm_dir: PathBuf,
+    pub vsock_socket: PathBuf,
+    /// Firecracker's API socket, `None` for the local backend.
+    pub api_socket: Option<PathBuf>,
+    pub jail: Option<JailRecord>,
+    pub profile: String,
+    pub owner: Option<String>,
+    /// Spec the VM was created from, `None` for an idle warm pool VM.
+    pub spec: Option<VmSpec>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl VmRecord {
+    fn new(backend: &dyn VmBackend, vm_dir: &VmDir, pid: u32, cid: u32, profile: String) -> Self {
+        let api_socket = vm_dir.path().join(API_SOCKET_FILE);
+        Self {
+            vm_id: None,
+            backend: backend.name().to_string(),
+            cid,
+            pid,
+            pid_start_time: process_start_time(pid).unwrap_or_default(),
+            vm_dir: vm_dir.path().to_path_buf(),
+            vsock_socket: vm_dir.path().join(VSOCK_SOCKET_FILE),
+            api_socket: api_socket.exists().then_some(api_socket),
+            jail: vm_dir.jail().map(|jail| jail.record()),
+            profile,
+            owner: None,
+            spec: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub(crate) fn for_instance(backend: &dyn VmBackend, vm_instance: &VmInstance) -> Self {
+        let spec = &vm_instance.spec;
+        let profile = spec
+            .profile
+            .clone()
+            .unwrap_or_else(|| super::vm_spec::DEFAULT_PROFILE.to_string());
+        Self {
+            vm_id: Some(vm_instance.vm_id.clone()),
+            owner: spec.owner.clone(),
+            spec: Some(spec.clone()),
+            created_at: vm_instance.lifetime.created_at(),
+            ..Self::new(
+                backend,
+                &vm_instance.vm_dir,
+                vm_instance.process.id(),
+                vm_instance.cid.get(),
+                profile,
+            )
+        }
+    }
+
+    pub(crate) fn for_pooled(backend: &dyn VmBackend, booted: &BootedVm, profile: &str) -> Self {
+        Self::new(
+            backend,
+            &booted.vm_dir,
+            booted.child.id(),
+            booted.cid.get(),
+            profile.to_string(),
+        )
+    }
+
+    pub(crate) fn key(&self) -> String {
+        match &self.vm_id {
+            Some(vm_id) => vm_key(vm_id),
+            None => pool_key(self.pid),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CidRecord {
+    next: u32,
+}
+
+/// Registry key of the VM `vm_id`.
+pub(crate) fn vm_key(vm_id: &str) -> String {
+    format!("vm:{}", vm_id)
+}
+
+/// Registry key of an idle warm pool VM.
+pub(crate) fn pool_key(pid: u32) -> String {
+    format!("warm-pool:{}", pid)
+}
+
+/// The records in `<state_dir>/vms`. Failing to write one is logged and otherwise ignored, as it
+/// only means the VM cannot be reattached.
+pub(crate) struct VmRegistry {
+    dir: PathBuf,
+}
+
+impl VmRegistry {
+    pub(crate) fn new(state_dir: &Path) -> Self {
+        Self {
+            dir: state_dir.join("vms"),
+        }
+    }
+
+    pub(crate) fn save(&self, record: &VmRecord) {
+        if let Err(e) = self.write(&self.path(&record.key()), record) {
+            log::warn!("Failed to save VM {} to the registry: {}", record.key(), e);
+        }
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        match std::fs::remove_file(self.path(key)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove VM {} from the registry: {}", key, e),
+        }
+    }
+
+    /// Where the previous run of the CID allocator stopped.
+    pub(crate) fn load_next_cid(&self) -> Option<u32> {
+        let json = std::fs::read(self.dir.join(CID_STATE_FILE)).ok()?;
+        let state: CidRecord = serde_json::from_slice(&json).ok()?;
+        Some(state.next)
+    }
+
+    pub(crate) fn save_next_cid(&self, next: u32) {
+        if let Err(e) = self.write(&self.dir.join(CID_STATE_FILE), &CidRecord { next }) {
+            log::warn!("Failed to save the CID allocator to the registry: {}", e);
+        }
+    }
+
+    pub(crate) fn load(&self) -> Vec<VmRecord> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter(|path| !path.ends_with(CID_STATE_FILE))
+            .filter_map(|path| {
+                let record = std::fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string()));
+                match record {
+                    Ok(record) => Some(record),
+                    Err(e) => {
+                        log::warn!("Ignoring unreadable VM record {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn write(&self, path: &Path, value: &impl Serialize) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Written to the side and renamed, so a crash never leaves half a file
+        std::fs::write(
+            path.with_extension("tmp"),
+            serde_json::to_vec_pretty(value)?,
+        )?;
+        std::fs::rename(path.with_extension("tmp"), path)
+    }
+
+    /// VM ids can hold any character, so files are named after a hash of the key.
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(&hash[..16])))
+    }
+}
+
+/// The process of a running VM.
+pub enum VmProcess {
+    /// Started by this host.
+    Child(Child),
+    /// Started by the previous run of the host and reattached from the registry. It is not a
+    /// child of this host, so it can only be signalled and polled.
+    Adopted { pid: u32, start_time: u64 },
+}
+
+impl VmProcess {
+    pub fn id(&self) -> u32 {
+        match self {
+            VmProcess::Child(child) => child.id(),
+            VmProcess::Adopted { pid, .. } => *pid,
+        }
+    }
+
+    /// Returns how the process exited, without blocking, or `None` while it runs. Also reaps a
+    /// child that exited.
+    pub(crate) fn try_wait(&mut self) -> std::io::Result<Option<String>> {
+        match self {
+            VmProcess::Child(child) => Ok(child.try_wait()?.map(|status| status.to_string())),
+            VmProcess::Adopted { pid, start_time } => {
+                Ok((!is_running(*pid, *start_time)).then(|| "exited".to_string()))
+            }
+        }
+    }
+
+    pub(crate) fn kill(&mut self) {
+        match self {
+            VmProcess::Child(child) => {
+                let _ = child.kill();
+            }
+            VmProcess::Adopted { pid, start_time } => {
+                if is_running(*pid, *start_time) {
+                    firecracker::terminate_process(*pid, "KILL").ok();
+                }
+            }
+        }
+    }
+
+    /// Waits for the process to exit, for an adopted one at most `ADOPTED_EXIT_TIMEOUT`.
+    pub(crate) fn wait(&mut self) {
+        match self {
+            VmProcess::Child(child) => {
+                let _ = child.wait();
+            }
+            VmProcess::Adopted { pid, start_time } => {
+                let deadline = Instant::now() + ADOPTED_EXIT_TIMEOUT;
+                while is_running(*pid, *start_time) {
+                    if Instant::now() > deadline {
+                        log::warn!("VM process {} did not exit", pid);
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+        }
+    }
+}
+
+/// Whether the process `pid` is still the one that started at `start_time`.
+fn is_running(pid: u32, start_time: u64) -> bool {
+    process_start_time(pid) == Some(start_time)
+}
+
+/// Start time of a running process, `None` if there is none with `pid` or it is a zombie.
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // Fields follow the command name in parentheses, which may itself contain spaces
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    if fields.next()? == "Z" {
+        return None;
+    }
+    // Field 22, starttime, counting from the state in field 3
+    fields.nth(18)?.parse().ok()
+}
+
+/// Reattaches to the VMs of the previous run whose process still runs and whose vm-agent
+/// answers, and stops and removes the others.
+pub(crate) async fn reattach(manager: &VmManager) {
+    for record in manager.registry.load() {
+        let result = match check_alive(manager, &record).await {
+            Ok((vm_id, spec)) => adopt(manager, &record, vm_id, spec),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => log::info!(
+                "Reattached VM {} (pid {}, CID {})",
+                record.vm_id.as_deref().unwrap_or_default(),
+                record.pid,
+                record.cid
+            ),
+            Err(e) => {
+                log::info!("Removing VM {} of the previous run: {}", record.key(), e);
+                remove_orphan(&manager.registry, &record);
+            }
+        }
+    }
+}
+
+async fn check_alive(
+    manager: &VmManager,
+    record: &VmRecord,
+) -> Result<(String, VmSpec), Box<dyn std::error::Error + Send + Sync>> {
+    let (Some(vm_id), Some(spec)) = (&record.vm_id, &record.spec) else {
+        return Err("idle warm pool VM".into());
+    };
+    if record.backend != manager.backend.name() {
+        return Err(format!("started by the {} backend", record.backend).into());
+    }
+    if !is_running(record.pid, record.pid_start_time) {
+        return Err("process exited".into());
+    }
+    match tokio::time::timeout(
+        REATTACH_PING_TIMEOUT,
+        readiness::ping(&*manager.backend, &record.vm_dir),
+    )
+    .await
+    {
+        Ok(Ok(_)) => Ok((vm_id.clone(), spec.clone())),
+        Ok(Err(e)) => Err(format!("vm-agent did not answer: {}", e).into()),
+        Err(_) => Err("vm-agent did not answer".into()),
+    }
+}
+
+fn adopt(
+    manager: &VmManager,
+    record: &VmRecord,
+    vm_id: String,
+    spec: VmSpec,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let logs = Arc::new(VmLogs::reopen(
+        &record.vm_dir,
+        manager.config.log_buffer_lines,
+    )?);
+    let lifetime = Arc::new(VmLifetime::resumed(
+        &spec,
+        &manager.config,
+        record.created_at,
+    ));
+    let vm_dir = VmDir::adopt(record);
+    let volumes = spec
+        .volumes
+        .iter()
+        .filter_map(|volume| volume.name.as_deref())
+        .map(|name| manager.volumes.claim(name, &vm_id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let listeners = VmListeners::start(manager, &vm_id, &vm_dir, &lifetime)?;
+    let (command_sender, command_receiver) = mpsc::channel();
+    let vm_instance = VmInstance {
+        vm_id: vm_id.clone(),
+        cid: manager.cids.acquire(record.cid),
+        pid: Some(record.pid),
+        process: VmProcess::Adopted {
+            pid: record.pid,
+            start_time: record.pid_start_time,
+        },
+        logs,
+        vm_dir,
+        volumes,
+        command_sender,
+        result_receiver: Arc::new(Mutex::new(HashMap::new())),
+        output_senders: Arc::new(Mutex::new(HashMap::new())),
+        memfd_rootfs: None,
+        rootfs_symlink: None,
+        lifetime,
+        listeners,
+        state: VmState::Ready,
+        spec,
+    };
+    manager
+        .instances
+        .lock()
+        .unwrap()
+        .insert(vm_id.clone(), vm_instance);
+
+    firecracker::start_command_processor(
+        manager.instances.clone(),
+        manager.backend.clone(),
+        manager.shutdown_flag.clone(),
+        vm_id,
+        command_receiver,
+    );
+    Ok(())
+}
+
+/// Kills a VM of the registry that is not running as an instance, if its process still runs, and
+/// removes its directory, jail and record.
+pub(crate) fn remove_orphan(registry: &VmRegistry, record: &VmRecord) {
+    let mut process = VmProcess::Adopted {
+        pid: record.pid,
+        start_time: record.pid_start_time,
+    };
+    process.kill();
+    process.wait();
+    drop(VmDir::adopt(record));
+    registry.remove(&record.key());
+}
@@ -0,0 +1,220 @@
+//! Copies a host directory or tarball into a directory in a VM. Directories are packed into a tar
+//! archive honouring `.gitignore`, which is uploaded like a file and unpacked by the vm-agent. An
+//! incremental sync leaves out files whose size and modification time match the copy in the VM.
+
+use super::files;
+use super::vm_spec::VmConfig;
+use super::{VmManager, VsockRequest, VsockResponse};
+use hyperlight_agents_common::{FileInfo, SyncResponse, SyncUnpackRequest, SyncedFile};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Parameters of a `sync_directory` request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncRequest {
+    /// Directory or tar archive on the host, inside one of `vm.sync_dirs`.
+    pub source: String,
+    /// Absolute directory in the VM, created if missing.
+    pub target: String,
+    /// Only send files that changed since they were last synced.
+    pub incremental: bool,
+}
+
+/// What a sync sent.
+#[derive(Debug, Clone, Default)]
+pub struct SyncSummary {
+    pub files: u64,
+    /// Files an incremental sync left out because the VM has them already.
+    pub unchanged: u64,
+    /// Size of the uploaded archive.
+    pub bytes: u64,
+}
+
+/// A tar archive of a source directory.
+struct PackedSource {
+    archive: File,
+    files: u64,
+    unchanged: u64,
+}
+
+pub(crate) async fn sync_directory(
+    manager: &VmManager,
+    vm_id: &str,
+    source: &Path,
+    target: &str,
+    incremental: bool,
+) -> Result<SyncSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let source = resolve_source(&manager.config, source)?;
+    if !target.starts_with('/') {
+        return Err(format!("Target must be an absolute path: {}", target).into());
+    }
+    let vm_dir = files::agent_dir(manager, vm_id)?;
+    let existing = sync_request(
+        manager,
+        &vm_dir,
+        &VsockRequest::SyncPrepare {
+            target: target.to_string(),
+        },
+    )
+    .await?
+    .files
+    .unwrap_or_default();
+
+    let (mut archive, unchanged) = if source.is_dir() {
+        let existing = if incremental {
+            existing
+        } else {
+            BTreeMap::new()
+        };
+        let packed = pack(&source, &existing)?;
+        if packed.files == 0 && packed.unchanged > 0 {
+            return Ok(SyncSummary {
+                unchanged: packed.unchanged,
+                ..SyncSummary::default()
+            });
+        }
+        (packed.archive, packed.unchanged)
+    } else {
+        (File::open(&source)?, 0)
+    };
+
+    let info = archive_info(&mut archive)?;
+    let max_size = manager.config.max_sync_size_mib as u64 * 1024 * 1024;
+    if info.size > max_size {
+        return Err(format!(
+            "Archive of {} bytes exceeds the limit of {} MiB",
+            info.size, manager.config.max_sync_size_mib
+        )
+        .into());
+    }
+    let archive_path = format!(
+        "{}/.vm-sync-{}.tar",
+        target.trim_end_matches('/'),
+        uuid::Uuid::new_v4().simple()
+    );
+    files::upload(manager, &vm_dir, &archive_path, &mut archive, &info).await?;
+    let unpack = VsockRequest::SyncUnpack(SyncUnpackRequest {
+        archive: archive_path,
+        target: target.to_string(),
+    });
+    let unpacked = sync_request(manager, &vm_dir, &unpack)
+        .await?
+        .unpacked
+        .unwrap_or_default();
+    log::info!(
+        "Synced {} files ({} bytes) from {} to {} in VM {}",
+        unpacked,
+        info.size,
+        source.display(),
+        target,
+        vm_id
+    );
+    Ok(SyncSummary {
+        files: unpacked,
+        unchanged,
+        bytes: info.size,
+    })
+}
+
+/// Resolves the source and makes sure it lies inside one of the allowed sync directories.
+fn resolve_source(config: &VmConfig, source: &Path) -> Result<PathBuf, String> {
+    let canonical = source
+        .canonicalize()
+        .map_err(|_| format!("Source not found: {}", source.display()))?;
+    let allowed = config
+        .sync_dirs
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| canonical.starts_with(dir));
+    if !allowed {
+        return Err(format!(
+            "Source {} is outside the allowed sync directories",
+            source.display()
+        ));
+    }
+    Ok(canonical)
+}
+
+/// Packs the files of `source` that are not ignored by git into a temporary archive, leaving out
+/// those `existing` lists with the same size and modification time.
+fn pack(
+    source: &Path,
+    existing: &BTreeMap<String, SyncedFile>,
+) -> Result<PackedSource, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder = tar::Builder::new(tempfile::tempfile()?);
+    builder.follow_symlinks(false);
+    let walker = ignore::WalkBuilder::new(source)
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+
+    let mut files = 0;
+    let mut unchanged = 0;
+    for entry in walker {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(source)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let metadata = std::fs::symlink_metadata(path)?;
+        if metadata.is_file() {
+            let synced = SyncedFile {
+                size: metadata.len(),
+                mtime: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |age| age.as_secs()),
+            };
+            if relative
+                .to_str()
+                .is_some_and(|relative| existing.get(relative) == Some(&synced))
+            {
+                unchanged += 1;
+                continue;
+            }
+            files += 1;
+        }
+        builder.append_path_with_name(path, relative)?;
+    }
+    Ok(PackedSource {
+        archive: builder.into_inner()?,
+        files,
+        unchanged,
+    })
+}
+
+/// Size and checksum of the archive, leaving it rewound for the upload.
+fn archive_info(archive: &mut File) -> Result<FileInfo, Box<dyn std::error::Error + Send + Sync>> {
+    archive.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(archive, &mut hasher)?;
+    archive.seek(SeekFrom::Start(0))?;
+    Ok(FileInfo {
+        size,
+        mode: 0o600,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
+
+async fn sync_request(
+    manager: &VmManager,
+    vm_dir: &Path,
+    request: &VsockRequest,
+) -> Result<SyncResponse, Box<dyn std::error::Error + Send + Sync>> {
+    match super::readiness::agent_request(&*manager.backend, vm_dir, request).await? {
+        VsockResponse::Sync(SyncResponse {
+            error: Some(error), ..
+        }) => Err(error.into()),
+        VsockResponse::Sync(response) => Ok(response),
+        other => Err(format!("Unexpected response to sync request: {:?}", other).into()),
+    }
+}
@@ -0,0 +1,92 @@
+use super::jailer::{self, Jail};
+use super::registry::VmRecord;
+use super::vm_spec::{ResolvedVmSpec, VmConfig};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Directory a VM's Firecracker runs in, holding its sockets, FIFOs and log files: a temporary
+/// directory, or the chroot of its jail in jailer mode. Removed when dropped.
+pub struct VmDir {
+    path: PathBuf,
+    _temp_dir: Option<TempDir>,
+    jail: Option<Jail>,
+    /// Directory of a VM reattached from the registry, removed on drop like a temporary one.
+    adopted: bool,
+}
+
+impl VmDir {
+    pub(crate) fn new(
+        config: &VmConfig,
+        vm_id: &str,
+        firecracker_bin: &Path,
+        spec: &ResolvedVmSpec,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if config.jailer.enabled {
+            let jail = Jail::create(&config.jailer, vm_id, firecracker_bin, spec)?;
+            Ok(Self {
+                path: jail.root(),
+                _temp_dir: None,
+                jail: Some(jail),
+                adopted: false,
+            })
+        } else {
+            Self::temp()
+        }
+    }
+
+    /// A temporary directory, for VMs that are not jailed.
+    pub(crate) fn temp() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let temp_dir = TempDir::new()?;
+        Ok(Self {
+            path: temp_dir.path().to_path_buf(),
+            _temp_dir: Some(temp_dir),
+            jail: None,
+            adopted: false,
+        })
+    }
+
+    /// The directory of a VM the previous run of the host started, with its jail if it has one.
+    pub(crate) fn adopt(record: &VmRecord) -> Self {
+        Self {
+            path: record.vm_dir.clone(),
+            _temp_dir: None,
+            jail: record.jail.as_ref().map(Jail::adopt),
+            adopted: true,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn jail(&self) -> Option<&Jail> {
+        self.jail.as_ref()
+    }
+
+    /// User and group of the jailed Firecracker, if any.
+    pub(crate) fn jail_owner(&self) -> Option<(u32, u32)> {
+        self.jail.as_ref().map(Jail::owner)
+    }
+
+    /// Gives a jailed Firecracker access to a file the host created in the directory.
+    pub(crate) fn grant(&self, path: &Path) -> std::io::Result<()> {
+        jailer::grant(path, self.jail_owner())
+    }
+
+    /// Removes the cgroup and network namespace of the jail once Firecracker has exited. The
+    /// directory itself stays until dropped, so the VM's output can still be read.
+    pub(crate) fn release_jail(&mut self) {
+        if let Some(jail) = &mut self.jail {
+            jail.release();
+        }
+    }
+}
+
+impl Drop for VmDir {
+    fn drop(&mut self) {
+        // A jail removes its own directory, which contains this one
+        if self.adopted && self.jail.is_none() {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use super::vm_dir::VmDir;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Serial console and Firecracker process output.
+pub(crate) const CONSOLE_LOG_FILE: &str = "console.log";
+const FIRECRACKER_LOG_FILE: &str = "firecracker.log";
+const METRICS_LOG_FILE: &str = "metrics.log";
+pub(crate) const FIRECRACKER_LOG_FIFO: &str = "firecracker.fifo";
+pub(crate) const METRICS_FIFO: &str = "metrics.fifo";
+
+/// Size at which a log file is moved to `<name>.1`, replacing the previous one.
+const MAX_LOG_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// An output stream captured for each VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VmLogStream {
+    /// Serial console (kernel, init.sh, vm-agent) and Firecracker's stdout and stderr.
+    Console,
+    /// Firecracker's own log.
+    Log,
+    /// Firecracker's metrics, one JSON object per line.
+    Metrics,
+}
+
+/// Parameters of a `get_vm_logs` request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VmLogsRequest {
+    pub stream: VmLogStream,
+    pub lines: usize,
+}
+
+impl Default for VmLogsRequest {
+    fn default() -> Self {
+        Self {
+            stream: VmLogStream::Console,
+            lines: 100,
+        }
+    }
+}
+
+/// Keeps the most recent lines of a stream.
+struct LogRing {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl LogRing {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(capacity.min(1024))),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn tail(&self, count: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A log file in the VM directory, rotated once it reaches its size limit so that a chatty VM
+/// cannot fill the disk.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_len: u64,
+}
+
+impl LogFile {
+    fn create(path: PathBuf, max_len: u64) -> io::Result<Self> {
+        let file = File::create(&path)?;
+        Ok(Self {
+            path,
+            file,
+            len: 0,
+            max_len,
+        })
+    }
+
+    /// Continues a file a previous run of the host wrote.
+    fn append(path: PathBuf, max_len: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            len,
+            max_len,
+        })
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        // A single line never takes more than a whole file
+        let buf = &buf[..buf.len().min(self.max_len as usize)];
+        if self.len + buf.len() as u64 > self.max_len {
+            if let Err(e) = self.rotate() {
+                log::warn!("Failed to rotate {}: {}", self.path.display(), e);
+                return;
+            }
+        }
+        if self.file.write_all(buf).is_ok() {
+            self.len += buf.len() as u64;
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.file = File::create(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Output captured for one VM, held in memory and mirrored to files in the VM directory.
+pub struct VmLogs {
+    console: Arc<LogRing>,
+    log: Arc<LogRing>,
+    metrics: Arc<LogRing>,
+    fifo_paths: Vec<PathBuf>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl VmLogs {
+    /// Creates the Firecracker log and metrics FIFOs in `vm_dir` and starts reading them.
+    pub(crate) fn new(
+        vm_dir: &Path,
+        capacity: usize,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::open(vm_dir, capacity, false)
+    }
+
+    /// Reads the FIFOs of a VM the previous run of the host started again, starting from the
+    /// output in its log files. Its console output is lost, as it went to the previous run.
+    pub(crate) fn reopen(
+        vm_dir: &Path,
+        capacity: usize,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let logs = Self::open(vm_dir, capacity, true)?;
+        load_lines(&vm_dir.join(CONSOLE_LOG_FILE), &logs.console);
+        Ok(logs)
+    }
+
+    fn open(
+        vm_dir: &Path,
+        capacity: usize,
+        existing: bool,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let logs = Self {
+            console: Arc::new(LogRing::new(capacity)),
+            log: Arc::new(LogRing::new(capacity)),
+            metrics: Arc::new(LogRing::new(capacity)),
+            fifo_paths: vec![vm_dir.join(FIRECRACKER_LOG_FIFO), vm_dir.join(METRICS_FIFO)],
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+
+        for (fifo_path, file_name, ring) in [
+            (&logs.fifo_paths[0], FIRECRACKER_LOG_FILE, &logs.log),
+            (&logs.fifo_paths[1], METRICS_LOG_FILE, &logs.metrics),
+        ] {
+            let file_path = vm_dir.join(file_name);
+            let file = if existing {
+                load_lines(&file_path, ring);
+                LogFile::append(file_path, MAX_LOG_FILE_BYTES)?
+            } else {
+                let status = Command::new("mkfifo").arg(fifo_path).status()?;
+                if !status.success() {
+                    return Err(format!("Failed to create FIFO {}", fifo_path.display()).into());
+                }
+                LogFile::create(file_path, MAX_LOG_FILE_BYTES)?
+            };
+            // Opening read-write never blocks, and keeps the FIFO open while Firecracker restarts it
+            let fifo = OpenOptions::new().read(true).write(true).open(fifo_path)?;
+            pump_lines(
+                fifo,
+                Arc::new(Mutex::new(file)),
+                ring.clone(),
+                Some(logs.stopped.clone()),
+            );
+        }
+        Ok(logs)
+    }
+
+    /// Captures the stdout and stderr of a Firecracker process started with piped output.
+    pub(crate) fn capture_process(
+        &self,
+        child: &mut Child,
+        vm_dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let file = Arc::new(Mutex::new(LogFile::create(
+            vm_dir.join(CONSOLE_LOG_FILE),
+            MAX_LOG_FILE_BYTES,
+        )?));
+        if let Some(stdout) = child.stdout.take() {
+            pump_lines(stdout, file.clone(), self.console.clone(), None);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            pump_lines(stderr, file, self.console.clone(), None);
+        }
+        Ok(())
+    }
+
+    /// Returns up to `lines` of the most recent output of a stream.
+    pub fn tail(&self, stream: VmLogStream, lines: usize) -> Vec<String> {
+        match stream {
+            VmLogStream::Console => self.console.tail(lines),
+            VmLogStream::Log => self.log.tail(lines),
+            VmLogStream::Metrics => self.metrics.tail(lines),
+        }
+    }
+
+    /// Stops reading the FIFOs once the VM is gone. Console capture ends with the process.
+    pub(crate) fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // Wake the readers blocked on an idle FIFO so they see the flag
+        for fifo_path in &self.fifo_paths {
+            if let Ok(mut fifo) = OpenOptions::new().read(true).write(true).open(fifo_path) {
+                fifo.write_all(b"\n").ok();
+            }
+        }
+    }
+}
+
+impl Drop for VmLogs {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Fills the ring with the last lines of a log file.
+fn load_lines(path: &Path, ring: &LogRing) {
+    if let Ok(file) = File::open(path) {
+        for line in BufReader::new(file).split(b'\n').map_while(Result::ok) {
+            ring.push(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches('\r')
+                    .to_string(),
+            );
+        }
+    }
+}
+
+/// Copies lines from `source` into the ring and the file until EOF, or until `stopped` is set.
+fn pump_lines(
+    source: impl Read + Send + 'static,
+    file: Arc<Mutex<LogFile>>,
+    ring: Arc<LogRing>,
+    stopped: Option<Arc<AtomicBool>>,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(source);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if stopped
+                .as_ref()
+                .is_some_and(|stopped| stopped.load(Ordering::SeqCst))
+            {
+                break;
+            }
+            file.lock().unwrap().write(&buf);
+            let line = String::from_utf8_lossy(&buf);
+            ring.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+    });
+}
+
+/// Output of a destroyed VM, kept for a grace period so failures can be investigated.
+pub(crate) struct RetiredVmLogs {
+    pub logs: Arc<VmLogs>,
+    // Keeps the log files on disk until the entry is dropped
+    _vm_dir: VmDir,
+    retired_at: Instant,
+    /// Why the VM was destroyed, if not by `destroy_vm`.
+    pub reason: Option<String>,
+}
+
+impl RetiredVmLogs {
+    pub(crate) fn new(logs: Arc<VmLogs>, vm_dir: VmDir) -> Self {
+        logs.stop();
+        Self {
+            logs,
+            _vm_dir: vm_dir,
+            retired_at: Instant::now(),
+            reason: None,
+        }
+    }
+
+    pub(crate) fn with_reason(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub(crate) fn is_expired(&self, retention: Duration) -> bool {
+        self.retired_at.elapsed() > retention
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_files_rotate_at_their_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONSOLE_LOG_FILE);
+        let rotated = dir.path().join(format!("{}.1", CONSOLE_LOG_FILE));
+        let mut file = LogFile::create(path.clone(), 100).unwrap();
+
+        for i in 0..100 {
+            file.write(format!("line {:04}\n", i).as_bytes());
+            assert!(fs::metadata(&path).unwrap().len() <= 100);
+        }
+        assert_eq!(fs::metadata(&rotated).unwrap().len(), 100);
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.ends_with("line 0099\n"), "{}", current);
+        let previous = fs::read_to_string(&rotated).unwrap();
+        assert!(previous.starts_with("line 0080\n"), "{}", previous);
+
+        // Oversized lines are cut to the limit
+        file.write(&[b'x'; 1000]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 100);
+
+        // A reopened file counts what is already there
+        let mut file = LogFile::append(path.clone(), 100).unwrap();
+        file.write(b"next\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+    }
+
+    #[test]
+    fn captured_output_is_bounded_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = LogFile::create(dir.path().join(METRICS_LOG_FILE), 1024).unwrap();
+        let ring = Arc::new(LogRing::new(10));
+        let output: Vec<u8> = (0..1000)
+            .flat_map(|i| format!("{{\"sample\": {}}}\n", i).into_bytes())
+            .collect();
+        let file = Arc::new(Mutex::new(file));
+        pump_lines(io::Cursor::new(output), file.clone(), ring.clone(), None);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while ring.tail(1) != vec!["{\"sample\": 999}".to_string()] {
+            assert!(Instant::now() < deadline, "output not captured");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut total = 0;
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            let len = entry.unwrap().metadata().unwrap().len();
+            assert!(len <= 1024);
+            total += len;
+        }
+        assert!(total <= 2048);
+        assert_eq!(ring.tail(100).len(), 10);
+    }
+}
@@ -0,0 +1,664 @@
+use super::backend::VmBackendKind;
+use super::jailer::JailerConfig;
+use super::local::LocalBackendConfig;
+use super::volumes;
+use super::warm_pool::WarmPoolConfig;
+use hyperlight_agents_common::{VmSpec, VmVolume};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Kernel command line every VM boots with; `VmSpec::boot_args` is appended to it.
+pub(crate) const BASE_BOOT_ARGS: &str =
+    "console=ttyS0 reboot=k panic=1 pci=off init=/sbin/init root=/dev/vda rootfstype=squashfs ro";
+
+/// Profile used when a request does not name one.
+pub const DEFAULT_PROFILE: &str = "alpine-base";
+
+const DEFAULT_KERNEL_PATH: &str = "firecracker/vmlinux";
+const DEFAULT_ROOTFS_PATH: &str = "firecracker/rootfs.squashfs";
+
+/// Longest `boot_args` accepted, leaving room for the base command line within the kernel limit.
+const MAX_EXTRA_BOOT_ARGS_LEN: usize = 1024;
+
+/// VM settings from the `vm` section of the host configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VmConfig {
+    /// Runs VMs with Firecracker, or as local processes on machines without KVM.
+    pub backend: VmBackendKind,
+    /// Settings of the local backend.
+    pub local: LocalBackendConfig,
+    pub max_vcpu_count: u8,
+    pub max_mem_size_mib: u32,
+    /// Largest data volume a VM or `create_volume` may create.
+    pub max_volume_size_mib: u32,
+    /// Largest file `put_file` and `get_file` transfer.
+    pub max_file_size_mib: u32,
+    /// Largest archive `sync_directory` uploads.
+    pub max_sync_size_mib: u32,
+    /// Lowest and highest guest CID handed out to VMs.
+    pub min_cid: u32,
+    pub max_cid: u32,
+    /// Directories kernel and rootfs images may be taken from.
+    pub image_dirs: Vec<PathBuf>,
+    /// Host directories `sync_directory` may copy from, none by default.
+    pub sync_dirs: Vec<PathBuf>,
+    /// Named machine configurations, added to or replacing the built-in profiles.
+    pub profiles: HashMap<String, VmSpec>,
+    /// Seconds to wait for the vm-agent to announce itself after the VM starts.
+    pub boot_timeout_secs: u64,
+    /// Lines of console, log and metrics output kept in memory per VM.
+    pub log_buffer_lines: usize,
+    /// Seconds the output of a destroyed VM remains available through `get_vm_logs`.
+    pub log_retention_secs: u64,
+    /// Idle VMs kept booted per profile, handed out by `create_vm` requests for that profile.
+    pub warm_pool: HashMap<String, WarmPoolConfig>,
+    /// Seconds after creation when VMs are destroyed, unless their spec sets `ttl_secs`.
+    pub ttl_secs: Option<u64>,
+    /// Seconds of inactivity after which VMs are destroyed, unless their spec sets
+    /// `idle_timeout_secs`.
+    pub idle_timeout_secs: Option<u64>,
+    /// Seconds between health checks of running VMs, 0 to disable them.
+    pub health_check_interval_secs: u64,
+    /// Run Firecracker through the jailer.
+    pub jailer: JailerConfig,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            backend: VmBackendKind::Firecracker,
+            local: LocalBackendConfig::default(),
+            max_vcpu_count: 8,
+            max_mem_size_mib: 8192,
+            max_volume_size_mib: 10240,
+            max_file_size_mib: 100,
+            max_sync_size_mib: 1024,
+            min_cid: 100,
+            max_cid: 65535,
+            image_dirs: vec![PathBuf::from("firecracker")],
+            sync_dirs: Vec::new(),
+            profiles: HashMap::new(),
+            boot_timeout_secs: 30,
+            log_buffer_lines: 1000,
+            log_retention_secs: 300,
+            warm_pool: HashMap::new(),
+            ttl_secs: None,
+            idle_timeout_secs: None,
+            health_check_interval_secs: 30,
+            jailer: JailerConfig::default(),
+        }
+    }
+}
+
+impl VmConfig {
+    /// Returns the built-in profiles overlaid with the configured ones.
+    pub fn all_profiles(&self) -> HashMap<String, VmSpec> {
+        let mut profiles = builtin_profiles();
+        profiles.extend(self.profiles.clone());
+        profiles
+    }
+}
+
+/// Profiles available without configuration. Images other than the base rootfs are built with
+/// `cargo run -p xtask -- build-rootfs <profile>`.
+fn builtin_profiles() -> HashMap<String, VmSpec> {
+    let mut profiles = HashMap::new();
+    profiles.insert(DEFAULT_PROFILE.to_string(), VmSpec::default());
+    profiles.insert(
+        "rust-toolchain".to_string(),
+        VmSpec {
+            vcpu_count: 2,
+            mem_size_mib: 2048,
+            rootfs_path: Some("firecracker/rust-toolchain.squashfs".to_string()),
+            env: BTreeMap::from([("CARGO_HOME".to_string(), "/tmp/cargo".to_string())]),
+            ..VmSpec::default()
+        },
+    );
+    profiles.insert(
+        "node".to_string(),
+        VmSpec {
+            mem_size_mib: 1024,
+            rootfs_path: Some("firecracker/node.squashfs".to_string()),
+            env: BTreeMap::from([("NODE_ENV".to_string(), "development".to_string())]),
+            ..VmSpec::default()
+        },
+    );
+    profiles
+}
+
+/// Builds the spec for a `create_vm` request: the fields present in `request` override those of
+/// the named profile, and `env` entries are merged with the profile's.
+pub(crate) fn spec_from_request(request: &str, config: &VmConfig) -> Result<VmSpec, String> {
+    let request: Value = if request.trim().is_empty() {
+        Value::Object(serde_json::Map::new())
+    } else {
+        serde_json::from_str(request).map_err(|e| format!("Invalid VM spec: {}", e))?
+    };
+    let Value::Object(overrides) = request else {
+        return Err("Invalid VM spec: expected a JSON object".to_string());
+    };
+
+    let profile_name = match overrides.get("profile") {
+        Some(Value::String(name)) => name.clone(),
+        Some(Value::Null) | None => DEFAULT_PROFILE.to_string(),
+        Some(_) => return Err("Invalid VM spec: profile must be a string".to_string()),
+    };
+    let profiles = config.all_profiles();
+    let profile = profiles.get(&profile_name).ok_or_else(|| {
+        let mut names: Vec<&String> = profiles.keys().collect();
+        names.sort();
+        format!(
+            "Unknown VM profile '{}', available: {:?}",
+            profile_name, names
+        )
+    })?;
+
+    let mut merged = serde_json::to_value(profile).map_err(|e| e.to_string())?;
+    let merged_fields = merged.as_object_mut().unwrap();
+    for (key, value) in overrides {
+        match (key.as_str(), merged_fields.get_mut(&key), value) {
+            ("env", Some(Value::Object(env)), Value::Object(extra)) => env.extend(extra),
+            (_, _, value) => {
+                merged_fields.insert(key, value);
+            }
+        }
+    }
+
+    let mut spec: VmSpec =
+        serde_json::from_value(merged).map_err(|e| format!("Invalid VM spec: {}", e))?;
+    spec.profile = Some(profile_name);
+    Ok(spec)
+}
+
+/// A `VmSpec` that passed validation, with image paths resolved on the host.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ResolvedVmSpec {
+    pub vcpu_count: u8,
+    pub mem_size_mib: u32,
+    pub smt: bool,
+    pub boot_args: String,
+    pub kernel_path: PathBuf,
+    pub rootfs_path: PathBuf,
+    pub volumes: Vec<VmVolume>,
+}
+
+/// Checks a requested spec against the host limits.
+pub(crate) fn resolve_spec(spec: &VmSpec, config: &VmConfig) -> Result<ResolvedVmSpec, String> {
+    if spec.vcpu_count == 0 || spec.vcpu_count > config.max_vcpu_count {
+        return Err(format!(
+            "vcpu_count must be between 1 and {}, got {}",
+            config.max_vcpu_count, spec.vcpu_count
+        ));
+    }
+    if spec.smt && spec.vcpu_count > 1 && !spec.vcpu_count.is_multiple_of(2) {
+        return Err(format!(
+            "vcpu_count must be 1 or even when smt is enabled, got {}",
+            spec.vcpu_count
+        ));
+    }
+    // Firecracker needs some memory to boot the kernel at all
+    if spec.mem_size_mib < 128 || spec.mem_size_mib > config.max_mem_size_mib {
+        return Err(format!(
+            "mem_size_mib must be between 128 and {}, got {}",
+            config.max_mem_size_mib, spec.mem_size_mib
+        ));
+    }
+
+    let mut extra_args = String::new();
+    if let Some(extra) = spec.boot_args.as_deref().map(str::trim) {
+        if extra.chars().any(|c| c.is_control()) {
+            return Err("boot_args must not contain control characters".to_string());
+        }
+        extra_args.push_str(extra);
+    }
+    // The kernel hands unrecognised KEY=VALUE parameters to init as environment variables
+    for (key, value) in &spec.env {
+        validate_env(key, value)?;
+        if !extra_args.is_empty() {
+            extra_args.push(' ');
+        }
+        extra_args.push_str(&format!("{}={}", key, value));
+    }
+    if let Some(volumes_arg) = volumes::resolve_volumes(spec, config)? {
+        if !extra_args.is_empty() {
+            extra_args.push(' ');
+        }
+        extra_args.push_str(&volumes_arg);
+    }
+    if extra_args.len() > MAX_EXTRA_BOOT_ARGS_LEN {
+        return Err(format!(
+            "boot_args, env and volumes must be at most {} bytes together",
+            MAX_EXTRA_BOOT_ARGS_LEN
+        ));
+    }
+    let mut boot_args = BASE_BOOT_ARGS.to_string();
+    if !extra_args.is_empty() {
+        boot_args.push(' ');
+        boot_args.push_str(&extra_args);
+    }
+
+    let kernel_path = spec.kernel_path.as_deref().unwrap_or(DEFAULT_KERNEL_PATH);
+    let rootfs_path = spec.rootfs_path.as_deref().unwrap_or(DEFAULT_ROOTFS_PATH);
+    // The local backend boots no kernel, so its images need not exist
+    let (kernel_path, rootfs_path) = if config.backend == VmBackendKind::Local {
+        (PathBuf::from(kernel_path), PathBuf::from(rootfs_path))
+    } else {
+        (
+            resolve_image(kernel_path, "Kernel image", config)?,
+            resolve_image(rootfs_path, "Rootfs image", config)?,
+        )
+    };
+
+    Ok(ResolvedVmSpec {
+        vcpu_count: spec.vcpu_count,
+        mem_size_mib: spec.mem_size_mib,
+        smt: spec.smt,
+        boot_args,
+        kernel_path,
+        rootfs_path,
+        volumes: spec.volumes.clone(),
+    })
+}
+
+/// Environment entries travel on the kernel command line, so names are limited to upper-case
+/// identifiers (lower-case words and dots are kernel parameters) and values to a single word.
+fn validate_env(key: &str, value: &str) -> Result<(), String> {
+    let valid_key = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_uppercase() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if !valid_key {
+        return Err(format!(
+            "Invalid env name '{}': use upper-case letters, digits and underscores",
+            key
+        ));
+    }
+    if value
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || c == '"')
+    {
+        return Err(format!(
+            "Invalid value for env '{}': spaces, quotes and control characters are not supported",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves an image path and makes sure it lies inside one of the allowed image directories.
+fn resolve_image(path: &str, label: &str, config: &VmConfig) -> Result<PathBuf, String> {
+    let canonical = Path::new(path)
+        .canonicalize()
+        .map_err(|_| format!("{} not found at: {}", label, path))?;
+    let allowed = config
+        .image_dirs
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| canonical.starts_with(dir));
+    if !allowed {
+        return Err(format!(
+            "{} {} is outside the allowed image directories",
+            label, path
+        ));
+    }
+    Ok(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Firecracker config whose only image directory holds a kernel and a rootfs.
+    fn config_with_images(dir: &Path) -> VmConfig {
+        std::fs::write(dir.join("vmlinux"), b"").unwrap();
+        std::fs::write(dir.join("rootfs.squashfs"), b"").unwrap();
+        VmConfig {
+            image_dirs: vec![dir.to_path_buf()],
+            ..VmConfig::default()
+        }
+    }
+
+    fn spec_in(dir: &Path) -> VmSpec {
+        VmSpec {
+            kernel_path: Some(dir.join("vmlinux").to_string_lossy().into_owned()),
+            rootfs_path: Some(dir.join("rootfs.squashfs").to_string_lossy().into_owned()),
+            ..VmSpec::default()
+        }
+    }
+
+    fn local_config() -> VmConfig {
+        VmConfig {
+            backend: VmBackendKind::Local,
+            ..VmConfig::default()
+        }
+    }
+
+    #[test]
+    fn vcpu_count_must_be_within_limits() {
+        let config = local_config();
+        for vcpu_count in [1, config.max_vcpu_count] {
+            let spec = VmSpec {
+                vcpu_count,
+                ..VmSpec::default()
+            };
+            assert_eq!(resolve_spec(&spec, &config).unwrap().vcpu_count, vcpu_count);
+        }
+        for vcpu_count in [0, config.max_vcpu_count + 1] {
+            let spec = VmSpec {
+                vcpu_count,
+                ..VmSpec::default()
+            };
+            assert!(resolve_spec(&spec, &config)
+                .unwrap_err()
+                .contains("vcpu_count must be between"));
+        }
+    }
+
+    #[test]
+    fn smt_needs_one_or_an_even_number_of_vcpus() {
+        let config = local_config();
+        for (vcpu_count, smt, ok) in [
+            (1, true, true),
+            (2, true, true),
+            (4, true, true),
+            (3, true, false),
+            (5, true, false),
+            (3, false, true),
+        ] {
+            let spec = VmSpec {
+                vcpu_count,
+                smt,
+                ..VmSpec::default()
+            };
+            match resolve_spec(&spec, &config) {
+                Ok(resolved) => {
+                    assert!(ok, "{} vCPUs with smt {} accepted", vcpu_count, smt);
+                    assert_eq!(resolved.smt, smt);
+                }
+                Err(e) => {
+                    assert!(!ok, "{} vCPUs with smt {} rejected: {}", vcpu_count, smt, e);
+                    assert!(e.contains("1 or even"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn memory_must_be_within_limits() {
+        let config = local_config();
+        for mem_size_mib in [128, config.max_mem_size_mib] {
+            let spec = VmSpec {
+                mem_size_mib,
+                ..VmSpec::default()
+            };
+            assert_eq!(
+                resolve_spec(&spec, &config).unwrap().mem_size_mib,
+                mem_size_mib
+            );
+        }
+        for mem_size_mib in [0, 127, config.max_mem_size_mib + 1] {
+            let spec = VmSpec {
+                mem_size_mib,
+                ..VmSpec::default()
+            };
+            assert!(resolve_spec(&spec, &config)
+                .unwrap_err()
+                .contains("mem_size_mib must be between"));
+        }
+    }
+
+    #[test]
+    fn boot_args_and_env_are_appended_to_the_base_command_line() {
+        let spec = VmSpec {
+            boot_args: Some("  quiet loglevel=3 ".to_string()),
+            env: BTreeMap::from([
+                ("A_1".to_string(), "x".to_string()),
+                ("_B".to_string(), "y=z".to_string()),
+            ]),
+            ..VmSpec::default()
+        };
+        let resolved = resolve_spec(&spec, &local_config()).unwrap();
+        assert_eq!(
+            resolved.boot_args,
+            format!("{} quiet loglevel=3 A_1=x _B=y=z", BASE_BOOT_ARGS)
+        );
+
+        let resolved = resolve_spec(&VmSpec::default(), &local_config()).unwrap();
+        assert_eq!(resolved.boot_args, BASE_BOOT_ARGS);
+    }
+
+    #[test]
+    fn boot_args_reject_control_characters_and_excess_length() {
+        let config = local_config();
+        let spec = VmSpec {
+            boot_args: Some("quiet\ninit=/bin/sh".to_string()),
+            ..VmSpec::default()
+        };
+        assert!(resolve_spec(&spec, &config)
+            .unwrap_err()
+            .contains("control characters"));
+
+        let spec = VmSpec {
+            boot_args: Some("a".repeat(MAX_EXTRA_BOOT_ARGS_LEN)),
+            ..VmSpec::default()
+        };
+        assert!(resolve_spec(&spec, &config).is_ok());
+        // The env entry pushes the same arguments over the limit
+        let spec = VmSpec {
+            env: BTreeMap::from([("A".to_string(), "b".to_string())]),
+            ..spec
+        };
+        assert!(resolve_spec(&spec, &config)
+            .unwrap_err()
+            .contains("at most"));
+    }
+
+    #[test]
+    fn env_names_must_be_upper_case_identifiers() {
+        for key in ["A", "_", "PATH_2", "_X9"] {
+            assert!(validate_env(key, "value").is_ok(), "{} rejected", key);
+        }
+        for key in ["", "path", "Path", "2A", "A-B", "A.B", "A B"] {
+            assert!(
+                validate_env(key, "value")
+                    .unwrap_err()
+                    .contains("Invalid env name"),
+                "{} accepted",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn env_values_must_be_a_single_word() {
+        for value in ["", "x", "a=b", "/usr/bin:/bin", "'quoted'"] {
+            assert!(validate_env("KEY", value).is_ok(), "{:?} rejected", value);
+        }
+        for value in ["a b", "a\tb", "a\nb", "\"a\"", "a\u{7}"] {
+            assert!(
+                validate_env("KEY", value)
+                    .unwrap_err()
+                    .contains("Invalid value for env 'KEY'"),
+                "{:?} accepted",
+                value
+            );
+        }
+
+        let spec = VmSpec {
+            env: BTreeMap::from([("lower".to_string(), "x".to_string())]),
+            ..VmSpec::default()
+        };
+        assert!(resolve_spec(&spec, &local_config()).is_err());
+    }
+
+    #[test]
+    fn images_must_exist_inside_the_image_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config_with_images(dir.path());
+        let resolved = resolve_spec(&spec_in(dir.path()), &config).unwrap();
+        assert_eq!(
+            resolved.kernel_path,
+            dir.path().join("vmlinux").canonicalize().unwrap()
+        );
+        assert_eq!(
+            resolved.rootfs_path,
+            dir.path().join("rootfs.squashfs").canonicalize().unwrap()
+        );
+
+        let spec = VmSpec {
+            kernel_path: Some(dir.path().join("missing").to_string_lossy().into_owned()),
+            ..spec_in(dir.path())
+        };
+        assert!(resolve_spec(&spec, &config)
+            .unwrap_err()
+            .starts_with("Kernel image not found"));
+
+        // Neither a file elsewhere nor a path escaping the directory is accepted
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("rootfs.squashfs"), b"").unwrap();
+        let escaping = dir
+            .path()
+            .join("..")
+            .join(outside.path().file_name().unwrap())
+            .join("rootfs.squashfs");
+        for rootfs_path in [outside.path().join("rootfs.squashfs"), escaping] {
+            let spec = VmSpec {
+                rootfs_path: Some(rootfs_path.to_string_lossy().into_owned()),
+                ..spec_in(dir.path())
+            };
+            assert!(resolve_spec(&spec, &config)
+                .unwrap_err()
+                .contains("outside the allowed image directories"));
+        }
+    }
+
+    #[test]
+    fn local_backend_does_not_check_images() {
+        let spec = VmSpec {
+            kernel_path: Some("/nonexistent/vmlinux".to_string()),
+            ..VmSpec::default()
+        };
+        let resolved = resolve_spec(&spec, &local_config()).unwrap();
+        assert_eq!(resolved.kernel_path, PathBuf::from("/nonexistent/vmlinux"));
+        assert_eq!(resolved.rootfs_path, PathBuf::from(DEFAULT_ROOTFS_PATH));
+    }
+
+    #[test]
+    fn requests_without_a_profile_use_the_default() {
+        let config = VmConfig::default();
+        for request in ["", "  ", "{}", r#"{"profile": null}"#] {
+            let spec = spec_from_request(request, &config).unwrap();
+            assert_eq!(spec.profile.as_deref(), Some(DEFAULT_PROFILE));
+            assert_eq!(spec.vcpu_count, VmSpec::default().vcpu_count);
+            assert_eq!(spec.mem_size_mib, VmSpec::default().mem_size_mib);
+        }
+    }
+
+    #[test]
+    fn request_fields_override_the_profile() {
+        let config = VmConfig::default();
+        let spec = spec_from_request(r#"{"profile": "rust-toolchain"}"#, &config).unwrap();
+        assert_eq!(spec.vcpu_count, 2);
+        assert_eq!(spec.mem_size_mib, 2048);
+        assert_eq!(
+            spec.rootfs_path.as_deref(),
+            Some("firecracker/rust-toolchain.squashfs")
+        );
+
+        let spec = spec_from_request(
+            r#"{"profile": "rust-toolchain", "mem_size_mib": 4096, "smt": true}"#,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(spec.profile.as_deref(), Some("rust-toolchain"));
+        assert_eq!(spec.vcpu_count, 2);
+        assert_eq!(spec.mem_size_mib, 4096);
+        assert!(spec.smt);
+        assert_eq!(
+            spec.rootfs_path.as_deref(),
+            Some("firecracker/rust-toolchain.squashfs")
+        );
+    }
+
+    #[test]
+    fn request_env_is_merged_with_the_profile_env() {
+        let config = VmConfig::default();
+        let spec = spec_from_request(
+            r#"{"profile": "node", "env": {"NODE_ENV": "production", "PORT": "8080"}}"#,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            spec.env,
+            BTreeMap::from([
+                ("NODE_ENV".to_string(), "production".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+            ])
+        );
+
+        let spec = spec_from_request(r#"{"profile": "node", "env": {"A": "b"}}"#, &config).unwrap();
+        assert_eq!(
+            spec.env.get("NODE_ENV").map(String::as_str),
+            Some("development")
+        );
+        assert_eq!(spec.env.get("A").map(String::as_str), Some("b"));
+    }
+
+    #[test]
+    fn configured_profiles_replace_builtin_ones() {
+        let config = VmConfig {
+            profiles: HashMap::from([
+                (
+                    "node".to_string(),
+                    VmSpec {
+                        vcpu_count: 4,
+                        ..VmSpec::default()
+                    },
+                ),
+                (
+                    "big".to_string(),
+                    VmSpec {
+                        mem_size_mib: 4096,
+                        ..VmSpec::default()
+                    },
+                ),
+            ]),
+            ..VmConfig::default()
+        };
+        let spec = spec_from_request(r#"{"profile": "node"}"#, &config).unwrap();
+        assert_eq!(spec.vcpu_count, 4);
+        assert!(spec.env.is_empty());
+        let spec = spec_from_request(r#"{"profile": "big"}"#, &config).unwrap();
+        assert_eq!(spec.mem_size_mib, 4096);
+        assert!(spec_from_request(r#"{"profile": "rust-toolchain"}"#, &config).is_ok());
+    }
+
+    #[test]
+    fn unknown_profiles_and_malformed_requests_are_rejected() {
+        let config = VmConfig::default();
+        let e = spec_from_request(r#"{"profile": "missing"}"#, &config).unwrap_err();
+        assert!(e.starts_with("Unknown VM profile 'missing'"), "{}", e);
+        assert!(e.contains(DEFAULT_PROFILE), "{}", e);
+
+        for request in [
+            r#"{"profile": 3}"#,
+            "[]",
+            "not json",
+            r#"{"vcpu_count": "two"}"#,
+        ] {
+            assert!(
+                spec_from_request(request, &config)
+                    .unwrap_err()
+                    .starts_with("Invalid VM spec"),
+                "{} accepted",
+                request
+            );
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Data volumes: ext4 images attached to VMs as block devices after the root filesystem and
+//! mounted by the vm-agent. A scratch volume is created empty in the VM directory and deleted with
+//! it. A named volume lives in `<state_dir>/volumes`, keeps its data across VMs and is attached to
+//! one VM at a time.
+
+use super::backend::VmBackendKind;
+use super::vm_dir::VmDir;
+use super::vm_spec::VmConfig;
+use hyperlight_agents_common::{VmSpec, VmVolume, VM_AGENT_VOLUMES_ENV};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// Most volumes a VM may have; each takes a virtio device.
+const MAX_VOLUMES: usize = 8;
+
+/// Smallest volume mkfs.ext4 formats with a usable journal.
+const MIN_VOLUME_SIZE_MIB: u32 = 16;
+
+const VOLUME_EXTENSION: &str = "ext4";
+
+/// File name prefix of the images of a VM in its directory.
+const IMAGE_PREFIX: &str = "volume-";
+
+/// A named volume as reported by `list_volumes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeSummary {
+    pub name: String,
+    pub size_mib: u64,
+    /// VM the volume is attached to.
+    pub attached_to: Option<String>,
+}
+
+/// Parameters of a `create_volume` request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VolumeRequest {
+    pub size_mib: u32,
+}
+
+impl Default for VolumeRequest {
+    fn default() -> Self {
+        Self {
+            size_mib: VmVolume::default().size_mib,
+        }
+    }
+}
+
+/// Checks the volumes of `spec` and returns the boot argument that tells the vm-agent where to
+/// mount them, `None` without volumes. Volumes follow the root filesystem `vda` in order.
+pub(crate) fn resolve_volumes(spec: &VmSpec, config: &VmConfig) -> Result<Option<String>, String> {
+    if spec.volumes.is_empty() {
+        return Ok(None);
+    }
+    if config.backend == VmBackendKind::Local {
+        return Err("The local backend cannot attach volumes".to_string());
+    }
+    if spec.volumes.len() > MAX_VOLUMES {
+        return Err(format!("A VM can have at most {} volumes", MAX_VOLUMES));
+    }
+
+    let mut mount_paths = HashSet::new();
+    let mut names = HashSet::new();
+    let mut entries = Vec::new();
+    for (index, volume) in spec.volumes.iter().enumerate() {
+        validate_mount_path(&volume.mount_path)?;
+        if !mount_paths.insert(volume.mount_path.as_str()) {
+            return Err(format!(
+                "Volume mount path {} is used twice",
+                volume.mount_path
+            ));
+        }
+        if let Some(name) = &volume.name {
+            validate_name(name)?;
+            if !names.insert(name.as_str()) {
+                return Err(format!("Volume {} is attached twice", name));
+            }
+        }
+        validate_size(volume.size_mib, config)?;
+
+        let mut entry = format!("{}:{}", device_name(index), volume.mount_path);
+        if volume.read_only {
+            entry.push_str(":ro");
+        }
+        entries.push(entry);
+    }
+    Ok(Some(format!(
+        "{}={}",
+        VM_AGENT_VOLUMES_ENV,
+        entries.join(",")
+    )))
+}
+
+/// Guest block device of the volume at `index`; the root filesystem is `vda`.
+fn device_name(index: usize) -> String {
+    format!("vd{}", (b'b' + index as u8) as char)
+}
+
+/// Mount paths travel on the kernel command line, so they are limited to a plain absolute path.
+fn validate_mount_path(path: &str) -> Result<(), String> {
+    let valid = path.starts_with('/')
+        && path != "/"
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '.'))
+        && !path.split('/').any(|part| part == "." || part == "..");
+    if !valid {
+        return Err(format!(
+            "Invalid volume mount path '{}': use an absolute path of letters, digits, '_', '-' and '.'",
+            path
+        ));
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!(
+            "Invalid volume name '{}': use up to 64 letters, digits, '_' and '-'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn validate_size(size_mib: u32, config: &VmConfig) -> Result<(), String> {
+    if size_mib < MIN_VOLUME_SIZE_MIB || size_mib > config.max_volume_size_mib {
+        return Err(format!(
+            "Volume size_mib must be between {} and {}, got {}",
+            MIN_VOLUME_SIZE_MIB, config.max_volume_size_mib, size_mib
+        ));
+    }
+    Ok(())
+}
+
+/// The named volumes in `<state_dir>/volumes` and the VMs they are attached to.
+pub(crate) struct VolumeStore {
+    dir: PathBuf,
+    max_size_mib: u32,
+    attached: Mutex<HashMap<String, String>>,
+}
+
+impl VolumeStore {
+    pub(crate) fn new(state_dir: &Path, config: &VmConfig) -> Self {
+        Self {
+            dir: state_dir.join("volumes"),
+            max_size_mib: config.max_volume_size_mib,
+            attached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn create(
+        self: &Arc<Self>,
+        name: &str,
+        size_mib: u32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        validate_name(name)?;
+        if size_mib < MIN_VOLUME_SIZE_MIB || size_mib > self.max_size_mib {
+            return Err(format!(
+                "Volume size_mib must be between {} and {}, got {}",
+                MIN_VOLUME_SIZE_MIB, self.max_size_mib, size_mib
+            )
+            .into());
+        }
+        // Claimed while it is formatted, so a VM cannot attach it half made
+        let _lease = self.claim(name, "create_volume")?;
+        if self.path(name).exists() {
+            return Err(format!("Volume {} already exists", name).into());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        format_image(&self.path(name), size_mib)?;
+        log::info!("Created volume {} ({} MiB)", name, size_mib);
+        Ok(())
+    }
+
+    pub(crate) fn list(&self) -> Vec<VolumeSummary> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let attached = self.attached.lock().unwrap();
+        let mut volumes: Vec<VolumeSummary> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == VOLUME_EXTENSION))
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_str()?.to_string();
+                let size = std::fs::metadata(&path).ok()?.len();
+                Some(VolumeSummary {
+                    attached_to: attached.get(&name).cloned(),
+                    name,
+                    size_mib: size / (1024 * 1024),
+                })
+            })
+            .collect();
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        volumes
+    }
+
+    pub(crate) fn delete(
+        self: &Arc<Self>,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        validate_name(name)?;
+        let _lease = self.claim(name, "delete_volume")?;
+        match std::fs::remove_file(self.path(name)) {
+            Ok(()) => {
+                log::info!("Deleted volume {}", name);
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(format!("Volume {} not found", name).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Marks the volume `name` as attached to `vm_id` until the lease is dropped. Fails if it is
+    /// attached to another VM.
+    pub(crate) fn claim(
+        self: &Arc<Self>,
+        name: &str,
+        vm_id: &str,
+    ) -> Result<VolumeLease, Box<dyn std::error::Error + Send + Sync>> {
+        let mut attached = self.attached.lock().unwrap();
+        if let Some(owner) = attached.get(name) {
+            return Err(format!("Volume {} is in use by {}", name, owner).into());
+        }
+        attached.insert(name.to_string(), vm_id.to_string());
+        Ok(VolumeLease {
+            name: name.to_string(),
+            store: self.clone(),
+        })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, VOLUME_EXTENSION))
+    }
+}
+
+/// A named volume attached to a VM, detached when dropped.
+pub struct VolumeLease {
+    name: String,
+    store: Arc<VolumeStore>,
+}
+
+impl Drop for VolumeLease {
+    fn drop(&mut self) {
+        self.store.attached.lock().unwrap().remove(&self.name);
+    }
+}
+
+/// A volume ready to be attached to a VM that is about to boot.
+pub(crate) struct PreparedVolume {
+    /// Image on the host, or in the jail for a jailed VM.
+    pub path: String,
+    pub read_only: bool,
+    pub lease: Option<VolumeLease>,
+}
+
+/// Claims the named volumes of a VM, creating those that do not exist yet, and creates its scratch
+/// volumes in the VM directory.
+pub(crate) fn prepare(
+    store: &Arc<VolumeStore>,
+    vm_id: &str,
+    dir: &VmDir,
+    volumes: &[VmVolume],
+) -> Result<Vec<PreparedVolume>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut prepared = Vec::new();
+    for (index, volume) in volumes.iter().enumerate() {
+        let file_name = format!("{}{}.{}", IMAGE_PREFIX, index, VOLUME_EXTENSION);
+        let (image, lease) = match &volume.name {
+            Some(name) => {
+                let lease = store.claim(name, vm_id)?;
+                let image = store.path(name);
+                if !image.exists() {
+                    std::fs::create_dir_all(&store.dir)?;
+                    format_image(&image, volume.size_mib)?;
+                    log::info!("Created volume {} for VM {}", name, vm_id);
+                }
+                (image, Some(lease))
+            }
+            None => {
+                let image = dir.path().join(&file_name);
+                format_image(&image, volume.size_mib)?;
+                (image, None)
+            }
+        };
+        // A jailed Firecracker only sees the files in its chroot, and writes as the jail user
+        let path = match dir.jail() {
+            Some(jail) => {
+                if lease.is_some() {
+                    jail.add_volume(&image, &file_name)?;
+                }
+                dir.grant(&dir.path().join(&file_name))?;
+                file_name
+            }
+            // Firecracker takes the path in its JSON configuration
+            None => image
+                .to_str()
+                .ok_or_else(|| format!("Volume image path {} is not UTF-8", image.display()))?
+                .to_string(),
+        };
+        prepared.push(PreparedVolume {
+            path,
+            read_only: volume.read_only,
+            lease,
+        });
+    }
+    Ok(prepared)
+}
+
+/// Removes the volume images from the directory of a stopped VM, which is kept a while longer for
+/// its output: the scratch volumes, and the links to named volumes in a jail.
+pub(crate) fn remove_images(vm_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(vm_dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let is_volume = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(IMAGE_PREFIX) && name.ends_with(VOLUME_EXTENSION));
+        if is_volume {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Creates a sparse image of `size_mib` and formats it, written to the side so a failed format
+/// leaves nothing behind.
+fn format_image(
+    path: &Path,
+    size_mib: u32,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let staging = path.with_extension("tmp");
+    File::create(&staging)?.set_len(size_mib as u64 * 1024 * 1024)?;
+    let output = Command::new("mkfs.ext4")
+        .arg("-q")
+        .arg("-F")
+        .arg(&staging)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Ok(std::fs::rename(&staging, path)?),
+        Ok(output) => {
+            let _ = std::fs::remove_file(&staging);
+            Err(format!(
+                "mkfs.ext4 failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&staging);
+            Err(format!("Failed to run mkfs.ext4: {}", e).into())
+        }
+    }
+}
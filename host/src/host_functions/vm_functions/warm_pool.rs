@@ -0,0 +1,196 @@
+use super::backend::VmBackend;
+use super::firecracker::{BootOrigin, BootedVm};
+use super::registry::{self, VmRecord, VmRegistry};
+use super::vm_spec::{self, ResolvedVmSpec};
+use super::VmManager;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the pool is checked for missing or expired VMs.
+const REFILL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait before booting for a profile again after a failed boot.
+const FAILURE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Warm pool settings for one profile.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WarmPoolConfig {
+    /// Number of idle, ready VMs to keep.
+    pub size: usize,
+    /// Seconds after which an idle VM is replaced by a fresh one.
+    pub max_age_secs: u64,
+}
+
+impl Default for WarmPoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            max_age_secs: 600,
+        }
+    }
+}
+
+struct PooledVm {
+    booted: BootedVm,
+    spec: ResolvedVmSpec,
+    booted_at: Instant,
+}
+
+/// Booted, agent-ready VMs waiting to be handed out by `create_vm`, per profile. They are in the
+/// registry until handed out, so a restarted host removes them.
+pub(crate) struct WarmPool {
+    vms: Mutex<HashMap<String, VecDeque<PooledVm>>>,
+    registry: Arc<VmRegistry>,
+}
+
+impl WarmPool {
+    pub(crate) fn new(registry: Arc<VmRegistry>) -> Self {
+        Self {
+            vms: Mutex::new(HashMap::new()),
+            registry,
+        }
+    }
+
+    /// Takes an idle VM of `profile` if one was booted with exactly `spec`.
+    pub(crate) fn take(&self, profile: &str, spec: &ResolvedVmSpec) -> Option<BootedVm> {
+        let mut vms = self.vms.lock().unwrap();
+        let pool = vms.get_mut(profile)?;
+        let index = pool.iter().position(|vm| &vm.spec == spec)?;
+        let mut booted = pool.remove(index)?.booted;
+        booted.origin = BootOrigin::WarmPool;
+        self.registry.remove(&registry::pool_key(booted.child.id()));
+        Some(booted)
+    }
+
+    fn len(&self, profile: &str) -> usize {
+        self.vms
+            .lock()
+            .unwrap()
+            .get(profile)
+            .map_or(0, VecDeque::len)
+    }
+
+    fn add(&self, profile: &str, booted: BootedVm, spec: ResolvedVmSpec, backend: &dyn VmBackend) {
+        self.registry
+            .save(&VmRecord::for_pooled(backend, &booted, profile));
+        self.vms
+            .lock()
+            .unwrap()
+            .entry(profile.to_string())
+            .or_default()
+            .push_back(PooledVm {
+                booted,
+                spec,
+                booted_at: Instant::now(),
+            });
+    }
+
+    /// Stops the VMs of `profile` that have been idle longer than `max_age`.
+    fn recycle_expired(&self, profile: &str, max_age: Duration, backend: &dyn VmBackend) {
+        let expired: Vec<PooledVm> = {
+            let mut vms = self.vms.lock().unwrap();
+            let Some(pool) = vms.get_mut(profile) else {
+                return;
+            };
+            let (expired, fresh): (VecDeque<PooledVm>, VecDeque<PooledVm>) = pool
+                .drain(..)
+                .partition(|vm| vm.booted_at.elapsed() > max_age);
+            *pool = fresh;
+            expired.into()
+        };
+        for vm in expired {
+            log::debug!("Recycling idle warm pool VM of profile {}", profile);
+            self.stop(vm, backend);
+        }
+    }
+
+    /// Stops all idle VMs.
+    pub(crate) fn clear(&self, backend: &dyn VmBackend) {
+        let pools: Vec<VecDeque<PooledVm>> = self
+            .vms
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, pool)| pool)
+            .collect();
+        for vm in pools.into_iter().flatten() {
+            self.stop(vm, backend);
+        }
+    }
+
+    fn stop(&self, vm: PooledVm, backend: &dyn VmBackend) {
+        let key = registry::pool_key(vm.booted.child.id());
+        vm.booted.stop(backend);
+        self.registry.remove(&key);
+    }
+}
+
+/// Keeps the configured pools filled in the background until the manager shuts down.
+pub(crate) fn start_refill(manager: Weak<VmManager>) {
+    thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut failed_at: HashMap<String, Instant> = HashMap::new();
+        loop {
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+            if manager.shutdown_flag.load(Ordering::SeqCst) {
+                break;
+            }
+            for (profile, pool_config) in &manager.config.warm_pool {
+                let max_age = Duration::from_secs(pool_config.max_age_secs);
+                manager
+                    .warm_pool
+                    .recycle_expired(profile, max_age, &*manager.backend);
+                if manager.warm_pool.len(profile) >= pool_config.size
+                    || failed_at
+                        .get(profile)
+                        .is_some_and(|at| at.elapsed() < FAILURE_BACKOFF)
+                {
+                    continue;
+                }
+                match rt.block_on(boot_pooled_vm(&manager, profile)) {
+                    // A VM that finished booting during shutdown would otherwise outlive the host
+                    Ok((booted, _)) if manager.shutdown_flag.load(Ordering::SeqCst) => {
+                        booted.stop(&*manager.backend);
+                    }
+                    Ok((booted, spec)) => {
+                        failed_at.remove(profile);
+                        manager
+                            .warm_pool
+                            .add(profile, booted, spec, &*manager.backend);
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to boot warm pool VM of profile {}: {}", profile, e);
+                        failed_at.insert(profile.clone(), Instant::now());
+                    }
+                }
+            }
+            drop(manager);
+            thread::sleep(REFILL_INTERVAL);
+        }
+    });
+}
+
+async fn boot_pooled_vm(
+    manager: &VmManager,
+    profile: &str,
+) -> Result<(BootedVm, ResolvedVmSpec), Box<dyn std::error::Error + Send + Sync>> {
+    let request = serde_json::json!({ "profile": profile }).to_string();
+    let spec = vm_spec::spec_from_request(&request, &manager.config)?;
+    let spec = vm_spec::resolve_spec(&spec, &manager.config)?;
+    // A pooled VM would hold its named volumes for whichever VM it becomes
+    if !spec.volumes.is_empty() {
+        return Err("profiles with volumes cannot be pooled".into());
+    }
+    let booted = manager
+        .backend
+        .start_vm(manager, &format!("warm-pool-{}", profile), &spec)
+        .await?;
+    Ok((booted, spec))
+}
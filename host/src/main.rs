@@ -4,11 +4,17 @@ use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use agents::agent::AgentServices;
+use host_functions::cassette::Cassette;
+use host_functions::kv_store::KvStore;
+use host_functions::network_functions;
+use host_functions::secrets::SecretStore;
 use host_functions::vm_functions::VmManager;
 
 use mcp::mcp_server;
 
 mod agents;
+mod config;
 mod host_functions;
 mod host_logger;
 mod mcp;
@@ -19,7 +25,6 @@ use log::{debug, error, info};
 //use opentelemetry::KeyValue;
 //use opentelemetry_otlp::{Protocol, WithExportConfig};
 //use opentelemetry_sdk::Resource;
-use reqwest::Client;
 
 #[tokio::main]
 async fn main() -> hyperlight_host::Result<()> {
@@ -51,37 +56,68 @@ async fn main() -> hyperlight_host::Result<()> {
     global::set_tracer_provider(tracer_provider);
     */
 
+    let host_config = match config::HostConfig::load() {
+        Ok(host_config) => Arc::new(host_config),
+        Err(e) => {
+            error!("{}", e);
+            return Err(hyperlight_host::HyperlightError::Error(e.to_string()));
+        }
+    };
+
+    let cassette = match host_config.http_cassette.clone().map(Cassette::open) {
+        Some(Ok(cassette)) => Some(Arc::new(cassette)),
+        Some(Err(e)) => {
+            error!("{}", e);
+            return Err(hyperlight_host::HyperlightError::Error(e.to_string()));
+        }
+        None => None,
+    };
+
+    let kv_store = match KvStore::open(host_config.state_dir.join("kv")) {
+        Ok(kv_store) => Arc::new(kv_store),
+        Err(e) => {
+            error!("{}", e);
+            return Err(hyperlight_host::HyperlightError::Error(e.to_string()));
+        }
+    };
+
+    let secret_store = match host_config.secrets_file.as_deref().map(SecretStore::open) {
+        Some(Ok(secret_store)) => Arc::new(secret_store),
+        Some(Err(e)) => {
+            error!("{}", e);
+            return Err(hyperlight_host::HyperlightError::Error(e.to_string()));
+        }
+        None => Arc::new(SecretStore::default()),
+    };
+
     // Create the MCP server manager
     let mcp_server_manager = mcp_server::McpServerManager::new();
 
-    let reqwest_client: reqwest::Client = Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-        .unwrap();
+    let reqwest_client: reqwest::Client = network_functions::client_builder().build().unwrap();
 
     let http_client = Arc::new(reqwest_client);
 
     // Create VM manager and start VSOCK servers
-    let vm_manager = Arc::new(VmManager::new());
+    let vm_manager = Arc::new(VmManager::new(
+        host_config.vm.clone(),
+        &host_config.state_dir,
+    ));
+    // Pick up the VMs a previous run left running before any new ones start
+    vm_manager.reattach_vms().await;
     if let Err(e) = vm_manager.start_vsock_server(1234) {
         error!("Failed to start VSOCK server: {}", e);
     } else {
         debug!("VSOCK server started on port 1234");
     }
 
-    // Start HTTP proxy VSOCK server
-    if let Err(e) = vm_manager.start_http_proxy_server(1235) {
-        error!("Failed to start HTTP proxy VSOCK server: {}", e);
-    } else {
-        debug!("HTTP proxy VSOCK server started on port 1235");
-    }
+    // Boot the idle VMs of the configured warm pools in the background
+    vm_manager.start_warm_pool();
 
-    // Start log listener VSOCK server
-    if let Err(e) = vm_manager.start_log_listener_server(1236) {
-        error!("Failed to start HTTP proxy VSOCK server: {}", e);
-    } else {
-        debug!("HTTP proxy VSOCK server started on port 1236");
-    }
+    // Destroy VMs whose TTL or idle timeout has passed
+    vm_manager.start_reaper();
+
+    // Probe running VMs and mark unresponsive or exited ones unhealthy
+    vm_manager.start_health_checks();
 
     let agent_ids: Vec<String> = std::fs::read_dir("./guest/target/x86_64-unknown-none/debug/")
         .or_else(|_| std::fs::read_dir("./guest/target/x86_64-unknown-none/release/"))
@@ -101,15 +137,22 @@ async fn main() -> hyperlight_host::Result<()> {
             })
         })
         .collect();
+    let agent_services = AgentServices {
+        http_client: http_client.clone(),
+        vm_manager: vm_manager.clone(),
+        host_config: host_config.clone(),
+        cassette,
+        kv_store,
+        secret_store,
+    };
     let mut agents = Vec::new();
 
     for agent_id in agent_ids {
         debug!("Creating agent for: {}", agent_id);
         match agents::agent::create_agent(
             agent_id.to_string(),
-            http_client.clone(),
             agent_id.to_string(),
-            vm_manager.clone(),
+            &agent_services,
         ) {
             Ok(agent) => {
                 debug!("✓ Agent created successfully: {}", agent.mcp_tool.name);
@@ -207,7 +250,7 @@ async fn main() -> hyperlight_host::Result<()> {
     vm_manager_cleanup.shutdown();
 
     // Perform emergency cleanup as well
-    VmManager::emergency_cleanup();
+    vm_manager_cleanup.emergency_cleanup();
 
     // Signal all agent threads to shutdown
     info!("Signaling agent threads to shutdown... Setting shutdown flag.");
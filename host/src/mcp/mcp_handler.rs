@@ -10,7 +10,8 @@ use hyperlight_agents_common::{
 //};
 use rust_mcp_schema::{
     schema_utils::CallToolError, CallToolRequest, CallToolResult, ListToolsRequest,
-    ListToolsResult, RpcError, Tool, ToolInputSchema,
+    ListToolsResult, LoggingMessageNotificationParams, RpcError, SetLevelRequest, Tool,
+    ToolInputSchema,
 };
 use rust_mcp_sdk::{mcp_server::ServerHandler, McpServer};
 use serde_json::{Map, Value};
@@ -18,19 +19,27 @@ use std::collections::HashMap;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 
-use crate::mcp::mcp_server::{MCP_AGENT_REQUEST_IDS, MCP_RESPONSE_CHANNELS};
+use crate::mcp::mcp_server::{
+    set_log_level, McpSessions, MCP_AGENT_REQUEST_IDS, MCP_LOG_CHANNELS, MCP_RESPONSE_CHANNELS,
+};
 
 use super::mcp_server::MCP_AGENT_METADATA;
 
 // Custom server handler for MCP
 pub struct HyperlightAgentHandler {
     pub agent_channels: Arc<Mutex<HashMap<String, Sender<(Option<String>, String)>>>>,
+    pub sessions: Arc<McpSessions>,
 }
 
 #[async_trait]
 impl ServerHandler for HyperlightAgentHandler {
+    // Learn the session id, so logging levels can be kept per session
+    async fn on_initialized(&self, runtime: &dyn McpServer) {
+        self.sessions.initialized(runtime).await;
+    }
+
     // Handle ListToolsRequest, return list of available tools
 
     async fn handle_list_tools_request(
@@ -76,11 +85,32 @@ impl ServerHandler for HyperlightAgentHandler {
         //})
     }
 
+    // Handle SetLevelRequest, enabling forwarding of guest logs at or above the level to the
+    // requests of this session
+    async fn handle_set_level_request(
+        &self,
+        request: SetLevelRequest,
+        runtime: &dyn McpServer,
+    ) -> Result<rust_mcp_schema::Result, RpcError> {
+        match self.sessions.id(runtime) {
+            Some(session_id) => {
+                log::debug!(
+                    "MCP session {} set logging level to {}",
+                    session_id,
+                    request.params.level
+                );
+                set_log_level(session_id, request.params.level);
+            }
+            None => log::debug!("Ignoring logging level set before the session initialized"),
+        }
+        Ok(rust_mcp_schema::Result::default())
+    }
+
     // Handle CallToolRequest, communicate with the agent and return the result
     async fn handle_call_tool_request(
         &self,
         request: CallToolRequest,
-        _runtime: &dyn McpServer,
+        runtime: &dyn McpServer,
     ) -> Result<CallToolResult, CallToolError> {
         let tool_name = request.tool_name();
 
@@ -127,6 +157,13 @@ impl ServerHandler for HyperlightAgentHandler {
             response_channels.insert(request_id.clone(), resp_tx);
         }
 
+        // Create a channel for guest log notifications emitted while the request runs
+        let (log_tx, log_rx) = mpsc::unbounded_channel::<LoggingMessageNotificationParams>();
+        {
+            let mut log_channels = MCP_LOG_CHANNELS.lock().unwrap();
+            log_channels.insert(request_id.clone(), (self.sessions.id(runtime), log_tx));
+        }
+
         let parameters = request.params.clone().arguments.unwrap_or_default();
 
         // Convert parameters to a JSON string to pass to the agent
@@ -146,6 +183,8 @@ impl ServerHandler for HyperlightAgentHandler {
         // Use .await to fix the Send future error
         if let Err(e) = agent_tx.clone().send((Some(mcp_message), function_name)) {
             log::debug!("Failed to send message to agent '{}': {}", tool_name, e);
+            MCP_RESPONSE_CHANNELS.lock().unwrap().remove(&request_id);
+            MCP_LOG_CHANNELS.lock().unwrap().remove(&request_id);
             return Err(CallToolError::new(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 format!("Failed to send message to agent: {}", e),
@@ -165,7 +204,7 @@ impl ServerHandler for HyperlightAgentHandler {
         );
 
         // Wait for response with timeout
-        let response = match wait_for_response(resp_rx, 120).await {
+        let response = match wait_for_response(resp_rx, log_rx, runtime, 120).await {
             Some(resp) => {
                 log::debug!(
                     "Received response from agent '{}', request_id: {}",
@@ -180,6 +219,7 @@ impl ServerHandler for HyperlightAgentHandler {
                     tool_name,
                     request_id
                 );
+                MCP_LOG_CHANNELS.lock().unwrap().remove(&request_id);
                 return Err(CallToolError::new(std::io::Error::new(
                     std::io::ErrorKind::TimedOut,
                     "Timeout waiting for agent response",
@@ -196,6 +236,7 @@ impl ServerHandler for HyperlightAgentHandler {
             let mut response_channels = MCP_RESPONSE_CHANNELS.lock().unwrap();
             response_channels.remove(&request_id);
             log::debug!("Cleaned up response channel for request_id: {}", request_id);
+            MCP_LOG_CHANNELS.lock().unwrap().remove(&request_id);
 
             // Also make sure we remove any dangling request IDs for this request
             if let Ok(mut request_ids) = MCP_AGENT_REQUEST_IDS.lock() {
@@ -220,7 +261,13 @@ impl ServerHandler for HyperlightAgentHandler {
     }
 }
 
-async fn wait_for_response(rx: oneshot::Receiver<String>, timeout_seconds: u64) -> Option<String> {
+/// Waits for the agent's response, relaying its log notifications to the client in the meantime.
+async fn wait_for_response(
+    mut rx: oneshot::Receiver<String>,
+    mut log_rx: mpsc::UnboundedReceiver<LoggingMessageNotificationParams>,
+    runtime: &dyn McpServer,
+    timeout_seconds: u64,
+) -> Option<String> {
     let timeout = Duration::from_secs(timeout_seconds);
 
     log::debug!(
@@ -228,8 +275,30 @@ async fn wait_for_response(rx: oneshot::Receiver<String>, timeout_seconds: u64)
         timeout_seconds
     );
 
-    // Use tokio timeout for the oneshot receiver
-    match tokio::time::timeout(timeout, rx).await {
+    let relay = async {
+        loop {
+            tokio::select! {
+                biased;
+                Some(params) = log_rx.recv() => {
+                    if let Err(e) = runtime.send_logging_message(params).await {
+                        log::warn!("Failed to send logging notification: {:?}", e);
+                    }
+                }
+                response = &mut rx => {
+                    // Flush records logged just before the response
+                    while let Ok(params) = log_rx.try_recv() {
+                        if let Err(e) = runtime.send_logging_message(params).await {
+                            log::warn!("Failed to send logging notification: {:?}", e);
+                        }
+                    }
+                    return response;
+                }
+            }
+        }
+    };
+
+    // Use tokio timeout for the whole exchange
+    match tokio::time::timeout(timeout, relay).await {
         Ok(Ok(response)) => {
             log::debug!("MCP server received response");
             Some(response)
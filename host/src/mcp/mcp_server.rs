@@ -1,18 +1,20 @@
-use hyperlight_agents_common::{traits::agent::Param, Tool};
+use hyperlight_agents_common::structs::log_record::{GuestLogLevel, GuestLogRecord};
+use hyperlight_agents_common::{traits::agent::Param, CommandOutput, Tool};
 use rust_mcp_schema::{
-    Implementation, InitializeResult, ServerCapabilities, ServerCapabilitiesTools,
-    LATEST_PROTOCOL_VERSION,
+    Implementation, InitializeResult, LoggingLevel, LoggingMessageNotificationParams,
+    ServerCapabilities, ServerCapabilitiesTools, LATEST_PROTOCOL_VERSION,
 };
 use rust_mcp_sdk::mcp_server::{
     hyper_server::{self},
-    HyperServerOptions,
+    HyperServerOptions, SessionStore,
 };
+use rust_mcp_sdk::{McpServer, SessionId};
 use serde::Serialize;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
-use std::sync::{Arc, Mutex};
-use tokio::sync::oneshot;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{mpsc, oneshot};
 
 use crate::mcp::mcp_handler::HyperlightAgentHandler;
 
@@ -21,6 +23,180 @@ lazy_static::lazy_static! {
     pub static ref MCP_RESPONSE_CHANNELS: Mutex<HashMap<String, oneshot::Sender<String>>> = Mutex::new(HashMap::new());
     pub static ref MCP_AGENT_METADATA: Mutex<HashMap<String, Tool>> = Mutex::new(HashMap::new());
     pub static ref MCP_AGENT_REQUEST_IDS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    // Log notifications for in-flight requests and the session that made them, keyed by request id
+    pub static ref MCP_LOG_CHANNELS: Mutex<HashMap<String, LogChannel>> = Mutex::new(HashMap::new());
+    // Minimum level each session set with logging/setLevel; nothing is forwarded to a session until it sets one
+    static ref MCP_LOG_LEVELS: Mutex<HashMap<SessionId, LoggingLevel>> = Mutex::new(HashMap::new());
+}
+
+/// Where log notifications of a request go, with the session that made the request.
+pub type LogChannel = (
+    Option<SessionId>,
+    mpsc::UnboundedSender<LoggingMessageNotificationParams>,
+);
+
+/// The ids of the MCP client sessions. The SDK serves each session with its own runtime but does
+/// not pass the session id to the handler, so the id is looked up in the SDK's session store
+/// when the session initializes. Sessions that ended are forgotten when the next one initializes.
+#[derive(Default)]
+pub struct McpSessions {
+    store: OnceLock<Arc<dyn SessionStore>>,
+    // Session id by runtime address
+    ids: Mutex<HashMap<usize, SessionId>>,
+}
+
+impl McpSessions {
+    /// Uses the session store of the server the handler runs in.
+    pub fn attach(&self, store: Arc<dyn SessionStore>) {
+        let _ = self.store.set(store);
+    }
+
+    /// Records the id of the session `runtime` serves, which has just initialized, and forgets
+    /// the sessions that ended along with their logging levels.
+    pub async fn initialized(&self, runtime: &dyn McpServer) {
+        let Some(store) = self.store.get() else {
+            return;
+        };
+        let address = runtime_address(runtime);
+        let live = store.keys().await;
+        let mut session_id = None;
+        for key in &live {
+            if let Some(server) = store.get(key).await {
+                if Arc::as_ptr(&*server.lock().await) as *const () as usize == address {
+                    session_id = Some(key.clone());
+                    break;
+                }
+            }
+        }
+
+        {
+            let mut ids = self.ids.lock().unwrap();
+            ids.retain(|_, id| live.contains(id));
+            // A runtime of an ended session may have had the same address
+            match session_id {
+                Some(session_id) => ids.insert(address, session_id),
+                None => ids.remove(&address),
+            };
+        }
+        forget_log_levels(&live);
+    }
+
+    /// The id of the session `runtime` serves, once it has initialized.
+    pub fn id(&self, runtime: &dyn McpServer) -> Option<SessionId> {
+        self.ids
+            .lock()
+            .unwrap()
+            .get(&runtime_address(runtime))
+            .cloned()
+    }
+}
+
+fn runtime_address(runtime: &dyn McpServer) -> usize {
+    runtime as *const dyn McpServer as *const () as usize
+}
+
+/// Forwards log records at or above `level` to the requests of the session `session_id`.
+pub fn set_log_level(session_id: SessionId, level: LoggingLevel) {
+    if let Ok(mut levels) = MCP_LOG_LEVELS.lock() {
+        levels.insert(session_id, level);
+    }
+}
+
+/// Drops the logging levels of the sessions not in `live`.
+fn forget_log_levels(live: &[SessionId]) {
+    if let Ok(mut levels) = MCP_LOG_LEVELS.lock() {
+        levels.retain(|session_id, _| live.contains(session_id));
+    }
+}
+
+/// Forwards a guest log record to the MCP client waiting on `request_id`, if the client enabled
+/// logging at or below the record's level.
+pub fn forward_guest_log(agent_id: &str, request_id: &str, record: &GuestLogRecord) {
+    let level = mcp_logging_level(record.level);
+    send_log_notification(
+        request_id,
+        LoggingMessageNotificationParams {
+            data: serde_json::json!({
+                "agent_id": agent_id,
+                "request_id": request_id,
+                "target": record.target,
+                "message": record.message,
+                "fields": record.fields,
+            }),
+            level,
+            logger: Some(agent_id.to_string()),
+        },
+    );
+}
+
+/// Forwards output of a command running in a VM to the MCP client waiting on `request_id`, as an
+/// info log notification, if the client enabled logging at that level.
+pub fn forward_command_output(
+    agent_id: &str,
+    request_id: &str,
+    vm_id: &str,
+    output: &CommandOutput,
+) {
+    send_log_notification(
+        request_id,
+        LoggingMessageNotificationParams {
+            data: serde_json::json!({
+                "agent_id": agent_id,
+                "request_id": request_id,
+                "vm_id": vm_id,
+                "stream": output.stream,
+                "output": output.data,
+            }),
+            level: LoggingLevel::Info,
+            logger: Some(format!("{}/{}", agent_id, vm_id)),
+        },
+    );
+}
+
+fn send_log_notification(request_id: &str, params: LoggingMessageNotificationParams) {
+    // The request may have completed in the meantime
+    let Some((Some(session_id), tx)) = MCP_LOG_CHANNELS
+        .lock()
+        .ok()
+        .and_then(|channels| channels.get(request_id).cloned())
+    else {
+        return;
+    };
+
+    let enabled = MCP_LOG_LEVELS
+        .lock()
+        .map(|levels| {
+            levels
+                .get(&session_id)
+                .is_some_and(|min| severity(&params.level) >= severity(min))
+        })
+        .unwrap_or(false);
+    if enabled {
+        let _ = tx.send(params);
+    }
+}
+
+fn mcp_logging_level(level: GuestLogLevel) -> LoggingLevel {
+    match level {
+        GuestLogLevel::Error => LoggingLevel::Error,
+        GuestLogLevel::Warn => LoggingLevel::Warning,
+        GuestLogLevel::Info => LoggingLevel::Info,
+        GuestLogLevel::Debug | GuestLogLevel::Trace => LoggingLevel::Debug,
+    }
+}
+
+/// Orders MCP logging levels by severity, following syslog.
+fn severity(level: &LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
 }
 
 // Agent info structure for agents
@@ -73,7 +249,11 @@ impl McpServerManager {
 
         log::debug!("Creating HyperlightAgentHandler with agent channels.");
         // Create a handler with agent channels
-        let handler = HyperlightAgentHandler { agent_channels };
+        let sessions = Arc::new(McpSessions::default());
+        let handler = HyperlightAgentHandler {
+            agent_channels,
+            sessions: sessions.clone(),
+        };
 
         log::debug!("Preparing MCP server configuration.");
         // Create server configuration
@@ -87,6 +267,8 @@ impl McpServerManager {
             capabilities: ServerCapabilities {
                 // Indicates that server supports MCP tools
                 tools: Some(ServerCapabilitiesTools { list_changed: None }),
+                // Guest log records are relayed as notifications/message
+                logging: Some(serde_json::Map::new()),
                 ..Default::default() // Using default values for other fields
             },
             meta: None,
@@ -103,6 +285,7 @@ impl McpServerManager {
         log::debug!("Creating Hyper server instance.");
         // Start the HTTP server with Hyper
         let server = hyper_server::create_server(server_details, handler, hyper_server_options);
+        sessions.attach(server.state().session_store.clone());
 
         log::debug!("MCP server listening on http://{}", addr);
         log::debug!("MCP server about to start serving requests.");
@@ -144,3 +327,51 @@ impl McpServerManager {
 //     println!("  Message: {}", message_preview);
 //     println!(""); // Add empty line for separation
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyperlight_agents_common::structs::log_record::GuestLogLevel;
+
+    fn open_request(
+        request_id: &str,
+        session_id: &str,
+    ) -> mpsc::UnboundedReceiver<LoggingMessageNotificationParams> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        MCP_LOG_CHANNELS
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), (Some(session_id.to_string()), tx));
+        rx
+    }
+
+    fn log(request_id: &str) {
+        let record = GuestLogRecord {
+            level: GuestLogLevel::Info,
+            target: "test".to_string(),
+            message: "hello".to_string(),
+            fields: Default::default(),
+        };
+        forward_guest_log("agent", request_id, &record);
+    }
+
+    #[test]
+    fn log_level_applies_to_its_session_only() {
+        let mut first = open_request("req-level-first", "session-level-first");
+        let mut second = open_request("req-level-second", "session-level-second");
+        set_log_level("session-level-first".to_string(), LoggingLevel::Info);
+
+        log("req-level-first");
+        log("req-level-second");
+        assert!(first.try_recv().is_ok());
+        assert!(second.try_recv().is_err());
+
+        // An ended session's level is dropped
+        forget_log_levels(&["session-level-second".to_string()]);
+        log("req-level-first");
+        assert!(first.try_recv().is_err());
+
+        MCP_LOG_CHANNELS.lock().unwrap().remove("req-level-first");
+        MCP_LOG_CHANNELS.lock().unwrap().remove("req-level-second");
+    }
+}
@@ -4,13 +4,27 @@ use strum_macros::AsRefStr;
 pub enum HostMethod {
     FinalResult,
     FetchData,
+    HttpRequest,
+    ListEgressViolations,
     CreateVM,
     DestroyVM,
     ListVMs,
+    GetVMLogs,
+    CreateVolume,
+    ListVolumes,
+    DeleteVolume,
+    PutFile,
+    GetFile,
+    SyncDirectory,
     ExecuteVMCommand,
     SpawnCommand,
     ListSpawnedProcesses,
     StopSpawnedProcess,
+    KvGet,
+    KvPut,
+    KvDelete,
+    KvList,
+    Log,
 }
 
 #[derive(Debug, PartialEq, AsRefStr)]
@@ -13,17 +13,54 @@ pub mod constants;
 
 pub const API_VERSION: &str = "0.1.0";
 
-use alloc::{string::String, vec::Vec};
+/// Vsock port the vm-agent accepts requests from the host on.
+pub const VM_AGENT_COMMAND_PORT: u32 = 1234;
+
+/// Vsock port on the host the vm-agent sends the requests of its HTTP proxy to.
+pub const VM_AGENT_PROXY_PORT: u32 = 1235;
+
+/// Vsock port on the host the vm-agent forwards its log to.
+pub const VM_AGENT_LOG_PORT: u32 = 1236;
+
+/// Vsock port on the host the vm-agent connects to once its listeners are up.
+pub const VM_AGENT_READY_PORT: u32 = 1237;
+
+/// Directory in which the vm-agent uses Unix sockets instead of vsock, named like the sockets of
+/// Firecracker's hybrid vsock. Set by the host's local backend.
+pub const VM_AGENT_UNIX_DIR_ENV: &str = "VM_AGENT_UNIX_DIR";
+
+/// Address the vm-agent's HTTP proxy listens on, `0.0.0.0:8080` by default.
+pub const VM_AGENT_HTTP_PROXY_ADDR_ENV: &str = "VM_AGENT_HTTP_PROXY_ADDR";
+
+/// Volumes the vm-agent mounts before it announces itself, as comma-separated
+/// `<device>:<mount_path>[:ro]` entries, e.g. `vdb:/data,vdc:/cache:ro`. Set by the host on the
+/// kernel command line.
+pub const VM_AGENT_VOLUMES_ENV: &str = "VM_AGENT_VOLUMES";
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
 use serde::{Deserialize, Serialize};
 
+/// Seconds a foreground command may run when its `timeout_seconds` is not set.
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 30;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VmCommand {
     pub id: String,
+    /// Program executed with `args` as its arguments, or with `shell` a command line run through
+    /// `sh -c` with `args` as its positional parameters.
     pub command: String,
     pub args: Vec<String>,
     pub working_dir: Option<String>,
     pub timeout_seconds: Option<u64>,
     pub mode: VmCommandMode,
+    /// Set for the command in addition to the VM's environment.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Written to the command's standard input, which is empty otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub shell: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,7 +74,209 @@ pub enum VmCommandMode {
 pub struct VmCommandResult {
     pub id: String,
     pub exit_code: i32,
+    /// The last `COMMAND_OUTPUT_TAIL_BYTES` of the output, all of it having been streamed.
     pub stdout: String,
     pub stderr: String,
     pub error: Option<String>,
+    /// Whether earlier output was left out of `stdout`.
+    #[serde(default)]
+    pub stdout_truncated: bool,
+    #[serde(default)]
+    pub stderr_truncated: bool,
+}
+
+/// Bytes of each output stream a foreground command's result keeps. Earlier output only reaches
+/// the host as `CommandOutput` while the command runs.
+pub const COMMAND_OUTPUT_TAIL_BYTES: usize = 256 * 1024;
+
+/// Output of a foreground command, sent by the vm-agent as the command produces it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub stream: OutputStream,
+    /// Split on character boundaries, invalid UTF-8 replaced.
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Machine configuration requested for a new VM. Fields left out of the JSON are taken from the
+/// profile, or from the defaults below.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmSpec {
+    /// Named host profile the VM is based on, `alpine-base` if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub vcpu_count: u8,
+    pub mem_size_mib: u32,
+    /// Simultaneous multithreading; needs an even vCPU count (or one vCPU).
+    pub smt: bool,
+    /// Appended to the default kernel command line.
+    pub boot_args: Option<String>,
+    /// Kernel image on the host, defaults to the bundled `vmlinux`.
+    pub kernel_path: Option<String>,
+    /// Root filesystem image on the host, defaults to the bundled `rootfs.squashfs`.
+    pub rootfs_path: Option<String>,
+    /// Environment variables set for the VM agent and the commands it runs.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Seconds after creation when the VM is destroyed, the host default if not set, 0 for none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    /// Seconds without commands, running spawned processes or proxy traffic after which the VM
+    /// is destroyed, the host default if not set, 0 for none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+    /// Who the VM belongs to, the creating agent if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Free-form metadata reported by `list_vms`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Ext4 disks attached to the VM in addition to its read-only root filesystem.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VmVolume>,
+}
+
+/// A disk attached to a VM and mounted by the vm-agent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmVolume {
+    /// Absolute path the volume is mounted at in the VM.
+    pub mount_path: String,
+    /// Persistent volume on the host, created on first use and kept when the VM is destroyed. A
+    /// volume without a name is an empty scratch disk that is deleted with the VM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Size of the volume when it is created. An existing persistent volume keeps its size.
+    pub size_mib: u32,
+    pub read_only: bool,
+}
+
+impl Default for VmVolume {
+    fn default() -> Self {
+        Self {
+            mount_path: String::new(),
+            name: None,
+            size_mib: 1024,
+            read_only: false,
+        }
+    }
+}
+
+impl Default for VmSpec {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            vcpu_count: 1,
+            mem_size_mib: 512,
+            smt: false,
+            boot_args: None,
+            kernel_path: None,
+            rootfs_path: None,
+            env: BTreeMap::new(),
+            ttl_secs: None,
+            idle_timeout_secs: None,
+            owner: None,
+            labels: BTreeMap::new(),
+            volumes: Vec::new(),
+        }
+    }
+}
+
+/// Sent by the vm-agent on `VM_AGENT_READY_PORT` when it is ready to accept commands, and in
+/// reply to a ping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentReady {
+    pub version: String,
+    /// Features the agent supports, e.g. `command`, `spawn` and `http_proxy`.
+    pub capabilities: Vec<String>,
+}
+
+/// The vm-agent's reply to a status request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStatus {
+    /// Spawned processes that have not exited yet.
+    pub running_processes: usize,
+}
+
+/// Size, permissions and SHA-256 checksum of a file in a VM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub size: u64,
+    /// Permission bits, e.g. `0o644`.
+    pub mode: u32,
+    /// Lower-case hex digest of the contents.
+    pub sha256: String,
+}
+
+/// Asks the vm-agent for a chunk of a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReadRequest {
+    pub path: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A chunk of a file the host writes to the VM. Chunks go to a partial file next to `path`, which
+/// `FileCommitRequest` moves into place.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWriteRequest {
+    pub path: String,
+    /// Names the partial file, so concurrent uploads to the same path do not mix.
+    pub transfer_id: String,
+    pub offset: u64,
+    /// Base64 encoded contents.
+    pub data: String,
+}
+
+/// Finishes an upload: the vm-agent checks the partial file against `info`, sets its mode and
+/// renames it to `path`. Without `info` the partial file is removed instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCommitRequest {
+    pub path: String,
+    pub transfer_id: String,
+    pub info: Option<FileInfo>,
+}
+
+/// The vm-agent's reply to a file request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileResponse {
+    /// The file, in reply to a stat or a commit.
+    pub info: Option<FileInfo>,
+    /// Base64 encoded chunk, in reply to a read.
+    pub data: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Size and modification time of a file in a synced directory, which decide whether an
+/// incremental sync sends it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedFile {
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+/// Unpacks a tar archive the host uploaded, gzip compressed or not, into `target` and removes
+/// the archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncUnpackRequest {
+    pub archive: String,
+    pub target: String,
+}
+
+/// The vm-agent's reply to a sync request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncResponse {
+    /// Regular files under the target by path relative to it, in reply to a prepare.
+    pub files: Option<BTreeMap<String, SyncedFile>>,
+    /// Number of regular files unpacked, in reply to an unpack.
+    pub unpacked: Option<u64>,
+    pub error: Option<String>,
 }
@@ -0,0 +1,29 @@
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+/// Category of a failure reported by the host to a guest callback.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostErrorKind {
+    /// The request was rejected by the agent's network policy.
+    EgressDenied,
+    /// The request was allowed but failed.
+    RequestFailed,
+    /// The host replays recorded traffic in strict mode and has no recording for the request.
+    UnmatchedReplay,
+    /// A secret referenced by the request is not configured or has no value.
+    SecretUnavailable,
+}
+
+/// Error delivered to a guest callback in place of a regular response.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct HostError {
+    pub kind: HostErrorKind,
+    pub message: String,
+}
+
+impl HostError {
+    /// Returns the error if a callback response is a serialized `HostError`.
+    pub fn parse(response: &str) -> Option<HostError> {
+        serde_json::from_str(response).ok()
+    }
+}
@@ -0,0 +1,24 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+/// HTTP request a guest asks the host to perform. The response body, or a serialized
+/// `HostError`, is delivered to the guest function named by `callback`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct HttpRequestMessage {
+    pub callback: String,
+    pub method: String,
+    pub url: String,
+    /// Header values may reference host secrets with `secret_ref`.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// Returns a placeholder the host replaces with the named secret when sending the request,
+/// e.g. `format!("Bearer {}", secret_ref("GITHUB_TOKEN"))`.
+pub fn secret_ref(name: &str) -> String {
+    format!("{{{{secret:{}}}}}", name)
+}
@@ -0,0 +1,25 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+/// Severity of a guest log record, mirroring the `log` crate levels.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestLogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Log record sent by a guest with the `Log` host method.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct GuestLogRecord {
+    pub level: GuestLogLevel,
+    /// Module or component that produced the record.
+    pub target: String,
+    pub message: String,
+    /// Structured key-value pairs attached to the record.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
@@ -1,2 +1,5 @@
 pub mod mcp_tool;
-pub mod agent_message;
\ No newline at end of file
+pub mod agent_message;
+pub mod host_error;
+pub mod http_request;
+pub mod log_record;
\ No newline at end of file
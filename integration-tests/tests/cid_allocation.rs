@@ -0,0 +1,135 @@
+//! Runs a host with two CIDs to hand out on the local backend: creating a third VM fails until one
+//! is destroyed, a restarted host keeps the CIDs of the VMs it reattaches, and a CID another VM
+//! holds through vhost-vsock is skipped.
+
+mod common;
+
+use common::{connect, run, start_host, vm_builder, HostGuard, TestClientHandler};
+use rust_mcp_sdk::mcp_client::ClientRuntime;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+
+const MIN_CID: u32 = 100;
+const MAX_CID: u32 = 101;
+
+// VHOST_VSOCK_SET_GUEST_CID from linux/vhost.h
+nix::ioctl_write_ptr!(vhost_vsock_set_guest_cid, 0xAF, 0x60, u64);
+
+/// Creates a VM and returns its CID, or the error text if creation failed.
+async fn create_vm(client: &Arc<ClientRuntime>, vm_id: &str) -> Result<u32, String> {
+    let res = vm_builder(client, json!({ "action": "create_vm", "vm_id": vm_id })).await;
+    res.split("created with CID ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|cid| cid.parse().ok())
+        .ok_or(res)
+}
+
+async fn destroy_vm(client: &Arc<ClientRuntime>, vm_id: &str) {
+    let res = vm_builder(client, json!({ "action": "destroy_vm", "vm_id": vm_id })).await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+}
+
+/// CIDs `list_vms` reports, by VM id.
+async fn vm_cids(client: &Arc<ClientRuntime>) -> Vec<(String, u32)> {
+    let res = vm_builder(client, json!({ "action": "list_vms" })).await;
+    let vms: Vec<Value> = serde_json::from_str(&res).expect("list_vms did not return JSON");
+    let mut cids: Vec<(String, u32)> = vms
+        .iter()
+        .map(|vm| {
+            (
+                vm["vm_id"].as_str().unwrap().to_string(),
+                vm["cid"].as_u64().unwrap() as u32,
+            )
+        })
+        .collect();
+    cids.sort();
+    cids
+}
+
+/// Claims `cid` on a vhost-vsock device the way QEMU does, for as long as the device is open.
+/// `None` if this machine has no vhost-vsock.
+fn hold_vhost_cid(cid: u32) -> Option<File> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/vhost-vsock")
+        .ok()?;
+    let guest_cid = cid as u64;
+    unsafe { vhost_vsock_set_guest_cid(device.as_raw_fd(), &guest_cid) }
+        .expect("Failed to claim a CID on /dev/vhost-vsock");
+    Some(device)
+}
+
+fn assert_exhausted(result: Result<u32, String>) {
+    match result {
+        Err(res) => assert!(
+            res.contains("No free vsock CID"),
+            "Expected running out of CIDs, got {:?}",
+            res
+        ),
+        Ok(cid) => panic!("Expected running out of CIDs, got CID {}", cid),
+    }
+}
+
+#[tokio::test]
+async fn cids_are_reused_and_never_shared() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({
+            "state_dir": state_dir.path(),
+            "vm": { "backend": "local", "min_cid": MIN_CID, "max_cid": MAX_CID }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let mut host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("cid-allocation-test", TestClientHandler).await;
+    assert_eq!(create_vm(&client, "cid_a").await, Ok(MIN_CID));
+    assert_eq!(create_vm(&client, "cid_b").await, Ok(MAX_CID));
+    assert_exhausted(create_vm(&client, "cid_c").await);
+
+    // The CID of a destroyed VM goes back to the pool
+    destroy_vm(&client, "cid_a").await;
+    assert_eq!(create_vm(&client, "cid_c").await, Ok(MIN_CID));
+
+    // A restarted host keeps the CIDs of the VMs it reattaches to, and hands out no others
+    host.kill();
+    let _host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to restart host"),
+    );
+    let client = connect("cid-allocation-test", TestClientHandler).await;
+    assert_eq!(
+        vm_cids(&client).await,
+        vec![
+            ("cid_b".to_string(), MAX_CID),
+            ("cid_c".to_string(), MIN_CID)
+        ]
+    );
+    assert_exhausted(create_vm(&client, "cid_d").await);
+
+    // A CID another VM on the host holds is skipped
+    destroy_vm(&client, "cid_c").await;
+    let Some(held) = hold_vhost_cid(MIN_CID) else {
+        eprintln!("No /dev/vhost-vsock, skipping the conflict with another VM");
+        return;
+    };
+    assert_exhausted(create_vm(&client, "cid_d").await);
+    drop(held);
+    assert_eq!(create_vm(&client, "cid_d").await, Ok(MIN_CID));
+}
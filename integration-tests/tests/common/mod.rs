@@ -0,0 +1,144 @@
+//! Helpers shared by the integration tests: starting the host and calling its VmBuilder agent
+//! over MCP. Each test file uses a different subset of them.
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use rust_mcp_sdk::mcp_client::{client_runtime, ClientHandler, ClientRuntime};
+use rust_mcp_sdk::schema::{
+    CallToolRequestParams, ClientCapabilities, ContentBlock, Implementation,
+    InitializeRequestParams, LATEST_PROTOCOL_VERSION,
+};
+use rust_mcp_sdk::{ClientSseTransport, ClientSseTransportOptions, McpClient};
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Client handler for tests that do not look at notifications.
+pub struct TestClientHandler;
+
+#[async_trait]
+impl ClientHandler for TestClientHandler {}
+
+/// Runs a program from the workspace root.
+pub fn run(program: &str, args: &[&str]) -> io::Result<()> {
+    let status = Command::new(program)
+        .current_dir("../")
+        .args(args)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{} {:?} failed", program, args)));
+    }
+    Ok(())
+}
+
+/// The debug build of the host, run with `config_path` as its configuration file.
+fn host_command(config_path: &Path) -> Command {
+    let mut command = Command::new("./target/debug/hyperlight-agents-host");
+    command
+        .current_dir("../")
+        .env("RUST_LOG", "debug,hyperlight_host=info")
+        .env("HYPERLIGHT_AGENTS_CONFIG", config_path);
+    command
+}
+
+/// How long the host gets to start listening.
+const HOST_STARTUP: Duration = Duration::from_secs(5);
+
+/// Starts the host in its own process group.
+fn spawn_host(mut command: Command) -> io::Result<Child> {
+    unsafe {
+        command.pre_exec(|| {
+            nix::unistd::setsid()?;
+            Ok(())
+        });
+    }
+    command.spawn()
+}
+
+/// Starts the host in its own process group with the given configuration file.
+pub async fn start_host(config_path: &Path) -> io::Result<Child> {
+    let child = spawn_host(host_command(config_path))?;
+    tokio::time::sleep(HOST_STARTUP).await;
+    Ok(child)
+}
+
+/// Starts the host like `start_host`, collecting what it logs to stderr.
+pub async fn start_host_with_output(
+    config_path: &Path,
+    output: Arc<Mutex<String>>,
+) -> io::Result<Child> {
+    let mut command = host_command(config_path);
+    command.stderr(Stdio::piped());
+    let mut child = spawn_host(command)?;
+    let stderr = child.stderr.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            eprintln!("{}", line);
+            let mut output = output.lock().unwrap();
+            output.push_str(&line);
+            output.push('\n');
+        }
+    });
+    tokio::time::sleep(HOST_STARTUP).await;
+    Ok(child)
+}
+
+/// Stops the host process group when the test ends, also when it fails.
+pub struct HostGuard(pub Child);
+
+impl HostGuard {
+    /// Kills the host without giving it a chance to stop its VMs.
+    pub fn kill(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+impl Drop for HostGuard {
+    fn drop(&mut self) {
+        let pgid = nix::unistd::Pid::from_raw(-(self.0.id() as i32));
+        let _ = nix::sys::signal::kill(pgid, nix::sys::signal::Signal::SIGINT);
+        let _ = self.0.wait();
+    }
+}
+
+/// Connects to the host's MCP server as `name`.
+pub async fn connect(name: &str, handler: impl ClientHandler) -> Arc<ClientRuntime> {
+    let client_details = InitializeRequestParams {
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            title: Some(name.into()),
+            name: name.into(),
+            version: "0.1.0".into(),
+        },
+        protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+    };
+    let transport = ClientSseTransport::new(
+        "http://127.0.0.1:3000/sse",
+        ClientSseTransportOptions::default(),
+    )
+    .unwrap();
+    let client = client_runtime::create_client(client_details, transport, handler);
+    client.clone().start().await.expect("Failed to connect");
+    client
+}
+
+/// Calls the VmBuilder agent and returns the text it answered with.
+pub async fn vm_builder(client: &Arc<ClientRuntime>, params: Value) -> String {
+    let request = CallToolRequestParams {
+        name: "VmBuilder".to_string(),
+        arguments: Some(params.as_object().unwrap().clone()),
+    };
+    let result = client
+        .call_tool(request)
+        .await
+        .expect("Failed to call tool");
+    match result.content.first() {
+        Some(ContentBlock::TextContent(content)) => content.text.clone(),
+        _ => panic!("No content found"),
+    }
+}
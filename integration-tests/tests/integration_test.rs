@@ -153,7 +153,7 @@ fn emergency_cleanup() -> io::Result<()> {
 
 async fn execute_command(client: &Arc<ClientRuntime>, command: &str, action: &str) -> String {
     println!("Sending command: {}", command);
-    let params = json!({"action": action, "vm_id": "integration_test_vm", "command": command})
+    let params = json!({"action": action, "vm_id": "integration_test_vm", "command": command, "shell": true})
         .as_object()
         .unwrap()
         .clone();
@@ -230,17 +230,23 @@ async fn integration_test() {
     };
     let result = client.call_tool(request).await;
     match result {
-        Ok(_) => {
-            // Process the result
+        Ok(res) => {
+            // create_vm only returns once the vm-agent has announced itself
+            let text = match res.content.first() {
+                Some(ContentBlock::TextContent(content)) => content.text.clone(),
+                _ => panic!("No content found"),
+            };
+            assert!(
+                text.contains("created with CID"),
+                "VM creation failed: {}",
+                text
+            );
         }
         Err(e) => {
             panic!("Failed to call tool: {}", e);
         }
     }
 
-    // let vm agent startup fully
-    tokio::time::sleep(Duration::from_secs(5)).await;
-
     // execute vm command
     let command = "free -m";
     let res = execute_command(&client, command, "execute_vm_command").await;
@@ -0,0 +1,51 @@
+//! Runs the MCP → agent → VM command path against the local backend, which runs the vm-agent as a
+//! plain process, so it also passes on machines without KVM for Firecracker.
+
+mod common;
+
+use common::{connect, run, start_host, vm_builder, HostGuard, TestClientHandler};
+use serde_json::json;
+
+const VM_ID: &str = "local_backend_vm";
+
+#[tokio::test]
+async fn local_backend_runs_commands() {
+    // The VmBuilder agent, and the static vm-agent the local backend runs
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({ "vm": { "backend": "local" } }).to_string(),
+    )
+    .unwrap();
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+    let _host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("local-backend-test", TestClientHandler).await;
+
+    let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": VM_ID })).await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "execute_vm_command", "vm_id": VM_ID, "command": "echo local backend" }),
+    )
+    .await;
+    assert!(
+        res.contains("local backend"),
+        "Expected the echoed text, got {:?}",
+        res
+    );
+
+    let res = vm_builder(&client, json!({ "action": "destroy_vm", "vm_id": VM_ID })).await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+}
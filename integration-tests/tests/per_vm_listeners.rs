@@ -0,0 +1,102 @@
+//! Creates two VMs with the local backend and checks that each gets its own HTTP proxy and log
+//! sockets, that the host attributes the log of each vm-agent to its VM, and that destroying a VM
+//! only removes its own sockets.
+
+mod common;
+
+use common::{connect, run, start_host_with_output, vm_builder, HostGuard, TestClientHandler};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const VM_IDS: [&str; 2] = ["listeners_vm_a", "listeners_vm_b"];
+
+/// Sockets Firecracker's hybrid vsock forwards the vm-agent's proxy and log connections to.
+const SOCKETS: [&str; 2] = ["vsock.sock_1235", "vsock.sock_1236"];
+
+/// Directory of the VM `vm_id`, from its record in the VM registry.
+fn vm_dir(state_dir: &Path, vm_id: &str) -> PathBuf {
+    std::fs::read_dir(state_dir.join("vms"))
+        .unwrap()
+        .flatten()
+        .filter_map(|entry| std::fs::read(entry.path()).ok())
+        .filter_map(|json| serde_json::from_slice::<Value>(&json).ok())
+        .find(|record| record["vm_id"] == vm_id)
+        .and_then(|record| record["vm_dir"].as_str().map(PathBuf::from))
+        .unwrap_or_else(|| panic!("VM {} is not in the registry", vm_id))
+}
+
+#[tokio::test]
+async fn every_vm_gets_its_own_listeners() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({ "state_dir": state_dir.path(), "vm": { "backend": "local" } }).to_string(),
+    )
+    .unwrap();
+
+    let output = Arc::new(Mutex::new(String::new()));
+    let _host = HostGuard(
+        start_host_with_output(config.path(), output.clone())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("per-vm-listeners-test", TestClientHandler).await;
+    for vm_id in VM_IDS {
+        let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": vm_id })).await;
+        assert!(
+            res.contains("created with CID"),
+            "VM creation failed: {}",
+            res
+        );
+    }
+
+    let vm_dirs = VM_IDS.map(|vm_id| vm_dir(state_dir.path(), vm_id));
+    for (vm_id, vm_dir) in VM_IDS.iter().zip(&vm_dirs) {
+        for socket in SOCKETS {
+            assert!(
+                vm_dir.join(socket).exists(),
+                "VM {} has no {}",
+                vm_id,
+                socket
+            );
+        }
+    }
+
+    // The agents flush the log they buffered once they reach their listener
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let output = output.lock().unwrap().clone();
+    for vm_id in VM_IDS {
+        assert!(
+            output.contains(&format!("[{}] INFO - === VM AGENT STARTING ===", vm_id)),
+            "No log of VM {} in the host output",
+            vm_id
+        );
+    }
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "destroy_vm", "vm_id": VM_IDS[0] }),
+    )
+    .await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+    for socket in SOCKETS {
+        assert!(
+            !vm_dirs[0].join(socket).exists(),
+            "Destroyed VM kept its {}",
+            socket
+        );
+        assert!(
+            vm_dirs[1].join(socket).exists(),
+            "Destroying another VM removed the {} of {}",
+            socket,
+            VM_IDS[1]
+        );
+    }
+}
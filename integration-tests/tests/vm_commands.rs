@@ -0,0 +1,212 @@
+//! Runs foreground commands with the local backend and checks that each `execute_vm_command`
+//! field reaches the command: arguments, working directory, timeout, environment, stdin and shell
+//! mode. Also checks that output is streamed to the client while a command runs, and that long
+//! output is cut to its end in the result.
+
+mod common;
+
+use async_trait::async_trait;
+use common::{connect, run, start_host_with_output, vm_builder, HostGuard};
+use rust_mcp_sdk::mcp_client::{ClientHandler, ClientRuntime};
+use rust_mcp_sdk::schema::{LoggingLevel, LoggingMessageNotification, RpcError};
+use rust_mcp_sdk::McpClient;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const VM_ID: &str = "commands_vm";
+
+/// Collects the log notifications the host sends, with when they arrived.
+#[derive(Clone, Default)]
+pub struct CommandsClientHandler {
+    notifications: Arc<Mutex<Vec<(Instant, Value)>>>,
+}
+
+#[async_trait]
+impl ClientHandler for CommandsClientHandler {
+    async fn handle_logging_message_notification(
+        &self,
+        notification: LoggingMessageNotification,
+        _runtime: &dyn McpClient,
+    ) -> Result<(), RpcError> {
+        self.notifications
+            .lock()
+            .unwrap()
+            .push((Instant::now(), notification.params.data));
+        Ok(())
+    }
+}
+
+async fn execute(client: &Arc<ClientRuntime>, request: Value) -> String {
+    let mut params = json!({ "action": "execute_vm_command", "vm_id": VM_ID });
+    params
+        .as_object_mut()
+        .unwrap()
+        .extend(request.as_object().unwrap().clone());
+    vm_builder(client, params).await
+}
+
+#[tokio::test]
+async fn commands_honour_their_options() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({ "state_dir": state_dir.path(), "vm": { "backend": "local" } }).to_string(),
+    )
+    .unwrap();
+
+    let output = Arc::new(Mutex::new(String::new()));
+    let _host = HostGuard(
+        start_host_with_output(config.path(), output.clone())
+            .await
+            .expect("Failed to start host"),
+    );
+    let handler = CommandsClientHandler::default();
+    let client = connect("vm-commands-test", handler.clone()).await;
+    client
+        .set_logging_level(LoggingLevel::Info)
+        .await
+        .expect("Failed to set logging level");
+    let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": VM_ID })).await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+
+    let res = execute(
+        &client,
+        json!({ "command": "printf", "args": ["%s|", "a b", "$HOME", "*", "'; echo injected"] }),
+    )
+    .await;
+    assert_eq!(
+        res, "a b|$HOME|*|'; echo injected|",
+        "Arguments were not passed as is"
+    );
+    let res = execute(
+        &client,
+        json!({ "command": "printf '%s|'", "args": ["a b", "$HOME", "*"], "shell": true }),
+    )
+    .await;
+    assert_eq!(
+        res, "a b|$HOME|*|",
+        "Arguments were not passed as is in shell mode"
+    );
+    // Without shell mode a command line is not split into a program and its arguments
+    let res = execute(&client, json!({ "command": "echo hello" })).await;
+    assert!(
+        res.contains("Failed to execute echo hello"),
+        "Command line was run without shell mode: {}",
+        res
+    );
+
+    // The local backend's vm-agent runs on the host, so directories in the VM are host paths
+    let working_dir = tempfile::tempdir().unwrap();
+    let working_dir = working_dir.path().canonicalize().unwrap();
+    let res = execute(
+        &client,
+        json!({ "command": "pwd", "working_dir": working_dir }),
+    )
+    .await;
+    assert_eq!(res.trim(), working_dir.to_str().unwrap());
+
+    let res = execute(
+        &client,
+        json!({ "command": "echo \"$GREETING, $NAME\"", "env": { "GREETING": "hello", "NAME": "vm" }, "shell": true }),
+    )
+    .await;
+    assert_eq!(res.trim(), "hello, vm");
+
+    let res = execute(
+        &client,
+        json!({ "command": "tr", "args": ["a-z", "A-Z"], "stdin": "hello\n" }),
+    )
+    .await;
+    assert_eq!(res.trim(), "HELLO");
+    let res = execute(&client, json!({ "command": "wc", "args": ["-c"] })).await;
+    assert_eq!(
+        res.trim(),
+        "0",
+        "Commands without stdin did not get empty input"
+    );
+
+    let started = Instant::now();
+    let res = execute(
+        &client,
+        json!({ "command": "sleep", "args": ["60"], "timeout_seconds": 2 }),
+    )
+    .await;
+    assert!(
+        res.contains("timed out after 2 seconds"),
+        "Command was not timed out: {}",
+        res
+    );
+    assert!(
+        started.elapsed() < Duration::from_secs(20),
+        "Timed out command took {:?}",
+        started.elapsed()
+    );
+
+    // Longer than the agent's former fixed limit of 15 seconds
+    let res = execute(
+        &client,
+        json!({ "command": "sleep 17 && echo done", "timeout_seconds": 25, "shell": true }),
+    )
+    .await;
+    assert_eq!(res.trim(), "done", "Long command did not complete: {}", res);
+
+    let res = execute(
+        &client,
+        json!({ "command": "for i in 1 2 3; do echo line $i; sleep 1; done", "shell": true }),
+    )
+    .await;
+    let finished = Instant::now();
+    assert_eq!(res, "line 1\nline 2\nline 3\n");
+    let streamed: Vec<(Instant, Value)> = handler
+        .notifications
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, data)| data["vm_id"] == VM_ID && data["stream"] == "stdout")
+        .cloned()
+        .collect();
+    let text: String = streamed
+        .iter()
+        .map(|(_, data)| data["output"].as_str().unwrap())
+        .collect();
+    assert!(
+        text.contains("line 1\nline 2\nline 3\n"),
+        "Output was not streamed: {:?}",
+        streamed
+    );
+    let (first_at, _) = streamed
+        .iter()
+        .find(|(_, data)| data["output"].as_str().unwrap().contains("line 1"))
+        .unwrap();
+    assert!(
+        finished.duration_since(*first_at) >= Duration::from_secs(1),
+        "Output arrived only when the command finished"
+    );
+
+    // More than the 256 KiB of each stream the result keeps
+    let res = execute(
+        &client,
+        json!({ "command": "head -c 1000000 /dev/zero | tr '\\0' a; echo end", "shell": true }),
+    )
+    .await;
+    assert!(
+        res.starts_with("[earlier output truncated"),
+        "Long output was not truncated: {:.100}",
+        res
+    );
+    assert!(res.trim_end().ends_with("aend"));
+    assert!(res.len() < 300_000, "Result kept {} bytes", res.len());
+
+    let res = vm_builder(&client, json!({ "action": "destroy_vm", "vm_id": VM_ID })).await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+}
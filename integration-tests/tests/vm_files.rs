@@ -0,0 +1,142 @@
+//! Copies a binary file spanning several chunks into a VM with the local backend and back, checks
+//! its contents, mode and checksum on both ends, and that files over `max_file_size_mib` are
+//! rejected.
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use common::{connect, run, start_host_with_output, vm_builder, HostGuard, TestClientHandler};
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::sync::{Arc, Mutex};
+
+const VM_ID: &str = "files_vm";
+
+#[tokio::test]
+async fn files_round_trip_through_the_vm() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({
+            "state_dir": state_dir.path(),
+            "vm": { "backend": "local", "max_file_size_mib": 1 }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = Arc::new(Mutex::new(String::new()));
+    let _host = HostGuard(
+        start_host_with_output(config.path(), output.clone())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("vm-files-test", TestClientHandler).await;
+    let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": VM_ID })).await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+
+    // The local backend's vm-agent runs on the host, so paths in the VM are host paths
+    let files_dir = tempfile::tempdir().unwrap();
+    let path = files_dir.path().join("blob.bin");
+    let path_str = path.to_str().unwrap();
+    let contents: Vec<u8> = (0..600 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let res = vm_builder(
+        &client,
+        json!({
+            "action": "put_file",
+            "vm_id": VM_ID,
+            "path": path_str,
+            "content": BASE64.encode(&contents),
+            "encoding": "base64",
+            "mode": "750"
+        }),
+    )
+    .await;
+    assert!(res.starts_with("Wrote"), "File upload failed: {}", res);
+    assert_eq!(std::fs::read(&path).unwrap(), contents);
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+        0o750
+    );
+    let leftovers: Vec<_> = std::fs::read_dir(files_dir.path())
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name())
+        .filter(|name| name != "blob.bin")
+        .collect();
+    assert!(leftovers.is_empty(), "Upload left {:?} behind", leftovers);
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "get_file", "vm_id": VM_ID, "path": path_str }),
+    )
+    .await;
+    let file: Value =
+        serde_json::from_str(&res).unwrap_or_else(|_| panic!("File download failed: {}", res));
+    assert_eq!(file["size"], contents.len());
+    assert_eq!(file["mode"], "750");
+    assert_eq!(file["encoding"], "base64");
+    assert_eq!(
+        BASE64.decode(file["content"].as_str().unwrap()).unwrap(),
+        contents
+    );
+
+    let res = vm_builder(
+        &client,
+        json!({
+            "action": "put_file",
+            "vm_id": VM_ID,
+            "path": files_dir.path().join("notes.txt").to_str().unwrap(),
+            "content": "hello\n"
+        }),
+    )
+    .await;
+    assert!(res.starts_with("Wrote"), "File upload failed: {}", res);
+    let res = vm_builder(
+        &client,
+        json!({
+            "action": "get_file",
+            "vm_id": VM_ID,
+            "path": files_dir.path().join("notes.txt").to_str().unwrap()
+        }),
+    )
+    .await;
+    let file: Value =
+        serde_json::from_str(&res).unwrap_or_else(|_| panic!("File download failed: {}", res));
+    assert_eq!(file["mode"], "644");
+    assert_eq!(file["encoding"], "utf8");
+    assert_eq!(file["content"], "hello\n");
+
+    let oversized = vec![0u8; 2 * 1024 * 1024];
+    let res = vm_builder(
+        &client,
+        json!({
+            "action": "put_file",
+            "vm_id": VM_ID,
+            "path": files_dir.path().join("oversized.bin").to_str().unwrap(),
+            "content": BASE64.encode(&oversized),
+            "encoding": "base64"
+        }),
+    )
+    .await;
+    assert!(
+        res.contains("exceeds the limit"),
+        "Oversized upload was not rejected: {}",
+        res
+    );
+    assert!(!files_dir.path().join("oversized.bin").exists());
+
+    let res = vm_builder(&client, json!({ "action": "destroy_vm", "vm_id": VM_ID })).await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+}
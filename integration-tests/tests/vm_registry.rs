@@ -0,0 +1,122 @@
+//! Kills the host while it runs VMs with the local backend and checks that the next run reattaches
+//! to the VMs that are still alive and removes the others from the registry.
+
+mod common;
+
+use common::{connect, run, start_host, vm_builder, HostGuard, TestClientHandler};
+use rust_mcp_sdk::mcp_client::ClientRuntime;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+const KEPT_VM_ID: &str = "registry_kept_vm";
+const LOST_VM_ID: &str = "registry_lost_vm";
+
+/// Pids of the VMs `list_vms` reports, by VM id.
+async fn vm_pids(client: &Arc<ClientRuntime>) -> Vec<(String, u32)> {
+    let res = vm_builder(client, json!({ "action": "list_vms" })).await;
+    let vms: Vec<Value> = serde_json::from_str(&res).expect("list_vms did not return JSON");
+    vms.iter()
+        .map(|vm| {
+            (
+                vm["vm_id"].as_str().unwrap().to_string(),
+                vm["pid"].as_u64().unwrap() as u32,
+            )
+        })
+        .collect()
+}
+
+/// Whether `pid` runs and is not a zombie waiting for a reaper.
+fn is_running(pid: u32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(|stat| {
+        stat.rsplit_once(')')
+            .is_some_and(|(_, fields)| !fields.trim_start().starts_with('Z'))
+    })
+}
+
+/// Number of VM records, leaving out the CID allocator's file next to them.
+fn registry_len(state_dir: &Path) -> usize {
+    std::fs::read_dir(state_dir.join("vms"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name() != "cids.json")
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn restarted_host_reattaches_to_running_vms() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({ "state_dir": state_dir.path(), "vm": { "backend": "local" } }).to_string(),
+    )
+    .unwrap();
+
+    let mut host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("vm-registry-test", TestClientHandler).await;
+    for vm_id in [KEPT_VM_ID, LOST_VM_ID] {
+        let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": vm_id })).await;
+        assert!(
+            res.contains("created with CID"),
+            "VM creation failed: {}",
+            res
+        );
+    }
+    let pids = vm_pids(&client).await;
+    assert_eq!(registry_len(state_dir.path()), 2);
+
+    // The host dies without stopping its VMs, and one of them exits while it is gone
+    host.kill();
+    let pid_of = |vm_id: &str| pids.iter().find(|(id, _)| id == vm_id).unwrap().1;
+    assert!(is_running(pid_of(KEPT_VM_ID)), "VM died with the host");
+    Command::new("kill")
+        .args(["-KILL", &pid_of(LOST_VM_ID).to_string()])
+        .status()
+        .unwrap();
+
+    let host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to restart host"),
+    );
+    let client = connect("vm-registry-test", TestClientHandler).await;
+    let reattached = vm_pids(&client).await;
+    assert_eq!(
+        reattached,
+        vec![(KEPT_VM_ID.to_string(), pid_of(KEPT_VM_ID))],
+        "Expected only the VM that is still running"
+    );
+    assert_eq!(registry_len(state_dir.path()), 1);
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "execute_vm_command", "vm_id": KEPT_VM_ID, "command": "echo reattached" }),
+    )
+    .await;
+    assert!(
+        res.contains("reattached"),
+        "Expected the echoed text, got {:?}",
+        res
+    );
+
+    // A clean shutdown stops the reattached VM and empties the registry
+    drop(host);
+    assert!(
+        !is_running(pid_of(KEPT_VM_ID)),
+        "Reattached VM outlived the host"
+    );
+    assert_eq!(registry_len(state_dir.path()), 0);
+}
@@ -0,0 +1,157 @@
+//! Syncs a project directory into a VM with the local backend, checks that ignored files stay
+//! behind, that an incremental sync only sends what changed and keeps files created in the VM, and
+//! that tarballs are unpacked.
+
+mod common;
+
+use common::{connect, run, start_host_with_output, vm_builder, HostGuard, TestClientHandler};
+use rust_mcp_sdk::mcp_client::ClientRuntime;
+use serde_json::json;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+const VM_ID: &str = "sync_vm";
+
+async fn sync(
+    client: &Arc<ClientRuntime>,
+    source: &Path,
+    target: &Path,
+    incremental: bool,
+) -> String {
+    vm_builder(
+        client,
+        json!({
+            "action": "sync_directory",
+            "vm_id": VM_ID,
+            "source": source,
+            "target": target,
+            "incremental": incremental
+        }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn directories_sync_into_the_vm() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let sync_root = tempfile::tempdir().unwrap();
+    let source = sync_root.path().join("app");
+    for (path, contents) in [
+        ("src/main.rs", "fn main() {}\n"),
+        ("README.md", "# App\n"),
+        (".gitignore", "build/\n*.log\n"),
+        ("build/out.bin", "output"),
+        ("debug.log", "log"),
+        (".git/config", "[core]\n"),
+    ] {
+        let path = source.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({
+            "state_dir": state_dir.path(),
+            "vm": { "backend": "local", "sync_dirs": [sync_root.path()] }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = Arc::new(Mutex::new(String::new()));
+    let _host = HostGuard(
+        start_host_with_output(config.path(), output.clone())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("vm-sync-test", TestClientHandler).await;
+    let res = vm_builder(&client, json!({ "action": "create_vm", "vm_id": VM_ID })).await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+
+    // The local backend's vm-agent runs on the host, so paths in the VM are host paths
+    let targets = tempfile::tempdir().unwrap();
+    let target = targets.path().join("app");
+    let res = sync(&client, &source, &target, false).await;
+    assert!(res.starts_with("Synced 3 files"), "Sync failed: {}", res);
+    for path in ["src/main.rs", "README.md", ".gitignore"] {
+        assert_eq!(
+            std::fs::read(target.join(path)).unwrap(),
+            std::fs::read(source.join(path)).unwrap(),
+            "{} was not synced",
+            path
+        );
+    }
+    for path in ["build", "debug.log", ".git"] {
+        assert!(!target.join(path).exists(), "Ignored {} was synced", path);
+    }
+
+    std::fs::write(source.join("README.md"), "# App\n\nBuilt in a VM.\n").unwrap();
+    std::fs::write(target.join("generated.txt"), "made in the VM").unwrap();
+    let res = sync(&client, &source, &target, true).await;
+    assert!(
+        res.starts_with("Synced 1 files") && res.ends_with(", 2 unchanged"),
+        "Incremental sync did not send only the change: {}",
+        res
+    );
+    assert_eq!(
+        std::fs::read_to_string(target.join("README.md")).unwrap(),
+        "# App\n\nBuilt in a VM.\n"
+    );
+    assert!(
+        target.join("generated.txt").exists(),
+        "Sync removed a file created in the VM"
+    );
+
+    let res = sync(&client, &source, &target, true).await;
+    assert!(
+        res.starts_with("Synced 0 files") && res.ends_with(", 3 unchanged"),
+        "Sync without changes sent files: {}",
+        res
+    );
+
+    let tarball = sync_root.path().join("app.tar.gz");
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(&source)
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success(), "Failed to create tarball");
+    let unpacked = targets.path().join("unpacked");
+    let res = sync(&client, &tarball, &unpacked, false).await;
+    assert!(res.starts_with("Synced"), "Tarball sync failed: {}", res);
+    assert!(
+        unpacked.join("src/main.rs").exists(),
+        "Tarball was not unpacked"
+    );
+    assert!(
+        std::fs::read_dir(&unpacked)
+            .unwrap()
+            .flatten()
+            .all(|entry| !entry.file_name().to_string_lossy().starts_with(".vm-sync-")),
+        "Sync left its archive behind"
+    );
+
+    let res = sync(&client, Path::new("/etc"), &target, false).await;
+    assert!(
+        res.contains("outside the allowed sync directories"),
+        "Source outside sync_dirs was synced: {}",
+        res
+    );
+
+    let res = vm_builder(&client, json!({ "action": "destroy_vm", "vm_id": VM_ID })).await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+}
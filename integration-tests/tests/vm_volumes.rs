@@ -0,0 +1,158 @@
+//! Attaches a named and a scratch volume to a Firecracker VM, and checks that what the VM wrote to
+//! the named volume is there for the next VM while the scratch volume starts empty, and that a
+//! named volume is only attached to one VM at a time.
+
+mod common;
+
+use common::{connect, run, start_host, vm_builder, HostGuard, TestClientHandler};
+use rust_mcp_sdk::mcp_client::ClientRuntime;
+use serde_json::json;
+use std::sync::Arc;
+
+const VOLUME: &str = "volumes_test_data";
+
+/// Creates a VM with the named volume at `/data` and a scratch volume at `/scratch`.
+async fn create_vm(client: &Arc<ClientRuntime>, vm_id: &str) -> String {
+    let volumes = json!([
+        { "mount_path": "/data", "name": VOLUME },
+        { "mount_path": "/scratch", "size_mib": 32 }
+    ]);
+    vm_builder(
+        client,
+        json!({ "action": "create_vm", "vm_id": vm_id, "spec": { "volumes": volumes } }),
+    )
+    .await
+}
+
+async fn execute(client: &Arc<ClientRuntime>, vm_id: &str, command: &str) -> String {
+    vm_builder(
+        client,
+        json!({ "action": "execute_vm_command", "vm_id": vm_id, "command": command }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn named_volumes_outlive_their_vm() {
+    run("cargo", &["run", "--bin", "xtask", "build-guest"]).expect("Failed to build guest");
+    run("cargo", &["run", "--bin", "xtask", "build-vm-agent"]).expect("Failed to build vm-agent");
+    run("cargo", &["build", "--package", "hyperlight-agents-host"]).expect("Failed to build host");
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let config = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        config.path(),
+        json!({ "state_dir": state_dir.path() }).to_string(),
+    )
+    .unwrap();
+
+    let _host = HostGuard(
+        start_host(config.path())
+            .await
+            .expect("Failed to start host"),
+    );
+    let client = connect("vm-volumes-test", TestClientHandler).await;
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "create_volume", "volume": VOLUME, "size_mib": 64 }),
+    )
+    .await;
+    assert!(res.contains("created"), "Volume creation failed: {}", res);
+    let res = vm_builder(
+        &client,
+        json!({ "action": "create_volume", "volume": VOLUME }),
+    )
+    .await;
+    assert!(
+        res.contains("already exists"),
+        "Created the volume twice: {}",
+        res
+    );
+
+    let res = create_vm(&client, "volumes_vm_a").await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+    let res = execute(
+        &client,
+        "volumes_vm_a",
+        "echo kept > /data/marker && echo lost > /scratch/marker && echo written",
+    )
+    .await;
+    assert!(
+        res.contains("written"),
+        "Writing to the volumes failed: {}",
+        res
+    );
+
+    // Attached to one VM at a time, and not deleted from under it
+    let res = vm_builder(&client, json!({ "action": "list_volumes", "vm_id": "" })).await;
+    assert!(
+        res.contains(r#""attached_to":"volumes_vm_a""#),
+        "Volume not listed as attached: {}",
+        res
+    );
+    let res = create_vm(&client, "volumes_vm_b").await;
+    assert!(res.contains("is in use"), "Volume attached twice: {}", res);
+    let res = vm_builder(
+        &client,
+        json!({ "action": "delete_volume", "volume": VOLUME }),
+    )
+    .await;
+    assert!(
+        res.contains("is in use"),
+        "Deleted an attached volume: {}",
+        res
+    );
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "destroy_vm", "vm_id": "volumes_vm_a" }),
+    )
+    .await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+
+    let res = create_vm(&client, "volumes_vm_b").await;
+    assert!(
+        res.contains("created with CID"),
+        "VM creation failed: {}",
+        res
+    );
+    let res = execute(&client, "volumes_vm_b", "cat /data/marker").await;
+    assert!(res.contains("kept"), "Named volume lost its data: {}", res);
+    let res = execute(
+        &client,
+        "volumes_vm_b",
+        "test -e /scratch/marker && echo found || echo empty",
+    )
+    .await;
+    assert!(
+        res.contains("empty"),
+        "Scratch volume kept its data: {}",
+        res
+    );
+
+    let res = vm_builder(
+        &client,
+        json!({ "action": "destroy_vm", "vm_id": "volumes_vm_b" }),
+    )
+    .await;
+    assert!(res.contains("destroyed"), "VM destruction failed: {}", res);
+    let res = vm_builder(
+        &client,
+        json!({ "action": "delete_volume", "volume": VOLUME }),
+    )
+    .await;
+    assert!(res.contains("deleted"), "Volume deletion failed: {}", res);
+    assert!(
+        !state_dir
+            .path()
+            .join("volumes")
+            .join(format!("{}.ext4", VOLUME))
+            .exists(),
+        "Deleted volume is still on disk"
+    );
+}
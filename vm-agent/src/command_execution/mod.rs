@@ -1,20 +1,41 @@
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use std::io::Read;
-use std::process::{Command, Stdio};
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
 
 lazy_static! {
     static ref PROCESS_TABLE: std::sync::Mutex<std::collections::HashMap<u64, (String, std::process::Child)>> =
         std::sync::Mutex::new(std::collections::HashMap::new());
 }
 
-use hyperlight_agents_common::{VmCommand, VmCommandMode};
+use hyperlight_agents_common::{
+    CommandOutput, OutputStream, VmCommand, VmCommandMode, COMMAND_OUTPUT_TAIL_BYTES,
+    DEFAULT_COMMAND_TIMEOUT_SECS,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CommandResponse {
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
+    #[serde(default)]
+    pub stdout_truncated: bool,
+    #[serde(default)]
+    pub stderr_truncated: bool,
+}
+
+impl CommandResponse {
+    /// A command that did not run, failing with `message`.
+    pub fn failed(message: String) -> Self {
+        CommandResponse {
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: message,
+            stdout_truncated: false,
+            stderr_truncated: false,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,121 +60,265 @@ pub struct StopSpawnedProcessRequest {
     pub id: u64,
 }
 
+use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-pub fn execute_command(command: &str, timeout_secs: u64) -> CommandResponse {
-    log::debug!("Executing command: {}", command);
+/// How long output is still collected after a command exits, while processes it left in the
+/// background keep its stdout or stderr open.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
 
-    let mut child = match Command::new("sh")
-        .arg("-c")
-        .arg(command)
+/// How often a running foreground command is checked for having exited or timed out.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs a foreground command and waits for it for its `timeout_seconds`, after which it is killed
+/// together with the processes it started. Its output is passed to `on_output` as it is produced;
+/// the response keeps the last `COMMAND_OUTPUT_TAIL_BYTES` of each stream.
+pub fn execute_command(
+    cmd: &VmCommand,
+    mut on_output: impl FnMut(CommandOutput),
+) -> CommandResponse {
+    log::debug!("Executing command: {}", cmd.command);
+    let timeout_secs = cmd.timeout_seconds.unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS);
+
+    let mut command = build_command(cmd);
+    // Its own process group, so a timeout also kills what the shell started
+    command
+        .process_group(0)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
+        .stderr(Stdio::piped());
+    let mut child = match command.spawn() {
         Ok(child) => child,
         Err(e) => {
-            log::error!("Failed to execute command: {}", e);
-            return CommandResponse {
-                exit_code: -1,
-                stdout: String::new(),
-                stderr: format!("Failed to execute command: {}", e),
-            };
+            log::error!("Failed to execute {}: {}", cmd.command, e);
+            return CommandResponse::failed(format!("Failed to execute {}: {}", cmd.command, e));
         }
     };
+    write_stdin(&mut child, cmd);
+    // Read while the command runs, so it cannot block on a full pipe
+    let (output_sender, output_receiver) = mpsc::channel();
+    read_pipe(
+        child.stdout.take(),
+        OutputStream::Stdout,
+        output_sender.clone(),
+    );
+    read_pipe(child.stderr.take(), OutputStream::Stderr, output_sender);
+    let mut stdout = OutputTail::default();
+    let mut stderr = OutputTail::default();
+    let mut forward = |output: CommandOutput| {
+        match output.stream {
+            OutputStream::Stdout => stdout.push(&output.data),
+            OutputStream::Stderr => stderr.push(&output.data),
+        }
+        on_output(output);
+    };
 
     let start = Instant::now();
-    loop {
+    // Cleared once both pipes close, which the command may do long before it exits
+    let mut pipes_open = true;
+    let exit_code = loop {
         match child.try_wait() {
             Ok(Some(status)) => {
                 // Process exited
-                let mut stdout = String::new();
-                let mut stderr = String::new();
-                if let Some(mut out) = child.stdout.take() {
-                    let _ = out.read_to_string(&mut stdout);
-                }
-                if let Some(mut err) = child.stderr.take() {
-                    let _ = err.read_to_string(&mut stderr);
-                }
                 let exit_code = status.code().unwrap_or(-1);
                 log::debug!("Command completed with exit code {}", exit_code);
-                return CommandResponse {
-                    exit_code,
-                    stdout,
-                    stderr,
-                };
+                break exit_code;
             }
             Ok(None) => {
                 // Still running
                 if start.elapsed() > Duration::from_secs(timeout_secs) {
-                    // Timeout reached, kill the process
-                    let _ = child.kill();
-                    let _ = child.wait();
-
-                    let mut stdout = String::new();
-                    let mut stderr = String::new();
-                    if let Some(mut out) = child.stdout.take() {
-                        let _ = out.read_to_string(&mut stdout);
-                    }
-                    if let Some(mut err) = child.stderr.take() {
-                        let _ = err.read_to_string(&mut stderr);
+                    // Timeout reached, kill the process group
+                    unsafe {
+                        libc::kill(-(child.id() as i32), libc::SIGKILL);
                     }
-
+                    let _ = child.wait();
                     log::error!("Command timed out after {} seconds", timeout_secs);
-                    log::error!("Partial stdout: {}", stdout);
-                    log::error!("Partial stderr: {}", stderr);
-
-                    return CommandResponse {
-                        exit_code: -2,
-                        stdout,
-                        stderr: format!(
-                            "Command timed out after {} seconds\n{}",
-                            timeout_secs, stderr
-                        ),
-                    };
+                    break -2;
+                }
+                // Forwards output while waiting
+                if !pipes_open {
+                    thread::sleep(COMMAND_POLL_INTERVAL);
+                    continue;
+                }
+                match output_receiver.recv_timeout(COMMAND_POLL_INTERVAL) {
+                    Ok(output) => forward(output),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => pipes_open = false,
                 }
-                thread::sleep(Duration::from_millis(100));
             }
             Err(e) => {
                 log::error!("Error waiting for child: {}", e);
-                return CommandResponse {
-                    exit_code: -1,
-                    stdout: String::new(),
-                    stderr: format!("Error waiting for child: {}", e),
-                };
+                return CommandResponse::failed(format!("Error waiting for child: {}", e));
             }
         }
+    };
+    // Until the pipes close, or for a while if processes the command started still hold them.
+    // Their readers stop at the next write once the receiver is gone.
+    let drain_deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+    loop {
+        let left = drain_deadline.saturating_duration_since(Instant::now());
+        match output_receiver.recv_timeout(left) {
+            Ok(output) => forward(output),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                log::debug!(
+                    "Background processes of {} still hold its output, not waiting for it",
+                    cmd.command
+                );
+                break;
+            }
+        }
+    }
+
+    let (stdout, stdout_truncated) = stdout.finish();
+    let (mut stderr, stderr_truncated) = stderr.finish();
+    if exit_code == -2 {
+        log::error!("Partial stdout: {}", stdout);
+        log::error!("Partial stderr: {}", stderr);
+        stderr = format!(
+            "Command timed out after {} seconds\n{}",
+            timeout_secs, stderr
+        );
+    }
+    CommandResponse {
+        exit_code,
+        stdout,
+        stderr,
+        stdout_truncated,
+        stderr_truncated,
     }
 }
 
-/// Spawns a command in the background using VmCommand and returns its ID.
-pub fn spawn_command_struct(cmd: &VmCommand) -> Option<SpawnedProcessInfo> {
-    log::debug!("Spawning command struct: {:?}", cmd);
+/// The end of an output stream, at most `COMMAND_OUTPUT_TAIL_BYTES` long.
+#[derive(Default)]
+struct OutputTail {
+    /// Up to twice the limit until `finish`.
+    text: String,
+    truncated: bool,
+}
 
-    // Build the full command string for shell execution
-    let full_command = if cmd.args.is_empty() {
-        cmd.command.clone()
-    } else {
-        let mut s = cmd.command.clone();
-        for arg in &cmd.args {
-            s.push(' ');
-            s.push_str(arg);
+impl OutputTail {
+    fn push(&mut self, data: &str) {
+        self.text.push_str(data);
+        // Trimmed only once it is twice the limit, so it is not moved on every chunk
+        if self.text.len() > 2 * COMMAND_OUTPUT_TAIL_BYTES {
+            self.trim();
         }
-        s
-    };
+    }
+
+    /// The tail and whether earlier output was dropped.
+    fn finish(mut self) -> (String, bool) {
+        if self.text.len() > COMMAND_OUTPUT_TAIL_BYTES {
+            self.trim();
+        }
+        (self.text, self.truncated)
+    }
 
-    let mut command = Command::new("sh");
-    command.arg("-c").arg(full_command);
+    fn trim(&mut self) {
+        let mut cut = self.text.len() - COMMAND_OUTPUT_TAIL_BYTES;
+        while !self.text.is_char_boundary(cut) {
+            cut += 1;
+        }
+        self.text.drain(..cut);
+        self.truncated = true;
+    }
+}
 
+/// The program with its arguments, or in shell mode `sh -c` running the command line with the
+/// arguments as positional parameters, so they reach it without being split or expanded. Either
+/// way in the command's working directory and environment.
+fn build_command(cmd: &VmCommand) -> Command {
+    let mut command = if cmd.shell {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        if cmd.args.is_empty() {
+            command.arg(&cmd.command);
+        } else {
+            command
+                .arg(format!("{} \"$@\"", cmd.command))
+                .arg("sh")
+                .args(&cmd.args);
+        }
+        command
+    } else {
+        let mut command = Command::new(&cmd.command);
+        command.args(&cmd.args);
+        command
+    };
     if let Some(ref dir) = cmd.working_dir {
         command.current_dir(dir);
     }
+    command.envs(&cmd.env).stdin(if cmd.stdin.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    command
+}
+
+/// Writes the command's `stdin` on its own thread, closing the pipe when done.
+fn write_stdin(child: &mut Child, cmd: &VmCommand) {
+    if let (Some(input), Some(mut pipe)) = (cmd.stdin.clone(), child.stdin.take()) {
+        thread::spawn(move || {
+            if let Err(e) = pipe.write_all(input.as_bytes()) {
+                log::debug!("Command did not read all of its input: {}", e);
+            }
+        });
+    }
+}
+
+/// Sends what a command writes to `pipe` to `sender` as it arrives, until the pipe closes.
+fn read_pipe(
+    pipe: Option<impl Read + Send + 'static>,
+    stream: OutputStream,
+    sender: mpsc::Sender<CommandOutput>,
+) {
+    let Some(mut pipe) = pipe else {
+        return;
+    };
+    thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        // Bytes of a character split across reads
+        let mut pending = Vec::new();
+        loop {
+            let n = match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            pending.extend_from_slice(&buffer[..n]);
+            let complete = match std::str::from_utf8(&pending) {
+                Ok(_) => pending.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => pending.len(),
+            };
+            if complete == 0 {
+                continue;
+            }
+            let rest = pending.split_off(complete);
+            let data = String::from_utf8_lossy(&pending).into_owned();
+            pending = rest;
+            if sender.send(CommandOutput { stream, data }).is_err() {
+                break;
+            }
+        }
+        if !pending.is_empty() {
+            let data = String::from_utf8_lossy(&pending).into_owned();
+            let _ = sender.send(CommandOutput { stream, data });
+        }
+    });
+}
+
+/// Spawns a command in the background using VmCommand and returns its ID.
+pub fn spawn_command_struct(cmd: &VmCommand) -> Option<SpawnedProcessInfo> {
+    log::debug!("Spawning command struct: {:?}", cmd);
+
+    let mut command = build_command(cmd);
     command.stdout(Stdio::null()).stderr(Stdio::null());
 
     // Optionally handle timeout_seconds (not implemented here)
     match command.spawn() {
-        Ok(child) => {
+        Ok(mut child) => {
+            write_stdin(&mut child, cmd);
             let id = next_process_id();
             let mut table = PROCESS_TABLE.lock().unwrap();
             table.insert(id, (cmd.command.clone(), child));
@@ -163,7 +328,7 @@ pub fn spawn_command_struct(cmd: &VmCommand) -> Option<SpawnedProcessInfo> {
             })
         }
         Err(e) => {
-            log::error!("Failed to spawn command: {}", e);
+            log::error!("Failed to spawn {}: {}", cmd.command, e);
             None
         }
     }
@@ -214,6 +379,16 @@ pub fn list_spawned_processes() -> Vec<SpawnedProcessInfo> {
         .collect()
 }
 
+/// Counts the spawned processes that are still running.
+pub fn running_process_count() -> usize {
+    let mut table = PROCESS_TABLE.lock().unwrap();
+    table
+        .values_mut()
+        .map(|(_, child)| child.try_wait())
+        .filter(|status| matches!(status, Ok(None)))
+        .count()
+}
+
 /// Stops a spawned process by ID and returns its output.
 pub fn stop_spawned_process(id: u64) -> Option<StopProcessResponse> {
     log::debug!("Stopping spawned process {}", id);
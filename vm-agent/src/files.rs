@@ -0,0 +1,137 @@
+//! Files the host reads from and writes to the VM, transferred in chunks of one request each.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyperlight_agents_common::{
+    FileCommitRequest, FileInfo, FileReadRequest, FileResponse, FileWriteRequest,
+};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Largest chunk returned by a single read.
+const MAX_READ_CHUNK: u64 = 1024 * 1024;
+
+pub fn stat(path: &str) -> FileResponse {
+    respond(absolute(path).and_then(file_info).map(|info| FileResponse {
+        info: Some(info),
+        ..FileResponse::default()
+    }))
+}
+
+pub fn read(request: &FileReadRequest) -> FileResponse {
+    let result = absolute(&request.path).and_then(|path| {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(request.offset))?;
+        let mut data = Vec::new();
+        file.take(request.length.min(MAX_READ_CHUNK))
+            .read_to_end(&mut data)?;
+        Ok(FileResponse {
+            data: Some(BASE64.encode(&data)),
+            ..FileResponse::default()
+        })
+    });
+    respond(result)
+}
+
+pub fn write(request: &FileWriteRequest) -> FileResponse {
+    let result = partial_path(&request.path, &request.transfer_id).and_then(|partial| {
+        let data = BASE64
+            .decode(&request.data)
+            .map_err(|e| format!("Invalid chunk: {}", e))?;
+        // The first chunk starts the file over, in case an earlier upload left it behind
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(request.offset == 0)
+            .open(&partial)?;
+        file.seek(SeekFrom::Start(request.offset))?;
+        file.write_all(&data)?;
+        Ok(FileResponse::default())
+    });
+    if result.is_err() {
+        if let Ok(partial) = partial_path(&request.path, &request.transfer_id) {
+            let _ = std::fs::remove_file(partial);
+        }
+    }
+    respond(result)
+}
+
+/// Moves a complete upload into place, or removes it without `info`.
+pub fn commit(request: &FileCommitRequest) -> FileResponse {
+    let result = partial_path(&request.path, &request.transfer_id).and_then(|partial| {
+        let Some(expected) = &request.info else {
+            let _ = std::fs::remove_file(&partial);
+            return Ok(FileResponse::default());
+        };
+        let result = file_info(partial.clone()).and_then(|info| {
+            if info.size != expected.size || info.sha256 != expected.sha256 {
+                return Err(format!(
+                    "Checksum mismatch: expected {} bytes with SHA-256 {}, got {} bytes with {}",
+                    expected.size, expected.sha256, info.size, info.sha256
+                )
+                .into());
+            }
+            std::fs::set_permissions(&partial, std::fs::Permissions::from_mode(expected.mode))?;
+            std::fs::rename(&partial, &request.path)?;
+            log::info!("Received {} ({} bytes)", request.path, info.size);
+            Ok(FileResponse {
+                info: Some(FileInfo {
+                    mode: expected.mode,
+                    ..info
+                }),
+                ..FileResponse::default()
+            })
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result
+    });
+    respond(result)
+}
+
+fn respond(result: Result<FileResponse, Box<dyn std::error::Error>>) -> FileResponse {
+    result.unwrap_or_else(|e| FileResponse {
+        error: Some(e.to_string()),
+        ..FileResponse::default()
+    })
+}
+
+fn absolute(path: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !path.starts_with('/') {
+        return Err(format!("Path must be absolute: {}", path).into());
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// `.<name>.<transfer_id>.partial` in the directory of `path`, so the final rename stays on one
+/// file system.
+fn partial_path(path: &str, transfer_id: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = absolute(path)?;
+    if transfer_id.is_empty() || !transfer_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid transfer id: {}", transfer_id).into());
+    }
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Not a file path: {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    Ok(dir.join(format!(".{}.{}.partial", name, transfer_id)))
+}
+
+fn file_info(path: PathBuf) -> Result<FileInfo, Box<dyn std::error::Error>> {
+    let metadata = std::fs::metadata(&path)?;
+    if !metadata.is_file() {
+        return Err(format!("Not a regular file: {}", path.display()).into());
+    }
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(&path)?, &mut hasher)?;
+    Ok(FileInfo {
+        size: metadata.len(),
+        mode: metadata.permissions().mode() & 0o7777,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use std::convert::Infallible;
 use std::sync::Arc;
 use std::io::{Read, Write};
+use crate::transport;
 use crate::{VsockRequest, VsockResponse};
+use hyperlight_agents_common::{VM_AGENT_HTTP_PROXY_ADDR_ENV, VM_AGENT_PROXY_PORT};
 
 use hyper::{Body, Request, Response, Server, StatusCode};
 use hyper::service::{make_service_fn, service_fn};
@@ -43,7 +45,7 @@ impl VsockHttpClient {
         req: HttpProxyRequest,
     ) -> Result<HttpProxyResponse, Box<dyn std::error::Error + Send + Sync>> {
         let result = tokio::task::spawn_blocking(move || {
-            let mut stream = vsock::VsockStream::connect_with_cid_port(vsock::VMADDR_CID_HOST, 1235)?;
+            let mut stream = transport::connect_host(VM_AGENT_PROXY_PORT)?;
 
             let vsock_request = VsockRequest::HttpProxy(req);
             let request_json = serde_json::to_string(&vsock_request)?;
@@ -92,10 +94,10 @@ pub async fn handle_http_request(
     if req.method() == hyper::Method::CONNECT {
         let target = req.uri().to_string();
         log::debug!("CONNECT request to: {}", target);
-        let vsock_port = 1235;
+        let vsock_port = VM_AGENT_PROXY_PORT;
 
         log::debug!("Attempting to establish a vsock connection to the host proxy at CID: {}, Port: {}", vsock::VMADDR_CID_HOST, vsock_port);
-        match vsock::VsockStream::connect_with_cid_port(vsock::VMADDR_CID_HOST, vsock_port) {
+        match transport::connect_host(vsock_port) {
             Ok(mut host_stream) => {
                 let connect_line = format!("CONNECT {} HTTP/1.1\r\n\r\n", target);
                 if let Err(e) = host_stream.write_all(connect_line.as_bytes()) {
@@ -288,11 +290,17 @@ pub async fn start_http_proxy_server() -> Result<(), Box<dyn std::error::Error +
         }
     });
 
-    let addr = ([0, 0, 0, 0], 8080).into();
+    let addr: std::net::SocketAddr = std::env::var(VM_AGENT_HTTP_PROXY_ADDR_ENV)
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or_else(|| ([0, 0, 0, 0], 8080).into());
     let server = Server::bind(&addr).serve(make_svc);
 
-    log::info!("HTTP Proxy Server listening on 0.0.0.0:8080");
-    log::info!("Set http_proxy=http://127.0.0.1:8080 to use this proxy");
+    log::info!("HTTP Proxy Server listening on {}", server.local_addr());
+    log::info!(
+        "Set http_proxy=http://127.0.0.1:{} to use this proxy",
+        server.local_addr().port()
+    );
 
     server.await?;
     Ok(())
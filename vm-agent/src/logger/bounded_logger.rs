@@ -1,7 +1,7 @@
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc;
 use log::{Record, Metadata, Level, LevelFilter, SetLoggerError};
-use vsock::VsockStream;
+use crate::transport::{self, Stream};
 use std::io::Write;
 
 /// The number of log messages to buffer before blocking/dropping.
@@ -15,32 +15,27 @@ pub struct BoundedVsockLogger {
 
 impl BoundedVsockLogger {
     /// Initializes the logger and spawns the background task.
+    ///
+    /// The task connects to the vsock log listener on its own, so the agent can start serving
+    /// before the host listener is reachable; messages are buffered in the channel meanwhile.
     pub async fn init(port: u32) -> Arc<Self> {
         let (tx, mut rx) = mpsc::channel::<String>(LOG_CHANNEL_CAPACITY);
 
-        // Connect to the vsock log listener
-        let vsock_stream = Arc::new(Mutex::new(
-            loop {
-                match VsockStream::connect_with_cid_port(vsock::VMADDR_CID_HOST, port) {
-                    Ok(stream) => break stream,
-                    Err(e) => {
-                        eprintln!(
-                            "Logger: failed to connect to log listener on port {} ({}), retrying in 1s...",
-                            port, e
-                        );
-                        std::thread::sleep(std::time::Duration::from_secs(1));
-                    }
-                }
-            }
-        ));
-
-        // Spawn background task for writing logs
-        let vsock_stream_clone = vsock_stream.clone();
+        // Spawn background task for connecting and writing logs
         tokio::spawn(async move {
+            let mut stream = connect(port).await;
+
             while let Some(msg) = rx.recv().await {
-                let mut stream = vsock_stream_clone.lock().await;
-                let _ = stream.write_all(msg.as_bytes());
-                let _ = stream.flush();
+                // The host end may go away, e.g. when the host restarts, so reconnect and resend
+                if stream
+                    .write_all(msg.as_bytes())
+                    .and_then(|_| stream.flush())
+                    .is_err()
+                {
+                    stream = connect(port).await;
+                    let _ = stream.write_all(msg.as_bytes());
+                    let _ = stream.flush();
+                }
             }
         });
 
@@ -54,6 +49,24 @@ impl BoundedVsockLogger {
     }
 }
 
+/// Connects to the host log listener, retrying until it is reachable.
+async fn connect(port: u32) -> Stream {
+    loop {
+        match transport::connect_host(port) {
+            Ok(stream) => return stream,
+            Err(e) => {
+                let _ = writeln!(
+                    std::io::stderr(),
+                    "Logger: failed to connect to log listener on port {} ({}), retrying in 1s...",
+                    port,
+                    e
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
 /// Combined logger that logs to both console and vsock (via bounded channel).
 pub struct CombinedLogger {
     vsock_logger: Arc<BoundedVsockLogger>,
@@ -74,8 +87,9 @@ impl log::Log for CombinedLogger {
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
             let msg = format!("{} - {}\n", record.level(), record.args());
-            // Log to console
-            print!("{}", msg);
+            // Log to console. A local agent's stdout is a pipe to the host that started it, which
+            // breaks if that host exits, so write errors are ignored.
+            let _ = std::io::stdout().write_all(msg.as_bytes());
 
             // Log to vsock (enqueue, non-blocking, may drop if full)
             self.vsock_logger.enqueue(msg);
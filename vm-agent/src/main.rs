@@ -3,13 +3,21 @@ use std::io::Read;
 use std::io::Write;
 mod logger;
 mod command_execution;
+mod files;
+mod sync;
 use command_execution::{execute_command, CommandResponse};
 mod http_proxy;
+mod transport;
+mod volumes;
 use http_proxy::HttpProxyResponse;
 use http_proxy::start_http_proxy_server;
 use serde::{Serialize, Deserialize};
 use hyperlight_agents_common::VmCommandMode;
 use hyperlight_agents_common::VmCommand;
+use hyperlight_agents_common::{
+    AgentReady, AgentStatus, CommandOutput, FileCommitRequest, FileReadRequest, FileResponse, FileWriteRequest,
+    SyncResponse, SyncUnpackRequest, VM_AGENT_COMMAND_PORT, VM_AGENT_LOG_PORT, VM_AGENT_READY_PORT,
+};
 
 /// VsockRequest enum for proxy requests
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,6 +25,18 @@ use hyperlight_agents_common::VmCommand;
 pub enum VsockRequest {
     Command(VmCommand),
     HttpProxy(http_proxy::HttpProxyRequest),
+    /// Asks whether the agent is serving, e.g. when a restarted host reattaches to the VM.
+    Ping,
+    /// Asks what the agent is busy with, used by the host to decide whether the VM is idle.
+    Status,
+    /// Size, mode and checksum of a file, before the host reads it.
+    FileStat { path: String },
+    FileRead(FileReadRequest),
+    FileWrite(FileWriteRequest),
+    FileCommit(FileCommitRequest),
+    /// Creates the target of a directory sync and lists the files already in it.
+    SyncPrepare { target: String },
+    SyncUnpack(SyncUnpackRequest),
 }
 
 /// VsockResponse enum for proxy responses
@@ -24,13 +44,19 @@ pub enum VsockRequest {
 #[serde(tag = "type")]
 pub enum VsockResponse {
     Command(command_execution::CommandResponse),
+    /// Output of a foreground command while it runs, one JSON line each before its `Command`.
+    CommandOutput(CommandOutput),
     HttpProxy(HttpProxyResponse),
     SpawnedProcess(command_execution::SpawnedProcessInfo),
     SpawnedProcessList(Vec<command_execution::SpawnedProcessInfo>),
     StoppedProcess(command_execution::StopProcessResponse),
+    Pong(AgentReady),
+    Status(AgentStatus),
+    File(FileResponse),
+    Sync(SyncResponse),
 }
 
-fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_connection(mut stream: transport::Stream) -> Result<(), Box<dyn std::error::Error>> {
     log::debug!("=== NEW CONNECTION HANDLER STARTED ===");
 
     // Remove the read timeout to handle non-blocking operations manually
@@ -69,8 +95,6 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
                     n,
                     total_message.len()
                 );
-                log::debug!("Received chunk: '{}'", chunk);
-                log::debug!("Total message so far: '{}'", total_message);
 
                 // Try to parse as complete JSON
                 log::debug!("Attempting to parse JSON...");
@@ -83,8 +107,10 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
                             log::debug!("Received Command: '{:?}'", vm_cmd);
                             match vm_cmd.mode {
                                 VmCommandMode::Foreground => {
-                                    // Foreground: run and wait for result
-                                    let cmd_response = command_execution::execute_command(&vm_cmd.command, 15);
+                                    // Foreground: stream the output, then the result
+                                    let cmd_response = command_execution::execute_command(&vm_cmd, |output| {
+                                        send_output(&mut stream, output)
+                                    });
                                     VsockResponse::Command(cmd_response)
                                 }
                                 VmCommandMode::Spawn => {
@@ -116,11 +142,21 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
                             };
                             VsockResponse::HttpProxy(error_response)
                         }
+                        VsockRequest::Ping => VsockResponse::Pong(agent_info()),
+                        VsockRequest::Status => VsockResponse::Status(AgentStatus {
+                            running_processes: command_execution::running_process_count(),
+                        }),
+                        VsockRequest::FileStat { path } => VsockResponse::File(files::stat(&path)),
+                        VsockRequest::FileRead(request) => VsockResponse::File(files::read(&request)),
+                        VsockRequest::FileWrite(request) => VsockResponse::File(files::write(&request)),
+                        VsockRequest::FileCommit(request) => VsockResponse::File(files::commit(&request)),
+                        VsockRequest::SyncPrepare { target } => VsockResponse::Sync(sync::prepare(&target)),
+                        VsockRequest::SyncUnpack(request) => VsockResponse::Sync(sync::unpack(&request)),
 
                     };
                     let response_json = serde_json::to_string(&response)?;
 
-                        log::debug!("Sending response: {}", response_json);
+                        log::debug!("Sending response of {} bytes", response_json.len());
                         match stream.write_all(response_json.as_bytes()) {
                             Ok(_) => {
                                 log::debug!("Response written to stream");
@@ -152,11 +188,9 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
                 );
                 if start_time.elapsed() > read_timeout {
                     log::error!("TIMEOUT: Read timeout reached, sending error response");
-                    let error_response = CommandResponse {
-                        exit_code: -1,
-                        stdout: String::new(),
-                        stderr: "Read timeout waiting for complete command".to_string(),
-                    };
+                    let error_response = CommandResponse::failed(
+                        "Read timeout waiting for complete command".to_string(),
+                    );
                     let response_json = serde_json::to_string(&error_response)?;
                     let _ = stream.write_all(response_json.as_bytes());
                     let _ = stream.flush();
@@ -169,11 +203,7 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
             Err(e) => {
                 log::debug!("ERROR: Read error - {} (kind: {:?})", e, e.kind());
                 // Send an error response if possible
-                let error_response = CommandResponse {
-                    exit_code: -1,
-                    stdout: String::new(),
-                    stderr: format!("Read error: {}", e),
-                };
+                let error_response = CommandResponse::failed(format!("Read error: {}", e));
                 if let Ok(response_json) = serde_json::to_string(&error_response) {
                     let _ = stream.write_all(response_json.as_bytes());
                     let _ = stream.flush();
@@ -191,11 +221,8 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
                 total_message
             );
             // Try to send error as legacy format first (more likely to work)
-            let error_response = CommandResponse {
-                exit_code: -1,
-                stdout: String::new(),
-                stderr: format!("Invalid JSON request: {}", total_message),
-            };
+            let error_response =
+                CommandResponse::failed(format!("Invalid JSON request: {}", total_message));
             if let Ok(response_json) = serde_json::to_string(&error_response) {
                 let _ = stream.write_all(response_json.as_bytes());
                 let _ = stream.flush();
@@ -207,16 +234,82 @@ fn handle_connection(mut stream: vsock::VsockStream) -> Result<(), Box<dyn std::
     Ok(())
 }
 
+/// Sends output of a running foreground command to the host as one JSON line.
+fn send_output(stream: &mut transport::Stream, output: CommandOutput) {
+    let line = match serde_json::to_string(&VsockResponse::CommandOutput(output)) {
+        Ok(json) => format!("{}\n", json),
+        Err(_) => return,
+    };
+    // The command runs on if the host stopped listening
+    if let Err(e) = stream.write_all(line.as_bytes()) {
+        log::debug!("Failed to send command output: {}", e);
+    }
+}
+
+/// Version and capabilities reported to the host.
+fn agent_info() -> AgentReady {
+    AgentReady {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: vec![
+            "command".to_string(),
+            "command_output".to_string(),
+            "spawn".to_string(),
+            "http_proxy".to_string(),
+            "logs".to_string(),
+            "ping".to_string(),
+            "status".to_string(),
+            "volumes".to_string(),
+            "files".to_string(),
+            "sync".to_string(),
+        ],
+    }
+}
+
+/// Tells the host the agent is accepting commands, retrying briefly in case the connection fails.
+fn announce_ready() {
+    let ready = agent_info();
+    let message = format!("{}\n", serde_json::to_string(&ready).unwrap());
+
+    for attempt in 1..=20 {
+        match transport::connect_host(VM_AGENT_READY_PORT) {
+            Ok(mut stream) => {
+                if stream
+                    .write_all(message.as_bytes())
+                    .and_then(|_| stream.flush())
+                    .is_ok()
+                {
+                    log::info!("Announced readiness to host (version {})", ready.version);
+                    return;
+                }
+            }
+            Err(e) => log::debug!("Readiness announcement attempt {} failed: {}", attempt, e),
+        }
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+    log::error!(
+        "Failed to announce readiness to host on port {}",
+        VM_AGENT_READY_PORT
+    );
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let log_level = std::env::var("RUST_LOG")
         .ok()
         .and_then(|lvl| lvl.parse::<log::LevelFilter>().ok())
         .unwrap_or(log::LevelFilter::Info);
-    let vsock_logger = logger::bounded_logger::BoundedVsockLogger::init(1236).await;
+    let vsock_logger = logger::bounded_logger::BoundedVsockLogger::init(VM_AGENT_LOG_PORT).await;
     logger::bounded_logger::init_combined_logger(vsock_logger.clone(), log_level).expect("Failed to initialize logger");
 
     log::info!("=== VM AGENT STARTING ===");
+
+    // Mounted before the agent announces itself, so commands find their volumes
+    if let Err(e) = volumes::mount_volumes() {
+        // The log only reaches the host once the VM is created, which it will not be
+        eprintln!("vm-agent: {}", e);
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
     log::debug!("Starting VM Agent with VSOCK server on port 1234 and HTTP proxy on port 8080");
 
     // Start HTTP proxy server in background
@@ -230,7 +323,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
 
     // Start VSOCK server in a separate task
-    let vsock_handle = tokio::task::spawn_blocking(|| {
+    let vsock_handle = tokio::task::spawn_blocking(|| -> std::io::Result<()> {
         // Check system information
         log::debug!("Checking system capabilities...");
         if std::path::Path::new("/dev/vsock").exists() {
@@ -277,13 +370,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Attempt to bind VSOCK listener
         log::debug!("Attempting to bind VSOCK listener...");
-        match vsock::VsockListener::bind_with_cid_port(vsock::VMADDR_CID_ANY, 1234) {
+        match transport::bind(VM_AGENT_COMMAND_PORT) {
             Ok(listener) => {
                 log::debug!("✓ VSOCK listener bound successfully on port 1234");
+                // Connections queue on the bound listener, so the host may start sending now
+                std::thread::spawn(announce_ready);
                 log::debug!("Entering connection accept loop...");
 
                 let mut connection_count = 0;
-                for stream in listener.incoming() {
+                loop {
+                    let stream = listener.accept();
                     connection_count += 1;
                     log::debug!(">>> INCOMING CONNECTION #{} <<<", connection_count);
                     match stream {
@@ -292,21 +388,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 "✓ New VSOCK connection accepted (connection #{})",
                                 connection_count
                             );
-                            // Handle each connection in current thread for easier debugging
-                            if let Err(e) = handle_connection(stream) {
-                                log::error!("✗ Error handling connection #{}: {}", connection_count, e);
-                            }
-                            log::debug!(
-                                "Connection #{} handling completed, waiting for next connection...",
-                                connection_count
-                            );
+                            // Each connection on its own thread, so a long-running command
+                            // does not hold up pings and other requests
+                            std::thread::spawn(move || {
+                                if let Err(e) = handle_connection(stream) {
+                                    log::error!("✗ Error handling connection #{}: {}", connection_count, e);
+                                }
+                                log::debug!("Connection #{} handling completed", connection_count);
+                            });
                         }
                         Err(e) => {
                             log::error!("✗ Error accepting connection #{}: {}", connection_count, e);
                         }
                     }
                 }
-                Ok(())
             }
             Err(e) => {
                 log::error!("✗ FAILED to bind VSOCK listener on port 1234: {}", e);
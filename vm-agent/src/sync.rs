@@ -0,0 +1,113 @@
+//! Directories the host syncs into the VM: the files already there, so an incremental sync can
+//! skip them, and unpacking the tar archives the host uploads.
+
+use flate2::read::GzDecoder;
+use hyperlight_agents_common::{SyncResponse, SyncUnpackRequest, SyncedFile};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Creates `target` if needed and lists the regular files in it.
+pub fn prepare(target: &str) -> SyncResponse {
+    let result = absolute(target).and_then(|target| {
+        std::fs::create_dir_all(target)?;
+        let mut files = BTreeMap::new();
+        list_files(target, target, &mut files)?;
+        Ok(SyncResponse {
+            files: Some(files),
+            ..SyncResponse::default()
+        })
+    });
+    respond(result)
+}
+
+pub fn unpack(request: &SyncUnpackRequest) -> SyncResponse {
+    let result = absolute(&request.archive).and_then(|archive_path| {
+        let target = absolute(&request.target)?;
+        let result = unpack_archive(archive_path, target);
+        let _ = std::fs::remove_file(archive_path);
+        let unpacked = result?;
+        log::info!("Unpacked {} files into {}", unpacked, request.target);
+        Ok(SyncResponse {
+            unpacked: Some(unpacked),
+            ..SyncResponse::default()
+        })
+    });
+    respond(result)
+}
+
+fn unpack_archive(archive_path: &Path, target: &Path) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut file = File::open(archive_path)?;
+    let mut magic = [0u8; 2];
+    let compressed = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+    file.seek(SeekFrom::Start(0))?;
+    let reader: Box<dyn Read> = if compressed {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    let mut unpacked = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        // Entries that would land outside the target are skipped
+        if entry.unpack_in(target)? && entry.header().entry_type().is_file() {
+            unpacked += 1;
+        }
+    }
+    Ok(unpacked)
+}
+
+fn list_files(
+    root: &Path,
+    dir: &Path,
+    files: &mut BTreeMap<String, SyncedFile>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(root, &entry.path(), files)?;
+        } else if file_type.is_file() {
+            let path = entry.path();
+            let (Ok(relative), Ok(metadata)) = (path.strip_prefix(root), entry.metadata()) else {
+                continue;
+            };
+            let Some(relative) = relative.to_str() else {
+                continue;
+            };
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_secs());
+            files.insert(
+                relative.to_string(),
+                SyncedFile {
+                    size: metadata.len(),
+                    mtime,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+fn respond(result: Result<SyncResponse, Box<dyn std::error::Error>>) -> SyncResponse {
+    result.unwrap_or_else(|e| SyncResponse {
+        error: Some(e.to_string()),
+        ..SyncResponse::default()
+    })
+}
+
+fn absolute(path: &str) -> Result<&Path, Box<dyn std::error::Error>> {
+    if !path.starts_with('/') {
+        return Err(format!("Path must be absolute: {}", path).into());
+    }
+    Ok(Path::new(path))
+}
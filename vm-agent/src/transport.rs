@@ -0,0 +1,133 @@
+//! Connections to the host: vsock inside a Firecracker VM, or Unix sockets when the host runs the
+//! agent as a local process. The Unix sockets are named and connected like those of Firecracker's
+//! hybrid vsock, so the host talks to both the same way.
+
+use hyperlight_agents_common::VM_AGENT_UNIX_DIR_ENV;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+use vsock::{VsockListener, VsockStream};
+
+/// Socket the host connects to, like Firecracker's vsock socket in the VM directory.
+const VSOCK_SOCKET_FILE: &str = "vsock.sock";
+
+/// Longest the host may take to send the `CONNECT` line of a new connection.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn unix_dir() -> Option<PathBuf> {
+    std::env::var_os(VM_AGENT_UNIX_DIR_ENV).map(PathBuf::from)
+}
+
+pub enum Stream {
+    Vsock(VsockStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Stream::Vsock(stream) => stream.try_clone().map(Stream::Vsock),
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Vsock(stream) => stream.set_read_timeout(timeout),
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Vsock(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Vsock(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Vsock(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Connects to a port on the host.
+pub fn connect_host(port: u32) -> io::Result<Stream> {
+    match unix_dir() {
+        // Where Firecracker forwards guest connections to host port P
+        Some(dir) => UnixStream::connect(dir.join(format!("{}_{}", VSOCK_SOCKET_FILE, port)))
+            .map(Stream::Unix),
+        None => VsockStream::connect_with_cid_port(vsock::VMADDR_CID_HOST, port).map(Stream::Vsock),
+    }
+}
+
+pub enum Listener {
+    Vsock(VsockListener),
+    Unix { listener: UnixListener, port: u32 },
+}
+
+/// Listens for host connections to `port`.
+pub fn bind(port: u32) -> io::Result<Listener> {
+    match unix_dir() {
+        Some(dir) => {
+            let path = dir.join(VSOCK_SOCKET_FILE);
+            let _ = std::fs::remove_file(&path);
+            Ok(Listener::Unix {
+                listener: UnixListener::bind(path)?,
+                port,
+            })
+        }
+        None => VsockListener::bind_with_cid_port(vsock::VMADDR_CID_ANY, port).map(Listener::Vsock),
+    }
+}
+
+impl Listener {
+    /// Waits for the next connection. On a Unix socket the host first sends `CONNECT <port>`,
+    /// which is answered like Firecracker does.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Vsock(listener) => listener.accept().map(|(stream, _)| Stream::Vsock(stream)),
+            Listener::Unix { listener, port } => {
+                let (mut stream, _) = listener.accept()?;
+                stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+                handshake(&mut stream, *port)?;
+                stream.set_read_timeout(None)?;
+                Ok(Stream::Unix(stream))
+            }
+        }
+    }
+}
+
+fn handshake(stream: &mut UnixStream, port: u32) -> io::Result<()> {
+    // Read byte by byte so the request that follows stays in the socket
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while byte[0] != b'\n' {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.push(byte[0]);
+    }
+    let line = String::from_utf8_lossy(&line);
+    if line.trim() != format!("CONNECT {}", port) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected handshake: {}", line.trim()),
+        ));
+    }
+    stream.write_all(format!("OK {}\n", port).as_bytes())
+}
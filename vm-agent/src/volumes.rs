@@ -0,0 +1,47 @@
+//! Mounts the volumes the host attached to the VM, as listed in `VM_AGENT_VOLUMES`.
+
+use hyperlight_agents_common::VM_AGENT_VOLUMES_ENV;
+use std::process::Command;
+
+/// Mounts every volume, stopping at the first that fails. Without the variable there is nothing
+/// to mount.
+pub fn mount_volumes() -> Result<(), String> {
+    let Ok(volumes) = std::env::var(VM_AGENT_VOLUMES_ENV) else {
+        return Ok(());
+    };
+    for entry in volumes.split(',').filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.split(':');
+        let (Some(device), Some(mount_path)) = (parts.next(), parts.next()) else {
+            return Err(format!("Invalid volume entry '{}'", entry));
+        };
+        let read_only = parts.next() == Some("ro");
+
+        std::fs::create_dir_all(mount_path)
+            .map_err(|e| format!("Failed to create mount point {}: {}", mount_path, e))?;
+        let mut mount = Command::new("mount");
+        mount.arg("-t").arg("ext4");
+        if read_only {
+            mount.arg("-o").arg("ro");
+        }
+        let output = mount
+            .arg(format!("/dev/{}", device))
+            .arg(mount_path)
+            .output()
+            .map_err(|e| format!("Failed to run mount: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to mount /dev/{} at {}: {}",
+                device,
+                mount_path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        log::info!(
+            "Mounted volume /dev/{} at {}{}",
+            device,
+            mount_path,
+            if read_only { " (read-only)" } else { "" }
+        );
+    }
+    Ok(())
+}
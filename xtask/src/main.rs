@@ -1,12 +1,15 @@
 use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use flate2::read::GzDecoder;
 use futures_util::StreamExt;
 use log;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tar::Archive;
 use which::which;
@@ -29,6 +32,11 @@ enum Commands {
     BuildVmAgent,
     /// Create a base rootfs image (without agent)
     BuildBaseRootfs,
+    /// Create the rootfs image for a VM profile from firecracker/Dockerfile.<profile>
+    BuildRootfs {
+        /// Profile name, e.g. rust-toolchain or node
+        profile: String,
+    },
     /// Download kernel binary if missing
     DownloadKernel,
     /// Download firecracker binary if missing
@@ -37,6 +45,14 @@ enum Commands {
     RunHost,
     /// Clean all downloaded and built artifacts
     Clean,
+    /// Encrypt a secret into the host secrets file
+    SealSecret {
+        /// Name guests use to reference the secret
+        name: String,
+        /// Secrets file to update
+        #[arg(long, default_value = "secrets.json")]
+        file: PathBuf,
+    },
 }
 
 // Configuration
@@ -90,10 +106,12 @@ async fn main() -> Result<()> {
         Commands::BuildGuest => build_guest(&paths),
         Commands::BuildVmAgent => build_vm_agent(&paths),
         Commands::BuildBaseRootfs => build_base_rootfs(&paths),
+        Commands::BuildRootfs { profile } => build_profile_rootfs(&paths, &profile),
         Commands::DownloadKernel => download_kernel(&paths).await,
         Commands::DownloadFirecracker => download_firecracker(&paths).await,
         Commands::RunHost => run_host(&paths),
         Commands::Clean => clean(&paths),
+        Commands::SealSecret { name, file } => seal_secret(&name, &file),
     }
 }
 
@@ -279,29 +297,50 @@ fn build_vm_agent(paths: &Paths) -> Result<()> {
 }
 
 fn build_base_rootfs(paths: &Paths) -> Result<()> {
+    build_rootfs(paths, "Dockerfile.rootfs", "rootfs")
+}
+
+fn build_profile_rootfs(paths: &Paths, profile: &str) -> Result<()> {
+    if profile.is_empty()
+        || !profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!("Invalid profile name: {}", profile));
+    }
+    build_rootfs(paths, &format!("Dockerfile.{}", profile), profile)
+}
+
+/// Builds `firecracker/<image_name>.squashfs` from a Dockerfile in the firecracker directory.
+fn build_rootfs(paths: &Paths, dockerfile_name: &str, image_name: &str) -> Result<()> {
     log::info!(
-        "{} Building base squashfs rootfs image from Dockerfile...",
-        "🐳".bright_blue()
+        "{} Building {} squashfs rootfs image from {}...",
+        "🐳".bright_blue(),
+        image_name,
+        dockerfile_name
     );
 
-    let squashfs_path = paths.vm_images_dir.join("rootfs.squashfs");
+    let squashfs_path = paths.vm_images_dir.join(format!("{}.squashfs", image_name));
     if squashfs_path.exists() {
         log::info!(
-            "{} Base squashfs rootfs image already exists. Skipping.",
-            "✓".bright_green()
+            "{} {} already exists. Skipping.",
+            "✓".bright_green(),
+            squashfs_path.display()
         );
         return Ok(());
     }
 
-    // 1. Build the Podman image from Dockerfile.rootfs
-    let dockerfile_path = paths.vm_images_dir.join("Dockerfile.rootfs");
+    // 1. Build the Podman image from the Dockerfile
+    let dockerfile_path = paths.vm_images_dir.join(dockerfile_name);
     if !dockerfile_path.exists() {
         return Err(anyhow!(
-            "Dockerfile.rootfs not found in firecracker directory"
+            "{} not found in firecracker directory",
+            dockerfile_name
         ));
     }
 
-    let podman_image_tag = "hyperlight-rootfs:latest";
+    let podman_image_tag = format!("hyperlight-{}:latest", image_name);
+    let podman_image_tag = podman_image_tag.as_str();
     log::info!("Building Podman image from Dockerfile...");
     let build_output = Command::new("podman")
         .args([
@@ -321,7 +360,8 @@ fn build_base_rootfs(paths: &Paths) -> Result<()> {
     }
 
     // 2. Create a container from the image (but don't run it)
-    let container_name = "hyperlight-rootfs-builder";
+    let container_name = format!("hyperlight-{}-builder", image_name);
+    let container_name = container_name.as_str();
     let _ = Command::new("podman").args(["rm", container_name]).output(); // Clean up old container
 
     log::info!("Creating container from image...");
@@ -336,7 +376,9 @@ fn build_base_rootfs(paths: &Paths) -> Result<()> {
     }
 
     // 3. Export the container filesystem to a temporary directory
-    let export_dir = paths.vm_images_dir.join("squashfs_export");
+    let export_dir = paths
+        .vm_images_dir
+        .join(format!("{}_squashfs_export", image_name));
     if export_dir.exists() {
         fs::remove_dir_all(&export_dir)?;
     }
@@ -387,7 +429,7 @@ fn build_base_rootfs(paths: &Paths) -> Result<()> {
     let _ = Command::new("podman").args(["rm", container_name]).output();
 
     log::info!(
-        "{} Base squashfs rootfs image created successfully at {}.",
+        "{} Squashfs rootfs image created successfully at {}.",
         "✓".bright_green(),
         squashfs_path.display()
     );
@@ -549,3 +591,51 @@ fn clean(paths: &Paths) -> Result<()> {
     log::info!("{}", "✓ Cleanup complete".bright_green());
     Ok(())
 }
+
+fn seal_secret(name: &str, file: &Path) -> Result<()> {
+    let key_hex = std::env::var("HYPERLIGHT_SECRETS_KEY")
+        .map_err(|_| anyhow!("HYPERLIGHT_SECRETS_KEY must be set to a hex-encoded 32-byte key"))?;
+    let key = hex::decode(key_hex.trim())?;
+    if key.len() != 32 {
+        return Err(anyhow!(
+            "HYPERLIGHT_SECRETS_KEY must be 32 bytes, got {}",
+            key.len()
+        ));
+    }
+
+    // Read the value from stdin so it does not end up in the shell history
+    log::info!("Reading value for secret '{}' from stdin...", name);
+    let mut value = String::new();
+    std::io::stdin().read_to_string(&mut value)?;
+    let value = value.trim_end_matches(['\r', '\n']);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: value.as_bytes(),
+                aad: name.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow!("Failed to encrypt secret '{}'", name))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+
+    let mut secrets: BTreeMap<String, String> = if file.exists() {
+        serde_json::from_str(&fs::read_to_string(file)?)?
+    } else {
+        BTreeMap::new()
+    };
+    secrets.insert(name.to_string(), hex::encode(sealed));
+    fs::write(file, serde_json::to_string_pretty(&secrets)?)?;
+
+    log::info!(
+        "{} Secret '{}' written to {}",
+        "✓".bright_green(),
+        name,
+        file.display()
+    );
+    Ok(())
+}